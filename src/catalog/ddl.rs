@@ -0,0 +1,143 @@
+// src/catalog/ddl.rs
+use std::sync::Arc;
+use super::{Catalog, OwnWrites};
+use crate::error::{MaemioError, Result};
+use crate::index::{Index, IndexType, KeyExtractor};
+use crate::sequence::Sequence;
+use crate::table::{ForeignKey, OnDelete, Table, TableIndex};
+
+/// A schema change staged by a transaction and applied when it commits
+pub(crate) enum DdlOp {
+    /// Registers a table allocated when the change was staged
+    CreateTable(Arc<Table>),
+    DropTable(Arc<Table>),
+    CreateIndex {
+        table_id: u64,
+        name: String,
+        index_type: IndexType,
+        key: Option<KeyExtractor>,
+        unique: bool,
+    },
+    DropIndex { table_id: u64, name: String },
+    CreateSequence { name: String, start: u64, increment: u64 },
+    DropSequence(String),
+    CreateForeignKey {
+        name: String,
+        child_table_id: u64,
+        column: String,
+        parent_table_id: u64,
+        on_delete: OnDelete,
+    },
+    DropForeignKey { child_table_id: u64, name: String },
+}
+
+/// How to revert one applied `DdlOp`
+pub(crate) enum Undo {
+    UnregisterTable(u64),
+    /// Puts back a dropped table with its indexes and the references its
+    /// foreign keys held on parent tables
+    RestoreTable {
+        table: Arc<Table>,
+        indexes: Vec<(String, IndexType, Arc<dyn Index>)>,
+        references: Vec<(Arc<Table>, Arc<ForeignKey>)>,
+    },
+    DropIndex { table_id: u64, name: String },
+    RestoreIndex {
+        table_id: u64,
+        name: String,
+        index_type: IndexType,
+        index: Arc<dyn Index>,
+        attached: Option<Arc<TableIndex>>,
+    },
+    DropSequence(String),
+    RestoreSequence(Arc<Sequence>),
+    DropForeignKey { child_table_id: u64, name: String },
+    /// Puts back a dropped foreign key together with its index
+    RestoreForeignKey { foreign_key: Arc<ForeignKey>, index: Box<Undo> },
+}
+
+impl DdlOp {
+    pub(crate) fn apply(&self, catalog: &Catalog, own_writes: &OwnWrites) -> Result<Undo> {
+        match self {
+            DdlOp::CreateTable(table) => {
+                catalog.tables.register_table(table.clone())?;
+                Ok(Undo::UnregisterTable(table.id()))
+            }
+            DdlOp::DropTable(table) => {
+                let referenced = table.referenced_by()
+                    .into_iter()
+                    .find(|fk| fk.child_table() != table.id());
+                if let Some(fk) = referenced {
+                    return Err(MaemioError::ConstraintViolation(format!(
+                        "Table {} is referenced by foreign key {}", table.name(), fk.name()
+                    )));
+                }
+                catalog.drop_table(table)
+            }
+            DdlOp::CreateIndex { table_id, name, index_type, key, unique } => {
+                catalog.create_index(*table_id, name, *index_type, key.as_ref(), *unique, own_writes)?;
+                Ok(Undo::DropIndex { table_id: *table_id, name: name.clone() })
+            }
+            DdlOp::DropIndex { table_id, name } => catalog.drop_index(*table_id, name),
+            DdlOp::CreateSequence { name, start, increment } => {
+                catalog.sequences.create_sequence(name, *start, *increment)?;
+                Ok(Undo::DropSequence(name.clone()))
+            }
+            DdlOp::DropSequence(name) => {
+                Ok(Undo::RestoreSequence(catalog.sequences.remove_sequence(name)?))
+            }
+            DdlOp::CreateForeignKey { name, child_table_id, column, parent_table_id, on_delete } => {
+                catalog.create_foreign_key(name, *child_table_id, column, *parent_table_id, *on_delete, own_writes)
+            }
+            DdlOp::DropForeignKey { child_table_id, name } => catalog.drop_foreign_key(*child_table_id, name),
+        }
+    }
+}
+
+impl Undo {
+    pub(crate) fn revert(self, catalog: &Catalog) {
+        // Reverting restores state this commit changed under the DDL lock, so
+        // the steps below cannot collide with other schema changes.
+        match self {
+            Undo::UnregisterTable(table_id) => {
+                let _ = catalog.tables.drop_table(table_id);
+            }
+            Undo::RestoreTable { table, indexes, references } => {
+                for (parent, fk) in references {
+                    parent.add_reference(fk);
+                }
+                for (name, index_type, index) in indexes {
+                    let _ = catalog.indexes.restore_index(table.id(), &name, index_type, index);
+                }
+                let _ = catalog.tables.register_table(table);
+            }
+            Undo::DropIndex { table_id, name } => {
+                let _ = catalog.drop_index(table_id, &name);
+            }
+            Undo::RestoreIndex { table_id, name, index_type, index, attached } => {
+                let _ = catalog.indexes.restore_index(table_id, &name, index_type, index);
+                if let (Some(attached), Ok(table)) = (attached, catalog.tables.get_table(table_id)) {
+                    table.reattach_index(attached);
+                }
+            }
+            Undo::DropSequence(name) => {
+                let _ = catalog.sequences.drop_sequence(&name);
+            }
+            Undo::RestoreSequence(sequence) => {
+                let _ = catalog.sequences.restore_sequence(sequence);
+            }
+            Undo::DropForeignKey { child_table_id, name } => {
+                let _ = catalog.drop_foreign_key(child_table_id, &name);
+            }
+            Undo::RestoreForeignKey { foreign_key, index } => {
+                index.revert(catalog);
+                if let Ok(child) = catalog.tables.get_table(foreign_key.child_table()) {
+                    child.add_foreign_key(foreign_key.clone());
+                }
+                if let Ok(parent) = catalog.tables.get_table(foreign_key.parent_table()) {
+                    parent.add_reference(foreign_key);
+                }
+            }
+        }
+    }
+}
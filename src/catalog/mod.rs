@@ -0,0 +1,465 @@
+// src/catalog/mod.rs
+mod ddl;
+
+pub(crate) use ddl::{DdlOp, Undo};
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use parking_lot::{Mutex, MutexGuard};
+use crate::data::Version;
+use crate::error::{MaemioError, Result};
+use crate::index::{IndexManager, IndexType, KeyExtractor};
+use crate::schema::{Column, ColumnType, Row, Schema, Value};
+use crate::queue::Queue;
+use crate::sequence::SequenceManager;
+use crate::table::{ForeignKey, OnDelete, Table, TableIndex, TableManager};
+use crate::transaction::RecordKey;
+
+/// Writes of the committing transaction, keyed by record; DDL applied at
+/// its commit treats them as already committed
+pub(crate) type OwnWrites = HashMap<RecordKey, Arc<Version>>;
+
+/// Id of the system table describing every table, index and sequence.
+///
+/// It sits at the top of the id space so user table ids are unaffected.
+pub const CATALOG_TABLE_ID: u64 = u64::MAX;
+
+/// Name of the system catalog table
+pub const CATALOG_TABLE_NAME: &str = "__catalog";
+
+/// Kind of object a catalog entry describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectKind {
+    Table,
+    Index,
+    Sequence,
+    ForeignKey,
+    Queue,
+}
+
+impl ObjectKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ObjectKind::Table => "table",
+            ObjectKind::Index => "index",
+            ObjectKind::Sequence => "sequence",
+            ObjectKind::ForeignKey => "foreign_key",
+            ObjectKind::Queue => "queue",
+        }
+    }
+
+    fn parse(kind: &str) -> Result<Self> {
+        match kind {
+            "table" => Ok(ObjectKind::Table),
+            "index" => Ok(ObjectKind::Index),
+            "sequence" => Ok(ObjectKind::Sequence),
+            "foreign_key" => Ok(ObjectKind::ForeignKey),
+            "queue" => Ok(ObjectKind::Queue),
+            _ => Err(MaemioError::System(format!("Unknown catalog object kind {}", kind))),
+        }
+    }
+}
+
+/// One row of the system catalog
+#[derive(Debug, Clone, PartialEq)]
+pub struct CatalogEntry {
+    pub kind: ObjectKind,
+    pub name: String,
+    /// The table itself for tables, the indexed table for indexes, the child
+    /// table for foreign keys, the message table for queues, `None` for
+    /// sequences
+    pub table_id: Option<u64>,
+    /// Human-readable description: the schema, index layout, sequence or
+    /// queue parameters
+    pub definition: String,
+}
+
+impl CatalogEntry {
+    pub(crate) fn table(table: &Table) -> Self {
+        Self {
+            kind: ObjectKind::Table,
+            name: table.name().to_string(),
+            table_id: Some(table.id()),
+            definition: table.schema().map(|schema| schema.to_string()).unwrap_or_default(),
+        }
+    }
+
+    pub(crate) fn index(
+        table_id: u64,
+        name: &str,
+        index_type: IndexType,
+        key: Option<&KeyExtractor>,
+        unique: bool,
+    ) -> Self {
+        let mut definition = format!("{:?}", index_type);
+        if unique {
+            definition.push_str(" UNIQUE");
+        }
+        match key {
+            Some(KeyExtractor::Column(column)) => definition.push_str(&format!(" ON {}", column)),
+            Some(KeyExtractor::Function(_)) => definition.push_str(" ON <function>"),
+            #[cfg(feature = "json")]
+            Some(KeyExtractor::JsonPath(path)) => definition.push_str(&format!(" ON {}", path)),
+            None => {}
+        }
+        Self {
+            kind: ObjectKind::Index,
+            name: name.to_string(),
+            table_id: Some(table_id),
+            definition,
+        }
+    }
+
+    pub(crate) fn sequence(name: &str, start: u64, increment: u64) -> Self {
+        Self {
+            kind: ObjectKind::Sequence,
+            name: name.to_string(),
+            table_id: None,
+            definition: format!("START {} INCREMENT {}", start, increment),
+        }
+    }
+
+    pub(crate) fn foreign_key(
+        name: &str,
+        child_table_id: u64,
+        column: &str,
+        parent_table_id: u64,
+        on_delete: OnDelete,
+    ) -> Self {
+        Self {
+            kind: ObjectKind::ForeignKey,
+            name: name.to_string(),
+            table_id: Some(child_table_id),
+            definition: format!("{} REFERENCES {} ON DELETE {:?}", column, parent_table_id, on_delete),
+        }
+    }
+
+    pub(crate) fn queue(queue: &Queue) -> Self {
+        Self {
+            kind: ObjectKind::Queue,
+            name: queue.name().to_string(),
+            table_id: Some(queue.table_id()),
+            definition: format!("VISIBILITY TIMEOUT {} MS", queue.visibility_timeout().as_millis()),
+        }
+    }
+
+    pub(crate) fn to_row(&self) -> Row {
+        Row::new(vec![
+            Value::String(self.kind.as_str().to_string()),
+            Value::String(self.name.clone()),
+            self.table_id.map_or(Value::Null, |id| Value::Int(id as i64)),
+            Value::String(self.definition.clone()),
+        ])
+    }
+
+    pub(crate) fn from_row(row: Row) -> Result<Self> {
+        match row.into_values().as_slice() {
+            [Value::String(kind), Value::String(name), table_id, Value::String(definition)] => Ok(Self {
+                kind: ObjectKind::parse(kind)?,
+                name: name.clone(),
+                table_id: match table_id {
+                    Value::Int(id) => Some(*id as u64),
+                    _ => None,
+                },
+                definition: definition.clone(),
+            }),
+            _ => Err(MaemioError::System("Malformed catalog row".into())),
+        }
+    }
+}
+
+/// Layout of the rows in the catalog table
+pub(crate) fn catalog_schema() -> Schema {
+    Schema::new(vec![
+        Column::new("kind", ColumnType::String),
+        Column::new("name", ColumnType::String),
+        Column::nullable("table_id", ColumnType::Int),
+        Column::new("definition", ColumnType::String),
+    ]).expect("catalog schema is valid")
+}
+
+/// Owns the structures that DDL changes and applies staged DDL at commit.
+///
+/// Committing transactions that carry DDL take the catalog's DDL lock for
+/// their whole commit, so schema changes are serialized with each other.
+/// Staged changes are applied only once the transaction's writes have
+/// passed every check, right before they are installed, so other
+/// transactions never see objects of a transaction that fails.
+pub struct Catalog {
+    tables: Arc<TableManager>,
+    indexes: Arc<IndexManager>,
+    sequences: Arc<SequenceManager>,
+    ddl_lock: Mutex<()>,
+}
+
+impl Catalog {
+    pub fn new(
+        tables: Arc<TableManager>,
+        indexes: Arc<IndexManager>,
+        sequences: Arc<SequenceManager>,
+    ) -> Self {
+        Self {
+            tables,
+            indexes,
+            sequences,
+            ddl_lock: Mutex::new(()),
+        }
+    }
+
+    pub fn tables(&self) -> &Arc<TableManager> {
+        &self.tables
+    }
+
+    pub fn indexes(&self) -> &Arc<IndexManager> {
+        &self.indexes
+    }
+
+    pub fn sequences(&self) -> &Arc<SequenceManager> {
+        &self.sequences
+    }
+
+    pub(crate) fn lock_ddl(&self) -> MutexGuard<'_, ()> {
+        self.ddl_lock.lock()
+    }
+
+    /// Creates an index and, when it has a key, attaches it to its table and
+    /// indexes the table's existing records, including those the committing
+    /// transaction writes. Nothing is left behind on failure.
+    ///
+    /// Indexes without a key are filled in by hand, so their table id only
+    /// names the index's namespace and the table need not exist.
+    pub(crate) fn create_index(
+        &self,
+        table_id: u64,
+        name: &str,
+        index_type: IndexType,
+        key: Option<&KeyExtractor>,
+        unique: bool,
+        own_writes: &OwnWrites,
+    ) -> Result<Option<Arc<TableIndex>>> {
+        let bound = match key {
+            Some(key) => {
+                let table = self.tables.get_table(table_id)?;
+                let bound = key.bind(table.schema())?;
+                Some((table, bound))
+            }
+            None => None,
+        };
+        if unique {
+            self.indexes.create_unique_index(table_id, name, index_type)?;
+        } else {
+            self.indexes.create_index(table_id, name, index_type)?;
+        }
+        let Some((table, key)) = bound else {
+            return Ok(None);
+        };
+
+        let attached = table.attach_index(TableIndex::new(name, self.indexes.get_index(table_id, name)?, key));
+        // Commits that worked out their index entries before the index was
+        // attached finish installing before the backfill reads the table.
+        // The committing transaction holds a pass of its own if it writes
+        // to the table.
+        let own = own_writes.keys().any(|&(written, _)| written == table_id);
+        table.drain_commits(usize::from(own));
+        if let Err(err) = Self::backfill_index(&table, &attached, own_writes) {
+            table.detach_index(name);
+            let _ = self.indexes.drop_index(table_id, name);
+            return Err(err);
+        }
+        Ok(Some(attached))
+    }
+
+    /// Latest versions of a table's live records, with the committing
+    /// transaction's writes in place of what they replace
+    fn latest_versions(table: &Table, own_writes: &OwnWrites) -> Result<Vec<(u64, Arc<Version>)>> {
+        let mut committed = Vec::new();
+        for (record_id, record) in table.records().entries() {
+            if own_writes.contains_key(&(table.id(), record_id)) {
+                continue;
+            }
+            if let Some(version) = record.find_visible_version(u64::MAX)? {
+                committed.push((record_id, version));
+            }
+        }
+        let written = own_writes
+            .iter()
+            .filter(|((table_id, _), _)| *table_id == table.id())
+            .map(|(&(_, record_id), version)| (record_id, version.clone()));
+        Ok(committed
+            .into_iter()
+            .chain(written)
+            .filter(|(_, version)| !version.is_tombstone())
+            .collect())
+    }
+
+    /// Indexes the latest versions of a table's existing records
+    fn backfill_index(table: &Table, attached: &TableIndex, own_writes: &OwnWrites) -> Result<()> {
+        // Commits racing with this loop saw the index when they computed
+        // their entries and maintain it themselves, so skip entries they added.
+        let _unique_guard = attached.commit_lock.lock();
+        for (record_id, version) in Self::latest_versions(table, own_writes)? {
+            if let Some(index_key) = attached.key.extract(&version.data)? {
+                let index = &attached.index;
+                if !index.get_all(&index_key, u64::MAX)?.contains(&record_id) {
+                    index.insert(index_key, record_id, version.wts)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes an index from the index manager and its table
+    pub(crate) fn drop_index(&self, table_id: u64, name: &str) -> Result<Undo> {
+        let (index_type, index) = self.indexes.take_index(table_id, name)?;
+        let attached = self.tables
+            .get_table(table_id)
+            .ok()
+            .and_then(|table| table.detach_index(name));
+        Ok(Undo::RestoreIndex { table_id, name: name.to_string(), index_type, index, attached })
+    }
+
+    /// Declares a foreign key from `column` of the child table to the parent
+    /// table's record ids, indexing the column. Fails if existing rows,
+    /// including the committing transaction's, already break the reference.
+    pub(crate) fn create_foreign_key(
+        &self,
+        name: &str,
+        child_table_id: u64,
+        column: &str,
+        parent_table_id: u64,
+        on_delete: OnDelete,
+        own_writes: &OwnWrites,
+    ) -> Result<Undo> {
+        let child = self.tables.get_table(child_table_id)?;
+        let parent = self.tables.get_table(parent_table_id)?;
+        let index_name = ForeignKey::index_name(name);
+        let index = self
+            .create_index(child_table_id, &index_name, IndexType::BTree, Some(&KeyExtractor::column(column)), false, own_writes)?
+            .expect("keyed indexes are attached to their table");
+        let foreign_key = Arc::new(ForeignKey::new(name, child_table_id, parent_table_id, on_delete, index));
+
+        // Register under the index's lock so no commit slips in between the
+        // check of existing rows and the constraint taking effect.
+        let guard = foreign_key.index.commit_lock.lock();
+        for (_, version) in Self::latest_versions(&child, own_writes)? {
+            let Some(parent_id) = foreign_key.parent_of(&version.data)? else { continue };
+            let parent_exists = match own_writes.get(&(parent_table_id, parent_id)) {
+                Some(parent_version) => !parent_version.is_tombstone(),
+                None => parent.record_exists(parent_id)?,
+            };
+            if !parent_exists {
+                drop(guard);
+                let _ = self.drop_index(child_table_id, &index_name);
+                return Err(MaemioError::ConstraintViolation(format!(
+                    "Foreign key {} references missing record {}", name, parent_id
+                )));
+            }
+        }
+        child.add_foreign_key(foreign_key.clone());
+        parent.add_reference(foreign_key.clone());
+        drop(guard);
+        Ok(Undo::DropForeignKey { child_table_id, name: name.to_string() })
+    }
+
+    /// Removes a foreign key and the index on its child column
+    pub(crate) fn drop_foreign_key(&self, child_table_id: u64, name: &str) -> Result<Undo> {
+        let child = self.tables.get_table(child_table_id)?;
+        let foreign_key = child.remove_foreign_key(name)
+            .ok_or_else(|| MaemioError::System(format!(
+                "Foreign key {} not found for table {}", name, child_table_id
+            )))?;
+        let index = match self.drop_index(child_table_id, &foreign_key.index.name) {
+            Ok(index) => index,
+            Err(err) => {
+                child.add_foreign_key(foreign_key);
+                return Err(err);
+            }
+        };
+        if let Ok(parent) = self.tables.get_table(foreign_key.parent_table()) {
+            parent.remove_reference(&foreign_key);
+        }
+        Ok(Undo::RestoreForeignKey { foreign_key, index: Box::new(index) })
+    }
+
+    /// Removes a table together with its indexes and the references its
+    /// foreign keys registered on parent tables, in one undoable step
+    pub(crate) fn drop_table(&self, table: &Arc<Table>) -> Result<Undo> {
+        let table = self.tables.drop_table(table.id())?;
+        let indexes = self.indexes.take_table_indexes(table.id());
+        let mut references = Vec::new();
+        for fk in table.foreign_keys() {
+            if let Ok(parent) = self.tables.get_table(fk.parent_table()) {
+                parent.remove_reference(&fk);
+                references.push((parent, fk));
+            }
+        }
+        Ok(Undo::RestoreTable { table, indexes, references })
+    }
+
+    /// Applies staged DDL in order, undoing the applied prefix if one step
+    /// fails. `own_writes` are the committing transaction's writes, which
+    /// new indexes and foreign keys take into account.
+    pub(crate) fn apply(&self, ops: &[DdlOp], own_writes: &OwnWrites) -> Result<Vec<Undo>> {
+        let mut undo = Vec::with_capacity(ops.len());
+        for op in ops {
+            match op.apply(self, own_writes) {
+                Ok(step) => undo.push(step),
+                Err(err) => {
+                    self.undo(undo);
+                    return Err(err);
+                }
+            }
+        }
+        Ok(undo)
+    }
+
+    /// Reverts applied DDL, newest first
+    pub(crate) fn undo(&self, undo: Vec<Undo>) {
+        for step in undo.into_iter().rev() {
+            step.revert(self);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_round_trip() {
+        let schema = catalog_schema();
+        let entries = [
+            CatalogEntry::index(3, "by_email", IndexType::Hash, Some(&KeyExtractor::column("email")), true),
+            CatalogEntry::sequence("ids", 1, 2),
+        ];
+        assert_eq!(entries[0].definition, "Hash UNIQUE ON email");
+        for entry in entries {
+            let data = schema.encode_row(&entry.to_row()).unwrap();
+            assert_eq!(CatalogEntry::from_row(schema.decode_row(&data).unwrap()).unwrap(), entry);
+        }
+    }
+
+    #[test]
+    fn test_drop_table_undo() {
+        let catalog = Catalog::new(
+            Arc::new(TableManager::new(4)),
+            Arc::new(IndexManager::new()),
+            Arc::new(SequenceManager::new()),
+        );
+        let table = catalog.tables().create_table("users").unwrap();
+        let key = KeyExtractor::function(|data| Some(crate::index::IndexKey::Bytes(data.to_vec())));
+        let no_writes = OwnWrites::new();
+        catalog.create_index(table.id(), "by_data", IndexType::Hash, Some(&key), false, &no_writes).unwrap();
+        catalog.create_index(table.id(), "manual", IndexType::BTree, None, false, &no_writes).unwrap();
+
+        // Table and indexes go in the same step and come back together
+        let undo = catalog.apply(&[DdlOp::DropTable(table.clone())], &no_writes).unwrap();
+        assert!(catalog.tables().get_table(table.id()).is_err());
+        assert!(catalog.indexes().table_indexes(table.id()).is_empty());
+
+        catalog.undo(undo);
+        assert!(catalog.tables().get_table_by_name("users").is_ok());
+        assert_eq!(catalog.indexes().table_indexes(table.id()), vec!["by_data", "manual"]);
+        assert_eq!(table.indexes().len(), 1);
+    }
+}
@@ -13,9 +13,11 @@ pub struct ClockManager {
 }
 
 impl ClockManager {
+    /// Creates a clock per worker thread, plus one for background tasks at
+    /// index `thread_count`
     pub fn new(thread_count: usize, sync_interval_micros: u64) -> Result<Self> {
-        let mut clocks = Vec::with_capacity(thread_count);
-        for id in 0..thread_count {
+        let mut clocks = Vec::with_capacity(thread_count + 1);
+        for id in 0..=thread_count {
             clocks.push(Arc::new(Clock::new(id as u8)?));
         }
 
@@ -31,6 +33,11 @@ impl ClockManager {
         self.clocks[thread_id].clone()
     }
 
+    /// Thread id of the clock background tasks run their transactions on
+    pub fn system_thread_id(&self) -> usize {
+        self.clocks.len() - 1
+    }
+
     pub fn start_synchronization(&self) -> thread::JoinHandle<()> {
         let clocks = self.clocks.clone();
         let sync_interval = self.sync_interval;
@@ -53,8 +60,14 @@ impl ClockManager {
             .min()
             .unwrap_or(0);
             
+        // Idle clocks would otherwise hold the minimum at their last
+        // transaction, which for an unused clock is the beginning of time
+        let latest = self.clocks.iter()
+            .map(|c| c.last_timestamp.load(Ordering::Relaxed))
+            .max()
+            .unwrap_or(0);
         let min_rts = self.clocks.iter()
-            .map(|c| c.read_timestamp.load(Ordering::Relaxed))
+            .map(|c| c.oldest_readable(latest))
             .min()
             .unwrap_or(0);
             
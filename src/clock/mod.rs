@@ -1,8 +1,12 @@
 mod manager;
 pub use manager::ClockManager;
 
+use std::collections::BTreeSet;
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use parking_lot::Mutex;
 use std::time::{Duration, Instant};
+use crate::data::PayloadArena;
 use crate::error::{MaemioError, Result};
 
 pub struct Clock {
@@ -11,11 +15,15 @@ pub struct Clock {
     thread_id: u8,
     last_timestamp: AtomicU64,
     read_timestamp: AtomicU64,
+    // Timestamps of this thread's transactions that have not finished yet
+    active: Mutex<BTreeSet<u64>>,
+    // Payload buffers of the versions this thread writes
+    arena: Arc<PayloadArena>,
 }
 
 impl Clock {
     pub fn new(thread_id: u8) -> Result<Self> {
-        if thread_id >= 255 {
+        if thread_id == u8::MAX {
             return Err(MaemioError::System("Thread ID must be less than 255".into()));
         }
 
@@ -25,17 +33,25 @@ impl Clock {
             thread_id,
             last_timestamp: AtomicU64::new(0),
             read_timestamp: AtomicU64::new(0),
+            active: Mutex::new(BTreeSet::new()),
+            arena: Arc::new(PayloadArena::new()),
         })
     }
 
+    /// The arena this thread's transactions allocate version payloads from
+    pub fn arena(&self) -> &Arc<PayloadArena> {
+        &self.arena
+    }
+
     pub fn generate_write_timestamp(&self) -> u64 {
         let now = Instant::now().elapsed().as_micros() as u64;
         self.local_clock.fetch_add(now, Ordering::Relaxed);
         
         let current_clock = self.local_clock.load(Ordering::Relaxed);
         let boosted_clock = current_clock + self.clock_boost.load(Ordering::Relaxed);
-        let last_ts = self.last_timestamp.load(Ordering::Relaxed);
-        let new_clock = std::cmp::max(boosted_clock, last_ts + 1);
+        // Timestamps carry the thread id in their low byte; compare clock parts only
+        let last_clock = self.last_timestamp.load(Ordering::Relaxed) >> 8;
+        let new_clock = std::cmp::max(boosted_clock, last_clock + 1);
         
         let timestamp = (new_clock << 8) | (self.thread_id as u64);
         self.last_timestamp.store(timestamp, Ordering::Relaxed);
@@ -49,6 +65,44 @@ impl Clock {
         read_ts
     }
 
+    /// Hands out the timestamp of a new transaction and holds this thread's
+    /// read timestamp below it until `end_transaction`
+    pub fn begin_transaction(&self) -> u64 {
+        let mut active = self.active.lock();
+        let timestamp = self.generate_write_timestamp();
+        active.insert(timestamp);
+        self.update_read_timestamp(&active);
+        timestamp
+    }
+
+    /// Lets the read timestamp move past a finished transaction
+    pub fn end_transaction(&self, timestamp: u64) {
+        let mut active = self.active.lock();
+        active.remove(&timestamp);
+        self.update_read_timestamp(&active);
+    }
+
+    // The oldest active transaction bounds what this thread may still read.
+    // Without one, transactions started later get timestamps past the last.
+    fn update_read_timestamp(&self, active: &BTreeSet<u64>) {
+        let read_ts = match active.first() {
+            Some(&oldest) => oldest.saturating_sub(1),
+            None => self.last_timestamp.load(Ordering::Relaxed),
+        };
+        self.read_timestamp.store(read_ts, Ordering::Release);
+    }
+
+    /// Oldest timestamp this thread may still read. An idle clock is first
+    /// moved past `bound`, so the transactions it starts later stay above it.
+    pub(crate) fn oldest_readable(&self, bound: u64) -> u64 {
+        let active = self.active.lock();
+        if active.is_empty() {
+            self.last_timestamp.fetch_max(bound, Ordering::Relaxed);
+        }
+        self.update_read_timestamp(&active);
+        self.read_timestamp.load(Ordering::Relaxed)
+    }
+
     pub fn synchronize_with(&self, other: &Clock) {
         let remote_clock = other.local_clock.load(Ordering::Relaxed);
         let local_clock = self.local_clock.load(Ordering::Relaxed);
@@ -80,6 +134,18 @@ mod tests {
         assert_eq!(ts1 & 0xFF, 1, "Thread ID should be preserved in timestamp");
     }
 
+    #[test]
+    fn test_timestamps_keep_increasing() {
+        let clock = Clock::new(3).unwrap();
+        let mut last = clock.generate_write_timestamp();
+        for _ in 0..1000 {
+            let ts = clock.generate_write_timestamp();
+            assert!(ts > last, "Timestamps should keep increasing");
+            assert_eq!(ts & 0xFF, 3);
+            last = ts;
+        }
+    }
+
     #[test]
     fn test_clock_synchronization() {
         let clock1 = Clock::new(1).unwrap();
@@ -90,4 +156,33 @@ mod tests {
         
         assert!(clock1.local_clock.load(Ordering::Relaxed) >= 1000);
     }
+
+    #[test]
+    fn test_read_timestamp_follows_oldest_transaction() {
+        let clock = Clock::new(0).unwrap();
+        let first = clock.begin_transaction();
+        let second = clock.begin_transaction();
+        assert_eq!(clock.read_timestamp.load(Ordering::Relaxed), first - 1);
+
+        // Ending a newer transaction does not move past an older one
+        clock.end_transaction(second);
+        assert_eq!(clock.read_timestamp.load(Ordering::Relaxed), first - 1);
+        clock.end_transaction(first);
+        assert_eq!(clock.read_timestamp.load(Ordering::Relaxed), second);
+        assert!(clock.begin_transaction() > second);
+    }
+
+    #[test]
+    fn test_idle_clock_moves_past_bound() {
+        let clock = Clock::new(2).unwrap();
+        let bound = 1 << 20;
+        assert_eq!(clock.oldest_readable(bound), bound);
+        let active = clock.begin_transaction();
+        assert!(active > bound);
+
+        // An active transaction holds the floor below it
+        assert_eq!(clock.oldest_readable(active + 1000), active - 1);
+        clock.end_transaction(active);
+        assert_eq!(clock.oldest_readable(active + 1000), active + 1000);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,68 @@
+// src/codec.rs
+//
+// Serde codecs for record values. A codec turns any serializable value into
+// the bytes a record stores and back; transactions use them for typed reads
+// and writes so callers never handle the bytes themselves.
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use crate::error::{MaemioError, Result};
+
+/// Serializes record values to bytes and back
+pub trait Codec {
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>>;
+
+    /// Fails with `MaemioError::Decode` when `data` is not a valid `T`
+    fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T>;
+}
+
+/// Compact binary encoding (postcard); the default for typed values
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Binary;
+
+impl Codec for Binary {
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+        postcard::to_allocvec(value).map_err(|err| MaemioError::System(format!("Encode failed: {}", err)))
+    }
+
+    fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
+        postcard::from_bytes(data).map_err(|err| MaemioError::Decode(err.to_string()))
+    }
+}
+
+/// JSON text, readable by other tools
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+impl Codec for Json {
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+        serde_json::to_vec(value).map_err(|err| MaemioError::System(format!("Encode failed: {}", err)))
+    }
+
+    fn decode<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
+        serde_json::from_slice(data).map_err(|err| MaemioError::Decode(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Point {
+        x: i32,
+        label: String,
+    }
+
+    #[test]
+    fn test_codecs_round_trip() {
+        let point = Point { x: -4, label: "origin".into() };
+        assert_eq!(Binary::decode::<Point>(&Binary::encode(&point).unwrap()).unwrap(), point);
+        let json = Json::encode(&point).unwrap();
+        assert_eq!(json, br#"{"x":-4,"label":"origin"}"#);
+        assert_eq!(Json::decode::<Point>(&json).unwrap(), point);
+
+        assert!(matches!(Json::decode::<Point>(b"{\"x\":1}"), Err(MaemioError::Decode(_))));
+        assert!(matches!(Binary::decode::<Point>(&[1, 2]), Err(MaemioError::Decode(_))));
+    }
+}
@@ -0,0 +1,120 @@
+// src/data/arena.rs
+//
+// Per-thread pools of payload buffers. Each worker clock owns an arena that
+// its transactions allocate version payloads from; versions remember their
+// arena and hand their buffer back when they are dropped, which happens once
+// the garbage collector has unlinked them and no reader holds them anymore.
+// Only the payload bytes are pooled; the `Version` and its chain node still
+// come from the global allocator.
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use parking_lot::Mutex;
+
+/// Buffer capacities the arena pools, from 64 bytes to 64 KiB
+const SIZE_CLASSES: [usize; 6] = [64, 256, 1024, 4096, 16 * 1024, 64 * 1024];
+
+/// Most bytes an arena keeps cached; further returned buffers are freed
+const MAX_CACHED_BYTES: usize = 8 * 1024 * 1024;
+
+/// Allocation counters of one arena
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ArenaStats {
+    /// Allocations served from a cached buffer
+    pub hits: u64,
+    /// Allocations that went to the global allocator
+    pub misses: u64,
+    /// Buffers returned to the arena and kept for reuse
+    pub recycled: u64,
+    /// Bytes currently cached
+    pub cached_bytes: usize,
+}
+
+/// Slab pool of payload buffers owned by one worker thread
+#[derive(Debug, Default)]
+pub struct PayloadArena {
+    classes: [Mutex<Vec<Vec<u8>>>; SIZE_CLASSES.len()],
+    cached_bytes: AtomicUsize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    recycled: AtomicU64,
+}
+
+impl PayloadArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns an empty buffer with room for at least `len` bytes
+    pub fn alloc(&self, len: usize) -> Vec<u8> {
+        let Some(class) = SIZE_CLASSES.iter().position(|&size| size >= len) else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return Vec::with_capacity(len);
+        };
+        if let Some(buffer) = self.classes[class].lock().pop() {
+            self.cached_bytes.fetch_sub(SIZE_CLASSES[class], Ordering::Relaxed);
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return buffer;
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        Vec::with_capacity(SIZE_CLASSES[class])
+    }
+
+    /// Copies `data` into a buffer from the arena
+    pub fn alloc_copy(&self, data: &[u8]) -> Vec<u8> {
+        let mut buffer = self.alloc(data.len());
+        buffer.extend_from_slice(data);
+        buffer
+    }
+
+    /// Keeps a buffer for reuse, filed under the largest class it can serve.
+    /// Buffers outside the pooled sizes, or beyond the cache limit, are freed.
+    pub(crate) fn recycle(&self, mut buffer: Vec<u8>) {
+        let Some(class) = SIZE_CLASSES.iter().rposition(|&size| size <= buffer.capacity()) else {
+            return;
+        };
+        if buffer.capacity() > 2 * SIZE_CLASSES[SIZE_CLASSES.len() - 1] {
+            return;
+        }
+        let size = SIZE_CLASSES[class];
+        if self.cached_bytes.fetch_add(size, Ordering::Relaxed) + size > MAX_CACHED_BYTES {
+            self.cached_bytes.fetch_sub(size, Ordering::Relaxed);
+            return;
+        }
+        buffer.clear();
+        self.classes[class].lock().push(buffer);
+        self.recycled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> ArenaStats {
+        ArenaStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            recycled: self.recycled.load(Ordering::Relaxed),
+            cached_bytes: self.cached_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffers_are_reused_by_size_class() {
+        let arena = PayloadArena::new();
+        let buffer = arena.alloc_copy(&[1; 100]);
+        assert!(buffer.capacity() >= 256);
+        arena.recycle(buffer);
+        assert_eq!(arena.stats().cached_bytes, 256);
+
+        // Smaller requests do not take a buffer from a larger class
+        assert_eq!(arena.alloc(10).capacity(), 64);
+        let reused = arena.alloc(200);
+        assert!(reused.is_empty() && reused.capacity() >= 256);
+        assert_eq!(arena.stats(), ArenaStats { hits: 1, misses: 2, recycled: 1, cached_bytes: 0 });
+
+        // Tiny and huge buffers are not pooled
+        arena.recycle(Vec::with_capacity(8));
+        arena.recycle(Vec::with_capacity(1 << 20));
+        assert_eq!(arena.stats().recycled, 1);
+    }
+}
@@ -0,0 +1,122 @@
+// src/data/compression.rs
+//
+// Per-table value compression. Values are compressed when their version is
+// installed and decompressed when a read finds them, so transactions only
+// ever see the original bytes. Values that fit the inline buffer are never
+// compressed, keeping the inline read path a plain copy.
+use std::sync::atomic::{AtomicU64, Ordering};
+use super::MAX_INLINE_SIZE;
+use crate::error::{MaemioError, Result};
+
+/// Codec applied to a table's values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    /// LZ4 block compression
+    #[cfg(feature = "compression")]
+    Lz4,
+}
+
+impl Compression {
+    /// Compresses a value, or returns `None` when it is stored as is because
+    /// it is small enough to be inlined or does not shrink
+    pub(crate) fn compress(self, data: &[u8]) -> Option<Vec<u8>> {
+        if data.len() <= MAX_INLINE_SIZE {
+            return None;
+        }
+        match self {
+            Compression::None => None,
+            #[cfg(feature = "compression")]
+            Compression::Lz4 => {
+                let compressed = lz4_flex::compress_prepend_size(data);
+                (compressed.len() < data.len()).then_some(compressed)
+            }
+        }
+    }
+}
+
+/// Restores a value compressed by `Compression::compress`
+pub(crate) fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    #[cfg(feature = "compression")]
+    {
+        lz4_flex::decompress_size_prepended(data)
+            .map_err(|err| MaemioError::System(format!("Malformed compressed value: {}", err)))
+    }
+    #[cfg(not(feature = "compression"))]
+    {
+        let _ = data;
+        Err(MaemioError::System("Compression support is not enabled".into()))
+    }
+}
+
+/// How well a table's values compressed so far
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CompressionStats {
+    /// Values large enough to be considered for compression
+    pub values: u64,
+    /// Values stored compressed because they shrank
+    pub compressed_values: u64,
+    /// Size of the considered values before compression
+    pub raw_bytes: u64,
+    /// Size of the considered values as stored
+    pub stored_bytes: u64,
+}
+
+impl CompressionStats {
+    /// Original size divided by stored size; 1.0 before any value was considered
+    pub fn ratio(&self) -> f64 {
+        if self.stored_bytes == 0 {
+            return 1.0;
+        }
+        self.raw_bytes as f64 / self.stored_bytes as f64
+    }
+}
+
+/// Counters behind `CompressionStats`, updated by committing transactions
+#[derive(Debug, Default)]
+pub(crate) struct CompressionCounters {
+    values: AtomicU64,
+    compressed_values: AtomicU64,
+    raw_bytes: AtomicU64,
+    stored_bytes: AtomicU64,
+}
+
+impl CompressionCounters {
+    pub(crate) fn record(&self, raw_len: usize, stored_len: usize) {
+        self.values.fetch_add(1, Ordering::Relaxed);
+        if stored_len < raw_len {
+            self.compressed_values.fetch_add(1, Ordering::Relaxed);
+        }
+        self.raw_bytes.fetch_add(raw_len as u64, Ordering::Relaxed);
+        self.stored_bytes.fetch_add(stored_len as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> CompressionStats {
+        CompressionStats {
+            values: self.values.load(Ordering::Relaxed),
+            compressed_values: self.compressed_values.load(Ordering::Relaxed),
+            raw_bytes: self.raw_bytes.load(Ordering::Relaxed),
+            stored_bytes: self.stored_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "compression"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lz4_round_trip() {
+        let json = br#"{"name":"maemio","tags":["db","mvcc"]}"#.repeat(64);
+        let compressed = Compression::Lz4.compress(&json).unwrap();
+        assert!(compressed.len() < json.len() / 4);
+        assert_eq!(decompress(&compressed).unwrap(), json);
+
+        // Inline-sized and incompressible values are left alone
+        assert!(Compression::Lz4.compress(&json[..MAX_INLINE_SIZE]).is_none());
+        let noise: Vec<u8> = (0..4096).map(|_| rand::random::<u8>()).collect();
+        assert!(Compression::Lz4.compress(&noise).is_none());
+        assert!(Compression::None.compress(&json).is_none());
+    }
+}
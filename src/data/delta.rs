@@ -0,0 +1,211 @@
+// src/data/delta.rs
+//
+// Byte-level deltas between two versions of a record. Equal-length payloads
+// are stored as a list of patched byte runs; payloads whose length changed
+// keep their common prefix and suffix and store only the bytes in between.
+// Partial row updates know which spans they replaced and store those spans
+// of the older version directly, without comparing the payloads.
+use crate::error::{MaemioError, Result};
+use crate::schema::encoding::{read_varint, write_varint};
+
+const TAG_SPLICE: u8 = 0;
+const TAG_PATCHES: u8 = 1;
+const TAG_SPANS: u8 = 2;
+
+// Equal runs shorter than this are folded into the surrounding patch, since
+// a new patch header costs about as much as the bytes it would skip.
+const MIN_GAP: usize = 8;
+
+/// Encodes `target` as a delta against `base`
+pub(crate) fn encode(base: &[u8], target: &[u8]) -> Vec<u8> {
+    if base.len() == target.len() {
+        encode_patches(base, target)
+    } else {
+        encode_splice(base, target)
+    }
+}
+
+/// Encodes a delta that rebuilds a base from a newer payload by replacing
+/// the given `(offset, len, bytes)` spans of the newer payload with `bytes`.
+///
+/// Spans are ordered by offset and do not overlap.
+pub(crate) fn encode_spans(spans: &[(usize, usize, &[u8])]) -> Vec<u8> {
+    let size = spans.iter().map(|(_, _, bytes)| bytes.len() + 12).sum::<usize>();
+    let mut out = Vec::with_capacity(size + 1);
+    out.push(TAG_SPANS);
+    for &(offset, len, bytes) in spans {
+        write_varint(&mut out, offset as u64);
+        write_varint(&mut out, len as u64);
+        write_varint(&mut out, bytes.len() as u64);
+        out.extend_from_slice(bytes);
+    }
+    out
+}
+
+/// Rebuilds the target payload from `base` and a delta produced by `encode`
+pub(crate) fn apply(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let (&tag, mut rest) = delta.split_first().ok_or_else(|| corrupt("empty delta"))?;
+    match tag {
+        TAG_SPLICE => {
+            let prefix = read_varint(&mut rest)? as usize;
+            let suffix = read_varint(&mut rest)? as usize;
+            let kept = prefix.checked_add(suffix).ok_or_else(|| corrupt("splice out of range"))?;
+            if kept > base.len() {
+                return Err(corrupt("splice out of range"));
+            }
+            let mut out = Vec::with_capacity(prefix + rest.len() + suffix);
+            out.extend_from_slice(&base[..prefix]);
+            out.extend_from_slice(rest);
+            out.extend_from_slice(&base[base.len() - suffix..]);
+            Ok(out)
+        }
+        TAG_PATCHES => {
+            let mut out = base.to_vec();
+            while !rest.is_empty() {
+                let offset = read_varint(&mut rest)? as usize;
+                let len = read_varint(&mut rest)? as usize;
+                let end = offset.checked_add(len).ok_or_else(|| corrupt("patch out of range"))?;
+                if len > rest.len() || end > out.len() {
+                    return Err(corrupt("patch out of range"));
+                }
+                let (bytes, tail) = rest.split_at(len);
+                out[offset..end].copy_from_slice(bytes);
+                rest = tail;
+            }
+            Ok(out)
+        }
+        TAG_SPANS => {
+            let mut out = Vec::with_capacity(base.len());
+            let mut copied = 0;
+            while !rest.is_empty() {
+                let offset = read_varint(&mut rest)? as usize;
+                let len = read_varint(&mut rest)? as usize;
+                let bytes_len = read_varint(&mut rest)? as usize;
+                let end = offset.checked_add(len).ok_or_else(|| corrupt("span out of range"))?;
+                if offset < copied || end > base.len() || bytes_len > rest.len() {
+                    return Err(corrupt("span out of range"));
+                }
+                let (bytes, tail) = rest.split_at(bytes_len);
+                out.extend_from_slice(&base[copied..offset]);
+                out.extend_from_slice(bytes);
+                copied = end;
+                rest = tail;
+            }
+            out.extend_from_slice(&base[copied..]);
+            Ok(out)
+        }
+        _ => Err(corrupt("unknown delta kind")),
+    }
+}
+
+fn encode_splice(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let prefix = base.iter().zip(target).take_while(|(a, b)| a == b).count();
+    let max_suffix = base.len().min(target.len()) - prefix;
+    let suffix = base.iter()
+        .rev()
+        .zip(target.iter().rev())
+        .take(max_suffix)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let middle = &target[prefix..target.len() - suffix];
+    let mut out = Vec::with_capacity(middle.len() + 21);
+    out.push(TAG_SPLICE);
+    write_varint(&mut out, prefix as u64);
+    write_varint(&mut out, suffix as u64);
+    out.extend_from_slice(middle);
+    out
+}
+
+fn encode_patches(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut out = vec![TAG_PATCHES];
+    let mut i = 0;
+    while i < target.len() {
+        if base[i] == target[i] {
+            i += 1;
+            continue;
+        }
+
+        // Extend the patch until a long enough run of equal bytes
+        let start = i;
+        let mut end = i + 1;
+        let mut equal_run = 0;
+        while end < target.len() && equal_run < MIN_GAP {
+            if base[end] == target[end] {
+                equal_run += 1;
+            } else {
+                equal_run = 0;
+            }
+            end += 1;
+        }
+        let end = end - equal_run;
+
+        write_varint(&mut out, start as u64);
+        write_varint(&mut out, (end - start) as u64);
+        out.extend_from_slice(&target[start..end]);
+        i = end;
+    }
+    out
+}
+
+fn corrupt(reason: &str) -> MaemioError {
+    MaemioError::System(format!("Malformed version delta: {}", reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trips() {
+        let base: Vec<u8> = (0..4096).map(|i| (i % 251) as u8).collect();
+
+        let mut patched = base.clone();
+        patched[10] = 0xFF;
+        patched[2000..2004].copy_from_slice(b"abcd");
+        let delta = encode(&base, &patched);
+        assert!(delta.len() < 32);
+        assert_eq!(apply(&base, &delta).unwrap(), patched);
+
+        let mut grown = base.clone();
+        grown.splice(100..100, b"inserted".iter().copied());
+        let delta = encode(&base, &grown);
+        assert!(delta.len() < 32);
+        assert_eq!(apply(&base, &delta).unwrap(), grown);
+
+        for target in [Vec::new(), base[..10].to_vec(), base.clone()] {
+            assert_eq!(apply(&base, &encode(&base, &target)).unwrap(), target);
+        }
+        assert!(apply(&base[..5], &encode(&base, &grown)).is_err());
+    }
+
+    #[test]
+    fn test_spans() {
+        let newer = b"0123456789".to_vec();
+        let delta = encode_spans(&[(0, 2, b"a"), (5, 3, b"bcdef")]);
+        assert_eq!(apply(&newer, &delta).unwrap(), b"a234bcdef89");
+        assert_eq!(apply(&newer, &encode_spans(&[])).unwrap(), newer);
+        assert!(apply(&newer[..6], &delta).is_err());
+        assert!(apply(&newer, &encode_spans(&[(5, 1, b""), (2, 1, b"")])).is_err());
+    }
+
+    #[test]
+    fn test_offsets_near_usize_max() {
+        let base = b"0123456789".to_vec();
+        let near_max = usize::MAX as u64 - 1;
+
+        let mut splice = vec![TAG_SPLICE];
+        write_varint(&mut splice, near_max);
+        write_varint(&mut splice, 5);
+        assert!(apply(&base, &splice).is_err());
+
+        let mut patches = vec![TAG_PATCHES];
+        write_varint(&mut patches, near_max);
+        write_varint(&mut patches, 3);
+        patches.extend_from_slice(b"abc");
+        assert!(apply(&base, &patches).is_err());
+
+        let spans = encode_spans(&[(usize::MAX - 1, 5, b"")]);
+        assert!(apply(&base, &spans).is_err());
+    }
+}
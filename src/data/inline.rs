@@ -0,0 +1,131 @@
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+
+/// Largest payload that fits in a record head's inline buffer.
+pub const MAX_INLINE_SIZE: usize = 216;
+
+const INLINE_WORDS: usize = MAX_INLINE_SIZE / 8;
+const EMPTY: u64 = u64::MAX;
+
+/// Fixed-size buffer holding a copy of one committed version inside the record head.
+///
+/// The slot is a sequence lock: writers make the sequence odd while they copy
+/// bytes in, and readers retry whenever the sequence moved under them. The
+/// payload is stored in atomic words so concurrent copies never race.
+pub(crate) struct InlineSlot {
+    seq: AtomicU64,
+    wts: AtomicU64,
+    len: AtomicU64,
+    words: [AtomicU64; INLINE_WORDS],
+}
+
+impl InlineSlot {
+    pub(crate) fn new() -> Self {
+        Self {
+            seq: AtomicU64::new(0),
+            wts: AtomicU64::new(0),
+            len: AtomicU64::new(EMPTY),
+            words: std::array::from_fn(|_| AtomicU64::new(0)),
+        }
+    }
+
+    /// Offset of the payload words from the start of the slot
+    #[cfg(test)]
+    pub(crate) const BUFFER_OFFSET: usize = std::mem::offset_of!(InlineSlot, words);
+
+    /// Copies a version into the slot.
+    ///
+    /// Returns false without waiting if another writer currently owns the slot.
+    pub(crate) fn store(&self, wts: u64, data: &[u8]) -> bool {
+        debug_assert!(data.len() <= MAX_INLINE_SIZE);
+
+        let seq = self.seq.load(Ordering::Relaxed);
+        if seq & 1 == 1
+            || self.seq
+                .compare_exchange(seq, seq + 1, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+        {
+            return false;
+        }
+        fence(Ordering::Release);
+
+        self.wts.store(wts, Ordering::Relaxed);
+        self.len.store(data.len() as u64, Ordering::Relaxed);
+        for (word, chunk) in self.words.iter().zip(data.chunks(8)) {
+            let mut bytes = [0u8; 8];
+            bytes[..chunk.len()].copy_from_slice(chunk);
+            word.store(u64::from_le_bytes(bytes), Ordering::Relaxed);
+        }
+
+        self.seq.store(seq + 2, Ordering::Release);
+        true
+    }
+
+    /// Returns a consistent copy of the inlined version, if any.
+    pub(crate) fn load(&self) -> Option<(u64, Vec<u8>)> {
+        loop {
+            let start = self.seq.load(Ordering::Acquire);
+            if start & 1 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+
+            let wts = self.wts.load(Ordering::Relaxed);
+            let len = self.len.load(Ordering::Relaxed);
+            let snapshot = if len == EMPTY || len as usize > MAX_INLINE_SIZE {
+                None
+            } else {
+                let len = len as usize;
+                let mut data = Vec::with_capacity(len);
+                for word in &self.words[..len.div_ceil(8)] {
+                    data.extend_from_slice(&word.load(Ordering::Relaxed).to_le_bytes());
+                }
+                data.truncate(len);
+                Some((wts, data))
+            };
+
+            fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == start {
+                return snapshot;
+            }
+        }
+    }
+
+    /// Returns the write timestamp of the inlined version, if any.
+    pub(crate) fn wts(&self) -> Option<u64> {
+        loop {
+            let start = self.seq.load(Ordering::Acquire);
+            if start & 1 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+
+            let wts = self.wts.load(Ordering::Relaxed);
+            let occupied = self.len.load(Ordering::Relaxed) != EMPTY;
+
+            fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == start {
+                return occupied.then_some(wts);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_and_load() {
+        let slot = InlineSlot::new();
+        assert!(slot.load().is_none());
+
+        let data: Vec<u8> = (0..13).collect();
+        assert!(slot.store(42, &data));
+        assert_eq!(slot.load(), Some((42, data)));
+        assert_eq!(slot.wts(), Some(42));
+
+        let full = vec![0xAB; MAX_INLINE_SIZE];
+        assert!(slot.store(43, &full));
+        assert_eq!(slot.load(), Some((43, full)));
+    }
+}
@@ -0,0 +1,63 @@
+// src/data/memory.rs
+//
+// Live memory accounting for record storage. Every record of a table shares
+// the table's counters and updates them as versions are linked, re-encoded
+// and reclaimed, so reading the usage never walks the version chains.
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Approximate memory held by a table, in bytes unless noted otherwise
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemoryUsage {
+    /// Number of records
+    pub records: usize,
+    /// Record heads, including their inline buffers
+    pub record_bytes: usize,
+    /// Number of versions linked into version chains
+    pub versions: usize,
+    /// Version payloads as stored, so deltas and compressed values count at
+    /// their encoded size
+    pub data_bytes: usize,
+    /// Chain nodes and version metadata
+    pub version_overhead_bytes: usize,
+    /// Entries of the indexes maintained on the table
+    pub index_bytes: usize,
+}
+
+impl MemoryUsage {
+    pub fn total_bytes(&self) -> usize {
+        self.record_bytes + self.data_bytes + self.version_overhead_bytes + self.index_bytes
+    }
+}
+
+/// Version counts shared by the records of one table
+#[derive(Debug, Default)]
+pub(crate) struct MemoryCounters {
+    versions: AtomicUsize,
+    data_bytes: AtomicUsize,
+}
+
+impl MemoryCounters {
+    pub(crate) fn add_version(&self, data_len: usize) {
+        self.versions.fetch_add(1, Ordering::Relaxed);
+        self.data_bytes.fetch_add(data_len, Ordering::Relaxed);
+    }
+
+    pub(crate) fn remove_version(&self, data_len: usize) {
+        self.versions.fetch_sub(1, Ordering::Relaxed);
+        self.data_bytes.fetch_sub(data_len, Ordering::Relaxed);
+    }
+
+    /// Records a version's payload being replaced by one of another size
+    pub(crate) fn resize_version(&self, old_len: usize, new_len: usize) {
+        self.data_bytes.fetch_add(new_len, Ordering::Relaxed);
+        self.data_bytes.fetch_sub(old_len, Ordering::Relaxed);
+    }
+
+    pub(crate) fn versions(&self) -> usize {
+        self.versions.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn data_bytes(&self) -> usize {
+        self.data_bytes.load(Ordering::Relaxed)
+    }
+}
@@ -1,8 +1,22 @@
 mod version;
+pub(crate) mod delta;
+mod compression;
+mod memory;
+mod arena;
 mod record;
+mod inline;
+mod record_table;
 
 pub use version::Version;
 pub use record::RecordHead;
+pub use record_table::RecordTable;
+pub use inline::MAX_INLINE_SIZE;
+pub use compression::{Compression, CompressionStats};
+pub(crate) use compression::CompressionCounters;
+pub use memory::MemoryUsage;
+pub use arena::{ArenaStats, PayloadArena};
+pub(crate) use memory::MemoryCounters;
+pub(crate) use record::{RECORD_OVERHEAD, VERSION_OVERHEAD};
 
 // Export common constants
 pub const VERSION_STATUS_UNUSED: u8 = 0;
@@ -1,115 +1,452 @@
+use super::compression;
+use super::delta;
+use super::inline::{InlineSlot, MAX_INLINE_SIZE};
+use super::memory::MemoryCounters;
 use super::Version;
-use parking_lot::RwLock;
-use std::sync::atomic::{AtomicU64, Ordering};
+use crate::error::{self, MaemioError};
+use crossbeam_epoch::{self as epoch, Atomic, Guard, Owned, Shared};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
 
-const MAX_INLINE_SIZE: usize = 216;
+/// Reads of the newest version served from the chain before it is promoted inline
+const INLINE_PROMOTION_THRESHOLD: u32 = 4;
 
+/// Memory taken by a linked version besides its payload: the chain node, the
+/// boxed handle in its version slot and the shared version itself
+pub(crate) const VERSION_OVERHEAD: usize = std::mem::size_of::<VersionNode>()
+    + std::mem::size_of::<Arc<Version>>()
+    + std::mem::size_of::<Version>()
+    + 2 * std::mem::size_of::<usize>();
+
+/// Memory taken by a record head, its reference counts and its table entry
+pub(crate) const RECORD_OVERHEAD: usize = std::mem::size_of::<RecordHead>()
+    + 2 * std::mem::size_of::<usize>()
+    + std::mem::size_of::<(u64, Arc<RecordHead>)>();
+
+/// A link in a record's version chain.
+///
+/// The version itself is shared through an `Arc`, so readers get a handle to
+/// the stored bytes instead of a copy, and a version stays alive for as long
+/// as a reader holds it even after the collector unlinks its node.
+///
+/// The version can be swapped for an equivalent one while the node is linked,
+/// which is how a full copy is replaced by a delta and back.
+struct VersionNode {
+    version: Atomic<Arc<Version>>,
+    next: Atomic<VersionNode>,
+}
+
+impl VersionNode {
+    fn new(version: Arc<Version>) -> Self {
+        Self {
+            version: Atomic::new(version),
+            next: Atomic::null(),
+        }
+    }
+
+    fn version<'g>(&self, guard: &'g Guard) -> &'g Arc<Version> {
+        // The slot is never null and replaced versions are reclaimed through
+        // the epoch collector, so the reference lives as long as the guard.
+        unsafe { self.version.load(Ordering::Acquire, guard).deref() }
+    }
+
+    /// Swaps in an equivalent version, returning the payload size it replaced
+    fn replace_version(&self, version: Arc<Version>, guard: &Guard) -> usize {
+        let old = self.version.swap(Owned::new(version), Ordering::AcqRel, guard);
+        let old_len = unsafe { old.deref() }.data.len();
+        unsafe { guard.defer_destroy(old) };
+        old_len
+    }
+}
+
+impl Drop for VersionNode {
+    fn drop(&mut self) {
+        // Nodes are dropped once unreachable, and so is their version slot.
+        unsafe {
+            drop(self.version.load(Ordering::Relaxed, epoch::unprotected()).into_owned());
+        }
+    }
+}
+
+/// What becomes of the version superseded by a newly installed head
+enum Superseded {
+    /// Stays a full copy
+    Keep,
+    /// Diffed against the new head when both are at least this many bytes
+    Diff(usize),
+    /// Stored as a delta the writer already has, if it is the version the
+    /// new head was made from
+    Patched { base_wts: u64, delta: Vec<u8> },
+}
+
+/// Head of a record's version chain.
+///
+/// Versions are kept in a singly linked list ordered from the newest to the
+/// oldest write timestamp. New versions are linked in with compare-and-swap,
+/// readers traverse the chain without taking locks, and versions unlinked by
+/// the garbage collector are reclaimed once no pinned reader can still see them.
+///
+/// Small committed versions are also copied into an inline buffer that shares
+/// cache lines with the head, so reads of the newest version do not have to
+/// chase the chain. A version is inlined when it is installed into an empty
+/// slot, or promoted there after it has been read repeatedly from the chain.
+///
+/// With delta encoding enabled, a large version that is superseded at the head
+/// is stored as a delta against its newer neighbour, and rebuilt from the
+/// versions above it when read. A delta's base is always the node directly
+/// above it: linking a version further down the chain first turns the delta
+/// below the insertion point back into a full copy. Versions superseded by a
+/// partial update are stored as the delta of the columns it replaced,
+/// whether or not delta encoding is enabled.
+#[repr(C, align(64))]
 pub struct RecordHead {
-    version_list: RwLock<Option<Box<Version>>>,
-    inline_version: RwLock<Option<Version>>,
+    head: Atomic<VersionNode>,
+    // Largest write timestamp installed so far
+    latest_wts: AtomicU64,
+    inline: InlineSlot,
+    inline_misses: AtomicU32,
     min_wts: AtomicU64,
     gc_lock: parking_lot::Mutex<()>,
+    // Serializes delta encoding and garbage collection with versions linked
+    // below the head
+    delta_lock: parking_lot::Mutex<()>,
+    // Counters of the table the record belongs to
+    memory: Arc<MemoryCounters>,
     creation_timestamp: u64,
 }
 
 impl RecordHead {
     pub fn new(creation_ts: u64) -> Self {
+        Self::with_memory(creation_ts, Arc::default())
+    }
+
+    /// Creates a record that accounts its versions in `memory`
+    pub(crate) fn with_memory(creation_ts: u64, memory: Arc<MemoryCounters>) -> Self {
         // Instead of creating an initial version, start with no version installed.
         Self {
-            version_list: RwLock::new(None),
-            inline_version: RwLock::new(None),
+            head: Atomic::null(),
+            latest_wts: AtomicU64::new(0),
+            inline: InlineSlot::new(),
+            inline_misses: AtomicU32::new(0),
             min_wts: AtomicU64::new(creation_ts),
             gc_lock: parking_lot::Mutex::new(()),
+            delta_lock: parking_lot::Mutex::new(()),
+            memory,
             creation_timestamp: creation_ts,
         }
     }
 
-    /// Attempts to create an inline version
-    pub fn try_inline_version(&self, version: Version) -> bool {
-        // Only inline if the data is small enough.
-        if version.data.len() > MAX_INLINE_SIZE {
-            return false;
+    /// Adds a new version to the version chain.
+    pub fn add_version(&self, version: impl Into<Arc<Version>>) {
+        let _ = self.install_version(version);
+    }
+
+    /// Links a version into the chain at the position given by its write timestamp.
+    pub fn install_version(&self, version: impl Into<Arc<Version>>) -> Result<(), ()> {
+        self.install(version.into(), Superseded::Keep)
+    }
+
+    /// Links a version like `install_version`, then stores the version it
+    /// superseded as a delta against it.
+    ///
+    /// Versions smaller than `min_size` bytes stay full copies, as do versions
+    /// whose delta would not be less than half their size.
+    pub fn install_version_delta(&self, version: impl Into<Arc<Version>>, min_size: usize) -> Result<(), ()> {
+        self.install(version.into(), Superseded::Diff(min_size))
+    }
+
+    /// Links a version made by changing a few parts of the version written at
+    /// `base_wts`, then stores that version as `delta`, a delta that rebuilds
+    /// it from the new one.
+    ///
+    /// The delta is dropped if the version it superseded is not the base, or
+    /// if it would not be less than half the base's size.
+    pub fn install_version_patched(
+        &self,
+        version: impl Into<Arc<Version>>,
+        base_wts: u64,
+        delta: Vec<u8>,
+    ) -> Result<(), ()> {
+        self.install(version.into(), Superseded::Patched { base_wts, delta })
+    }
+
+    fn install(&self, version: Arc<Version>, superseded: Superseded) -> Result<(), ()> {
+        let at_head = self.link_version(version.clone());
+        self.latest_wts.fetch_max(version.wts, Ordering::AcqRel);
+
+        // Best-effort inlining: fill an empty slot with a small committed version.
+        if version.data.len() <= MAX_INLINE_SIZE
+            && version.is_committed()
+            && !version.is_tombstone()
+            && !version.is_compressed()
+            && !version.is_blob_manifest()
+            && version.expires_at.is_none()
+            && self.inline.wts().is_none()
+        {
+            self.inline.store(version.wts, &version.data);
         }
 
-        let mut inline = self.inline_version.write();
-        if inline.is_some() {
-            return false;
+        if at_head {
+            self.encode_delta_below(&version, superseded);
         }
+        Ok(())
+    }
+
+    /// Links a node into the chain, returning whether it became the head.
+    fn link_version(&self, version: Arc<Version>) -> bool {
+        let guard = epoch::pin();
+        let wts = version.wts;
+        let data_len = version.data.len();
+        let mut new_node = Owned::new(VersionNode::new(version));
+
+        loop {
+            // The common case, a version newer than all others, needs no lock.
+            let head = self.head.load(Ordering::Acquire, &guard);
+            if unsafe { head.as_ref() }.is_none_or(|node| node.version(&guard).wts <= wts) {
+                new_node.next.store(head, Ordering::Relaxed);
+                match self.head.compare_exchange(head, new_node, Ordering::AcqRel, Ordering::Acquire, &guard) {
+                    Ok(_) => {
+                        self.memory.add_version(data_len);
+                        return true;
+                    }
+                    Err(e) => {
+                        new_node = e.new;
+                        continue;
+                    }
+                }
+            }
 
-        *inline = Some(version);
-        true
+            // Find the first version that is not newer than the one being installed.
+            let _delta_guard = self.delta_lock.lock();
+            let mut prev = &self.head;
+            let mut current = prev.load(Ordering::Acquire, &guard);
+            while let Some(node) = unsafe { current.as_ref() } {
+                if node.version(&guard).wts <= wts {
+                    break;
+                }
+                prev = &node.next;
+                current = node.next.load(Ordering::Acquire, &guard);
+            }
+
+            // The node below is about to get a new neighbour, so it can no
+            // longer be a delta against the old one.
+            if let Some(below) = unsafe { current.as_ref() } {
+                if below.version(&guard).is_delta() {
+                    // A delta that cannot be applied is left for reads to report
+                    if let Ok(Some(full)) = self.materialize(&guard, |node| std::ptr::eq(node, below)) {
+                        self.replace_version(below, full, &guard);
+                    }
+                }
+            }
+
+            new_node.next.store(current, Ordering::Relaxed);
+            match prev.compare_exchange(
+                current,
+                new_node,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+                &guard,
+            ) {
+                Ok(_) => {
+                    self.memory.add_version(data_len);
+                    return false;
+                }
+                // Another writer changed the chain here; retry from the head.
+                Err(e) => new_node = e.new,
+            }
+        }
     }
 
-    /// Adds a new version to the version chain.
-    pub fn add_version(&self, mut version: Version) {
-        // First try to inline if possible.
-        if !self.try_inline_version(version.clone()) {
-            let mut list = self.version_list.write();
-            version.next = list.take();
-            *list = Some(Box::new(version));
-        }
-    }
-
-    pub fn install_version(&self, version: Version) -> Result<(), ()> {
-        // If the version's data is small enough, try to store it inline.
-        if version.data.len() <= MAX_INLINE_SIZE {
-            let mut inline = self.inline_version.write();
-            if inline.is_none() {
-                *inline = Some(version);
-                return Ok(());
-            } else if version.wts >= inline.as_ref().unwrap().wts {
-                // New version is as new or newer than the current inline.
-                // Move the old inline version into the version_list.
-                let old_inline = inline.take().unwrap();
-                {
-                    let mut list = self.version_list.write();
-                    let mut boxed_old = Box::new(old_inline);
-                    boxed_old.next = list.take();
-                    *list = Some(boxed_old);
+    /// Re-encodes the version directly below `newer` as a delta against it.
+    ///
+    /// Skipped when another thread is linking or encoding versions, or when
+    /// `newer` is no longer where it was installed.
+    fn encode_delta_below(&self, newer: &Arc<Version>, superseded: Superseded) {
+        let min_size = match superseded {
+            Superseded::Keep => return,
+            Superseded::Diff(min_size) => min_size,
+            Superseded::Patched { .. } => 0,
+        };
+        if !Self::delta_candidate(newer, min_size) {
+            return;
+        }
+        let Some(_delta_guard) = self.delta_lock.try_lock() else {
+            return;
+        };
+
+        let guard = epoch::pin();
+        let mut current = self.head.load(Ordering::Acquire, &guard);
+        while let Some(node) = unsafe { current.as_ref() } {
+            let version = node.version(&guard);
+            if version.wts < newer.wts {
+                return;
+            }
+            if Arc::ptr_eq(version, newer) {
+                let next = node.next.load(Ordering::Acquire, &guard);
+                let Some(below) = (unsafe { next.as_ref() }) else {
+                    return;
+                };
+                let older = below.version(&guard);
+                if !Self::delta_candidate(older, min_size) {
+                    return;
                 }
-                *inline = Some(version);
-                return Ok(());
+                let encoded = match superseded {
+                    Superseded::Patched { base_wts, delta } if base_wts == older.wts => delta,
+                    Superseded::Patched { .. } | Superseded::Keep => return,
+                    Superseded::Diff(_) => delta::encode(&newer.data, &older.data),
+                };
+                if encoded.len() < older.data.len() / 2 {
+                    let mut delta = Version::delta(older.wts, encoded);
+                    delta.expires_at = older.expires_at;
+                    delta.blob_manifest = older.blob_manifest;
+                    self.replace_version(below, Arc::new(delta), &guard);
+                }
+                return;
             }
+            current = node.next.load(Ordering::Acquire, &guard);
         }
-        // Otherwise, add the version to the version_list.
-        let mut list = self.version_list.write();
-        let mut new_version = Box::new(version);
-        new_version.next = list.take();
-        *list = Some(new_version);
-        Ok(())
     }
-    
-    pub fn get_current_version(&self) -> Option<Box<Version>> {
-        self.version_list.read().clone()
+
+    fn replace_version(&self, node: &VersionNode, version: Arc<Version>, guard: &Guard) {
+        let new_len = version.data.len();
+        let old_len = node.replace_version(version, guard);
+        self.memory.resize_version(old_len, new_len);
+    }
+
+    fn delta_candidate(version: &Version, min_size: usize) -> bool {
+        version.is_committed()
+            && !version.is_tombstone()
+            && !version.is_delta()
+            && !version.is_compressed()
+            && version.data.len() >= min_size
     }
-    
+
     /// Finds the latest visible version for a given timestamp.
-    pub fn find_visible_version(&self, ts: u64) -> Option<Arc<Version>> {
+    ///
+    /// Fails if the version is stored as a delta that cannot be applied.
+    pub fn find_visible_version(&self, ts: u64) -> error::Result<Option<Arc<Version>>> {
+        if ts < self.creation_timestamp {
+            return Ok(None);
+        }
+
+        if let Some(version) = self.read_inline(ts) {
+            return Ok(Some(version));
+        }
+
+        let guard = epoch::pin();
+        let Some(version) = self.materialize(&guard, |node| node.version(&guard).is_visible_to(ts))? else {
+            return Ok(None);
+        };
+        if version.wts == self.latest_wts.load(Ordering::Acquire) {
+            self.note_inline_miss(&version);
+        }
+        Ok(Some(version))
+    }
+
+    /// Returns the write timestamp of the latest version visible at `ts`
+    /// without rebuilding or decompressing its value.
+    pub fn visible_wts(&self, ts: u64) -> Option<u64> {
         if ts < self.creation_timestamp {
             return None;
         }
+        if let Some(wts) = self.inline.wts() {
+            if wts <= ts && wts == self.latest_wts.load(Ordering::Acquire) {
+                return Some(wts);
+            }
+        }
+        let guard = epoch::pin();
+        self.visible_in_chain(ts, &guard).map(|node| node.version(&guard).wts)
+    }
 
-        // Check inline version first.
-        {
-            let inline = self.inline_version.read();
-            if let Some(ref version) = *inline {
-                if version.is_visible_to(ts) {
-                    return Some(Arc::new(version.clone()));
+    /// Returns the version of the first node matching `found`, walking down
+    /// from the head and applying deltas to the nearest full version above it.
+    ///
+    /// Uncompressed full versions are shared rather than copied.
+    fn materialize<'g, F>(&self, guard: &'g Guard, found: F) -> error::Result<Option<Arc<Version>>>
+    where
+        F: Fn(&VersionNode) -> bool,
+    {
+        let mut base: Option<&'g Arc<Version>> = None;
+        let mut deltas: Vec<&'g Arc<Version>> = Vec::new();
+        let mut current = self.head.load(Ordering::Acquire, guard);
+        while let Some(node) = unsafe { current.as_ref() } {
+            let version = node.version(guard);
+            if version.is_delta() {
+                deltas.push(version);
+            } else {
+                base = Some(version);
+                deltas.clear();
+            }
+
+            if found(node) {
+                let Some(last) = deltas.last() else {
+                    return Self::decompressed(version).map(Some);
+                };
+                let base = base.ok_or_else(|| {
+                    MaemioError::System("Version delta has no full version above it".to_string())
+                })?;
+                let mut data = base.data.clone();
+                for delta in &deltas {
+                    data = delta::apply(&data, &delta.data)?;
                 }
+                return Ok(Some(Arc::new(last.with_data(data))));
             }
+            current = node.next.load(Ordering::Acquire, guard);
         }
+        Ok(None)
+    }
 
-        // Check version list.
-        let list = self.version_list.read();
-        let mut current = list.as_ref();
-        while let Some(version) = current {
-            if version.is_visible_to(ts) {
-                return Some(Arc::new((**version).clone()));
-            }
-            current = version.next.as_ref();
+    /// Returns the version with its value decompressed, or the version itself
+    /// if it is stored uncompressed
+    fn decompressed(version: &Arc<Version>) -> error::Result<Arc<Version>> {
+        if !version.is_compressed() {
+            return Ok(version.clone());
         }
+        let data = compression::decompress(&version.data)?;
+        Ok(Arc::new(version.with_data(data)))
+    }
+
+    /// Serves a read from the inline buffer when it holds the newest version.
+    ///
+    /// Inlined bytes are small, so they are copied into a fresh version rather
+    /// than shared with the chain.
+    fn read_inline(&self, ts: u64) -> Option<Arc<Version>> {
+        let (wts, data) = self.inline.load()?;
+        if wts > ts || wts != self.latest_wts.load(Ordering::Acquire) {
+            return None;
+        }
+        Some(Arc::new(Version::new_committed(wts, data)))
+    }
+
+    /// Promotes the newest version into the inline buffer once it has been
+    /// read from the chain often enough.
+    fn note_inline_miss(&self, version: &Version) {
+        if version.data.len() > MAX_INLINE_SIZE
+            || version.is_tombstone()
+            || version.is_blob_manifest()
+            || version.expires_at.is_some()
+        {
+            return;
+        }
+        let misses = self.inline_misses.fetch_add(1, Ordering::Relaxed) + 1;
+        if misses >= INLINE_PROMOTION_THRESHOLD && self.inline.store(version.wts, &version.data) {
+            self.inline_misses.store(0, Ordering::Relaxed);
+        }
+    }
 
+    /// Returns the write timestamp of the version held inline, if any.
+    pub fn inline_wts(&self) -> Option<u64> {
+        self.inline.wts()
+    }
+
+    fn visible_in_chain<'g>(&self, ts: u64, guard: &'g Guard) -> Option<&'g VersionNode> {
+        let mut current = self.head.load(Ordering::Acquire, guard);
+        while let Some(node) = unsafe { current.as_ref() } {
+            if node.version(guard).is_visible_to(ts) {
+                return Some(node);
+            }
+            current = node.next.load(Ordering::Acquire, guard);
+        }
         None
     }
 
@@ -118,29 +455,99 @@ impl RecordHead {
         self.gc_lock.try_lock().is_some()
     }
 
+    /// Unlinks versions that no active transaction can read anymore.
+    ///
+    /// The newest committed version older than `min_rts` is still visible to
+    /// every transaction, so everything after it is cut off the chain and
+    /// handed to the epoch collector. Returns the number of versions unlinked,
+    /// or `None` if another collection of this record is running.
+    pub fn collect_garbage(&self, min_rts: u64) -> Option<usize> {
+        let _gc_guard = self.gc_lock.try_lock()?;
+        // Versions linked below the head are linked under this lock, so a
+        // node cannot be linked into the tail while it is being cut off.
+        let _delta_guard = self.delta_lock.lock();
+
+        let guard = epoch::pin();
+        let Some(oldest_needed) = self.visible_in_chain(min_rts.saturating_sub(1), &guard) else {
+            return Some(0);
+        };
+
+        let mut tail = oldest_needed.next.swap(Shared::null(), Ordering::AcqRel, &guard);
+        let mut reclaimed = 0;
+        while !tail.is_null() {
+            let node = unsafe { tail.deref() };
+            self.memory.remove_version(node.version(&guard).data.len());
+            let next = node.next.load(Ordering::Acquire, &guard);
+            unsafe { guard.defer_destroy(tail) };
+            tail = next;
+            reclaimed += 1;
+        }
+        Some(reclaimed)
+    }
+
     /// Updates the minimum write timestamp.
     pub fn update_min_wts(&self, ts: u64) {
         self.min_wts.store(ts, Ordering::Release);
     }
 
+    /// Returns the number of payload bytes held by the chain, counting deltas
+    /// at their encoded size.
+    pub fn stored_bytes(&self) -> usize {
+        let guard = epoch::pin();
+        let mut bytes = 0;
+        let mut current = self.head.load(Ordering::Acquire, &guard);
+        while let Some(node) = unsafe { current.as_ref() } {
+            bytes += node.version(&guard).data.len();
+            current = node.next.load(Ordering::Acquire, &guard);
+        }
+        bytes
+    }
+
+    /// Returns the number of versions currently linked into the chain.
+    pub fn version_count(&self) -> usize {
+        let guard = epoch::pin();
+        let mut count = 0;
+        let mut current = self.head.load(Ordering::Acquire, &guard);
+        while let Some(node) = unsafe { current.as_ref() } {
+            count += 1;
+            current = node.next.load(Ordering::Acquire, &guard);
+        }
+        count
+    }
+
     pub fn debug_versions(&self) -> String {
         let mut info = String::new();
-        let inline = self.inline_version.read();
-        if let Some(ref version) = *inline {
-            info.push_str(&format!("Inline version - wts: {}, status: {}\n", 
-                version.wts,
-                version.status.load(Ordering::Acquire)));
-        }
-        let list = self.version_list.read();
-        if let Some(ref version) = *list {
-            info.push_str(&format!("List version - wts: {}, status: {}\n",
+        let guard = epoch::pin();
+        let mut current = self.head.load(Ordering::Acquire, &guard);
+        while let Some(node) = unsafe { current.as_ref() } {
+            let version = node.version(&guard);
+            info.push_str(&format!("Version - wts: {}, status: {}, delta: {}\n",
                 version.wts,
-                version.status.load(Ordering::Acquire)));
+                version.status.load(Ordering::Acquire),
+                version.is_delta()));
+            current = node.next.load(Ordering::Acquire, &guard);
         }
         info
     }
 }
 
+impl Drop for RecordHead {
+    fn drop(&mut self) {
+        // No other thread can reach the chain anymore, so free it directly.
+        unsafe {
+            let guard = epoch::unprotected();
+            let mut current = self.head.load(Ordering::Relaxed, guard);
+            while !current.is_null() {
+                let node = current.deref();
+                self.memory.remove_version(node.version(guard).data.len());
+                let next = node.next.load(Ordering::Relaxed, guard);
+                drop(current.into_owned());
+                current = next;
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -148,30 +555,207 @@ mod tests {
     #[test]
     fn test_version_visibility() {
         let record = RecordHead::new(0);
-        
+
         // Create two versions.
         let v1 = Version::new(100, vec![1]);
         let v2 = Version::new(200, vec![2]);
-        
+
         // DO NOT commit versions yet.
         // Install versions into the record (newer version installed first).
         record.install_version(v2).unwrap();
         record.install_version(v1).unwrap();
-        
+
         // No version should be visible before commit.
-        assert!(record.find_visible_version(150).is_none(), 
+        assert!(record.find_visible_version(150).unwrap().is_none(),
             "Uncommitted version should not be visible");
-        
-        // Now commit v1 (which is stored in version_list).
-        if let Some(ref version_in_list) = *record.version_list.read() {
-            version_in_list.commit();
+
+        // Now commit v1 (the older version, second in the chain).
+        {
+            let guard = epoch::pin();
+            let newest = unsafe { record.head.load(Ordering::Acquire, &guard).deref() };
+            let older = unsafe { newest.next.load(Ordering::Acquire, &guard).deref() };
+            assert_eq!(older.version(&guard).wts, 100);
+            older.version(&guard).commit();
         }
-        
+
         // v1 should now be visible.
-        let visible = record.find_visible_version(150).unwrap();
+        let visible = record.find_visible_version(150).unwrap().unwrap();
         assert_eq!(visible.data, vec![1]);
-        
+
         // v2 is still not visible because it is not committed.
-        assert_eq!(record.find_visible_version(250).unwrap().data, vec![1]);
+        assert_eq!(record.find_visible_version(250).unwrap().unwrap().data, vec![1]);
+    }
+
+    #[test]
+    fn test_concurrent_installs() {
+        let record = Arc::new(RecordHead::new(0));
+
+        let handles: Vec<_> = (0..4u64)
+            .map(|thread| {
+                let record = record.clone();
+                std::thread::spawn(move || {
+                    for i in 0..250u64 {
+                        let version = Version::new(i * 4 + thread + 1, vec![thread as u8]);
+                        version.commit();
+                        record.install_version(version).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(record.version_count(), 1000);
+        assert_eq!(record.find_visible_version(u64::MAX).unwrap().unwrap().wts, 1000);
+        assert_eq!(record.find_visible_version(500).unwrap().unwrap().wts, 500);
+    }
+
+    #[test]
+    fn test_collect_garbage() {
+        let record = RecordHead::new(0);
+        for wts in [10, 20, 30, 40] {
+            let version = Version::new(wts, vec![wts as u8]);
+            version.commit();
+            record.install_version(version).unwrap();
+        }
+
+        // Version 20 is still visible to a reader at 25; 10 is not.
+        assert_eq!(record.collect_garbage(25), Some(1));
+        assert_eq!(record.version_count(), 3);
+        assert_eq!(record.find_visible_version(25).unwrap().unwrap().data, vec![20]);
+        assert_eq!(record.find_visible_version(45).unwrap().unwrap().data, vec![40]);
+
+        // A collection that finds the record busy reports it instead of
+        // claiming there was nothing to reclaim
+        let busy = record.gc_lock.lock();
+        assert_eq!(record.collect_garbage(45), None);
+        drop(busy);
+        assert_eq!(record.collect_garbage(45), Some(2));
+    }
+
+    #[test]
+    fn test_record_head_layout() {
+        assert_eq!(std::mem::align_of::<RecordHead>(), 64);
+        let inline = std::mem::offset_of!(RecordHead, inline);
+        assert!(inline < 64);
+        // The whole buffer is part of the head, not reached through a pointer
+        assert!(inline + InlineSlot::BUFFER_OFFSET + MAX_INLINE_SIZE <= std::mem::size_of::<RecordHead>());
+    }
+
+    #[test]
+    fn test_small_versions_are_inlined() {
+        let record = RecordHead::new(0);
+        let v1 = Arc::new(Version::new(10, vec![1, 2, 3]));
+        v1.commit();
+        record.install_version(v1.clone()).unwrap();
+        assert_eq!(record.inline_wts(), Some(10));
+        assert_eq!(record.find_visible_version(15).unwrap().unwrap().data, vec![1, 2, 3]);
+
+        // A newer version shadows the inline one until it is promoted.
+        let v2 = Version::new(20, vec![4, 5]);
+        v2.commit();
+        record.install_version(v2).unwrap();
+        assert_eq!(record.inline_wts(), Some(10));
+        assert_eq!(record.find_visible_version(15).unwrap().unwrap().data, vec![1, 2, 3]);
+
+        for _ in 0..INLINE_PROMOTION_THRESHOLD {
+            assert_eq!(record.find_visible_version(25).unwrap().unwrap().data, vec![4, 5]);
+        }
+        assert_eq!(record.inline_wts(), Some(20));
+        assert_eq!(record.find_visible_version(25).unwrap().unwrap().data, vec![4, 5]);
+        assert_eq!(record.find_visible_version(15).unwrap().unwrap().data, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_large_versions_stay_in_chain() {
+        let record = RecordHead::new(0);
+        let version = Version::new(10, vec![0; MAX_INLINE_SIZE + 1]);
+        version.commit();
+        record.install_version(version).unwrap();
+
+        for _ in 0..INLINE_PROMOTION_THRESHOLD {
+            record.find_visible_version(15).unwrap().unwrap();
+        }
+        assert_eq!(record.inline_wts(), None);
+    }
+
+    #[test]
+    fn test_reads_share_stored_version() {
+        let record = RecordHead::new(0);
+        let version = Arc::new(Version::new(10, vec![7; 4096]));
+        version.commit();
+        record.install_version(version.clone()).unwrap();
+
+        let first = record.find_visible_version(20).unwrap().unwrap();
+        let second = record.find_visible_version(20).unwrap().unwrap();
+        assert!(Arc::ptr_eq(&first, &version));
+        assert!(Arc::ptr_eq(&first, &second));
+
+        // Readers keep their handle after the version is unlinked.
+        let newer = Version::new(30, vec![8]);
+        newer.commit();
+        record.install_version(newer).unwrap();
+        assert_eq!(record.collect_garbage(40), Some(1));
+        assert_eq!(first.data.len(), 4096);
+    }
+
+    #[test]
+    fn test_superseded_versions_become_deltas() {
+        let record = RecordHead::new(0);
+        let payload = |wts: u64| {
+            let mut data = vec![0u8; 64 * 1024];
+            data[wts as usize * 10] = wts as u8;
+            data
+        };
+        for wts in (10..=100).step_by(10) {
+            let version = Version::new(wts, payload(wts));
+            version.commit();
+            record.install_version_delta(version, 1024).unwrap();
+        }
+
+        // Only the newest version is a full copy
+        assert_eq!(record.version_count(), 10);
+        assert!(record.stored_bytes() < 2 * 64 * 1024);
+        for wts in (10..=100).step_by(10) {
+            assert_eq!(record.find_visible_version(wts + 5).unwrap().unwrap().data, payload(wts));
+        }
+
+        // A late version linked between a delta and its base
+        let late = Version::new(55, payload(55));
+        late.commit();
+        record.install_version_delta(late, 1024).unwrap();
+        for wts in [50, 55, 60] {
+            assert_eq!(record.find_visible_version(wts).unwrap().unwrap().data, payload(wts));
+        }
+
+        // The oldest version kept by the collector can still be rebuilt
+        assert_eq!(record.collect_garbage(35), Some(2));
+        assert_eq!(record.find_visible_version(35).unwrap().unwrap().data, payload(30));
+
+        // Small versions keep full copies
+        let small = RecordHead::new(0);
+        for wts in [10, 20] {
+            let version = Version::new(wts, vec![wts as u8; 512]);
+            version.commit();
+            small.install_version_delta(version, 1024).unwrap();
+        }
+        assert_eq!(small.stored_bytes(), 1024);
+    }
+
+    #[test]
+    fn test_malformed_delta_is_an_error() {
+        let record = RecordHead::new(0);
+        record.add_version(Version::new_committed(20, vec![1; 64]));
+        record.add_version(Version::delta(10, vec![0xFF, 1, 2]));
+
+        assert_eq!(record.find_visible_version(25).unwrap().unwrap().wts, 20);
+        assert!(record.find_visible_version(15).is_err());
+
+        let compressed = Version::compressed(30, vec![0xFF; 8]);
+        compressed.commit();
+        record.add_version(compressed);
+        assert!(record.find_visible_version(35).is_err());
+        assert_eq!(record.visible_wts(35), Some(30));
     }
 }
@@ -0,0 +1,207 @@
+use super::RecordHead;
+use crossbeam_skiplist::SkipMap;
+use crossbeam_utils::CachePadded;
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+
+type Shard = CachePadded<RwLock<HashMap<u64, Arc<RecordHead>>>>;
+
+/// Concurrent map from record ids to record heads.
+///
+/// Records are spread over independently locked shards, so lookups on
+/// different shards never contend and creating a record only blocks readers
+/// of the one shard it lands in. A lock-free skip list orders the same
+/// records by id for range queries, and is kept in step under the shard lock.
+pub struct RecordTable {
+    shards: Box<[Shard]>,
+    shift: u32,
+    ordered: SkipMap<u64, Arc<RecordHead>>,
+}
+
+impl RecordTable {
+    /// Creates a table with at least `shard_count` shards (rounded up to a power of two)
+    pub fn new(shard_count: usize) -> Self {
+        let shard_count = shard_count.max(1).next_power_of_two();
+        let shards = (0..shard_count)
+            .map(|_| CachePadded::new(RwLock::new(HashMap::new())))
+            .collect();
+
+        Self {
+            shards,
+            shift: 64 - shard_count.trailing_zeros(),
+            ordered: SkipMap::new(),
+        }
+    }
+
+    /// Picks a shard with Fibonacci hashing so sequential ids spread evenly
+    fn shard(&self, record_id: u64) -> &Shard {
+        let hash = record_id.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        let idx = hash.checked_shr(self.shift).unwrap_or(0) as usize;
+        &self.shards[idx]
+    }
+
+    pub fn get(&self, record_id: u64) -> Option<Arc<RecordHead>> {
+        self.shard(record_id).read().get(&record_id).cloned()
+    }
+
+    pub fn contains(&self, record_id: u64) -> bool {
+        self.shard(record_id).read().contains_key(&record_id)
+    }
+
+    /// Inserts a record unless the id is already taken. Returns whether it was inserted.
+    pub fn insert(&self, record_id: u64, record: Arc<RecordHead>) -> bool {
+        let mut shard = self.shard(record_id).write();
+        if shard.contains_key(&record_id) {
+            return false;
+        }
+        self.ordered.insert(record_id, record.clone());
+        shard.insert(record_id, record);
+        true
+    }
+
+    pub fn remove(&self, record_id: u64) -> Option<Arc<RecordHead>> {
+        let mut shard = self.shard(record_id).write();
+        let record = shard.remove(&record_id)?;
+        self.ordered.remove(&record_id);
+        Some(record)
+    }
+
+    /// Returns a point-in-time copy of all entries, shard by shard
+    pub fn entries(&self) -> Vec<(u64, Arc<RecordHead>)> {
+        let mut entries = Vec::with_capacity(self.len());
+        for shard in self.shards.iter() {
+            entries.extend(shard.read().iter().map(|(&id, record)| (id, record.clone())));
+        }
+        entries
+    }
+
+    /// Returns a copy of the entries with ids in `range`, in id order. A
+    /// range that holds no ids, such as `10..5`, yields nothing.
+    pub fn range<R: RangeBounds<u64>>(&self, range: R) -> Vec<(u64, Arc<RecordHead>)> {
+        self.range_iter(range).collect()
+    }
+
+    /// Walks the entries with ids in `range` in id order without copying
+    /// them first. Records added or removed during the walk may or may not
+    /// be seen.
+    pub fn range_iter<R: RangeBounds<u64>>(
+        &self,
+        range: R,
+    ) -> impl DoubleEndedIterator<Item = (u64, Arc<RecordHead>)> + '_ {
+        let bounds = (range.start_bound().cloned(), range.end_bound().cloned());
+        (!is_empty_range(&bounds))
+            .then(|| self.ordered.range(bounds))
+            .into_iter()
+            .flatten()
+            .map(|entry| (*entry.key(), entry.value().clone()))
+    }
+
+    /// Returns the total number of records across all shards
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.read().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.read().is_empty())
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+}
+
+/// Whether no id lies within the bounds of `range`
+fn is_empty_range<R: RangeBounds<u64>>(range: &R) -> bool {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => match start.checked_add(1) {
+            Some(start) => start,
+            None => return true,
+        },
+        Bound::Unbounded => 0,
+    };
+    match range.end_bound() {
+        Bound::Included(&end) => start > end,
+        Bound::Excluded(&end) => start >= end,
+        Bound::Unbounded => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_lookup() {
+        let table = RecordTable::new(6);
+        assert_eq!(table.shard_count(), 8);
+
+        assert!(table.insert(5, Arc::new(RecordHead::new(0))));
+        assert!(!table.insert(5, Arc::new(RecordHead::new(0))));
+        assert!(table.contains(5));
+        assert!(table.get(6).is_none());
+
+        assert!(table.remove(5).is_some());
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn test_sequential_ids_spread_over_shards() {
+        let table = RecordTable::new(16);
+        for id in 0..1024 {
+            table.insert(id, Arc::new(RecordHead::new(0)));
+        }
+        assert_eq!(table.len(), 1024);
+        assert!(table.shards.iter().all(|shard| !shard.read().is_empty()));
+    }
+
+    #[test]
+    fn test_range_is_ordered() {
+        let table = RecordTable::new(8);
+        for id in (0..200).rev() {
+            table.insert(id * 3, Arc::new(RecordHead::new(0)));
+        }
+        let ids: Vec<u64> = table.range(100..=150).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(ids, (34..=50).map(|i| i * 3).collect::<Vec<_>>());
+        assert_eq!(table.range(..).len(), 200);
+        assert!(table.range(1000..).is_empty());
+
+        // Ranges without ids are empty rather than invalid
+        assert!(table.range((Bound::Included(150), Bound::Excluded(100))).is_empty());
+        assert!(table.range((Bound::Excluded(99), Bound::Excluded(99))).is_empty());
+        assert!(table.range((Bound::Excluded(99), Bound::Excluded(100))).is_empty());
+        assert!(table.range((Bound::Excluded(u64::MAX), Bound::Unbounded)).is_empty());
+        assert_eq!(table.range(99..=99).len(), 1);
+
+        assert!(table.remove(99).is_some());
+        assert!(table.range(99..=99).is_empty());
+    }
+
+    #[test]
+    fn test_single_shard() {
+        let table = RecordTable::new(1);
+        table.insert(u64::MAX, Arc::new(RecordHead::new(0)));
+        assert!(table.contains(u64::MAX));
+    }
+
+    #[test]
+    fn test_concurrent_inserts() {
+        let table = Arc::new(RecordTable::new(8));
+        let handles: Vec<_> = (0..4u64)
+            .map(|thread| {
+                let table = table.clone();
+                std::thread::spawn(move || {
+                    for i in 0..500 {
+                        assert!(table.insert(thread * 1000 + i, Arc::new(RecordHead::new(0))));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(table.len(), 2000);
+    }
+}
@@ -1,13 +1,28 @@
 //src/data/version.rs
 use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use super::arena::PayloadArena;
 
 pub struct Version {
     pub(crate) wts: u64,
     pub(crate) rts: AtomicU64,
     pub(crate) status: AtomicU8,
     pub(crate) data: Vec<u8>,
-    pub(crate) next: Option<Box<Version>>,
+    // Marks a deletion; a visible tombstone hides the record
+    pub(crate) tombstone: bool,
+    // `data` is a delta against the next newer version in the chain
+    pub(crate) delta: bool,
+    // `data` holds the value compressed with the table's codec
+    pub(crate) compressed: bool,
+    // The value is the manifest of a blob rather than an ordinary value
+    pub(crate) blob_manifest: bool,
+    // Arena the payload buffer goes back to when the version is dropped
+    pub(crate) arena: Option<Arc<PayloadArena>>,
+    // Deadline after which the value reads as deleted
+    pub(crate) expires_at: Option<Instant>,
+    // Time to live of a staged write, turned into a deadline at commit
+    pub(crate) ttl: Option<Duration>,
 }
 
 impl Version {
@@ -17,10 +32,87 @@ impl Version {
             rts: AtomicU64::new(0),
             status: AtomicU8::new(super::VERSION_STATUS_PENDING),
             data,
-            next: None,
+            tombstone: false,
+            delta: false,
+            compressed: false,
+            blob_manifest: false,
+            arena: None,
+            expires_at: None,
+            ttl: None,
         }
     }
 
+    /// Creates a version recording the deletion of a record
+    pub fn tombstone(wts: u64) -> Self {
+        let mut version = Self::new(wts, Vec::new());
+        version.tombstone = true;
+        version
+    }
+
+    pub fn is_tombstone(&self) -> bool {
+        self.tombstone
+    }
+
+    /// Creates a version that is already committed
+    pub(crate) fn new_committed(wts: u64, data: Vec<u8>) -> Self {
+        let version = Self::new(wts, data);
+        version.status.store(super::VERSION_STATUS_COMMITTED, Ordering::Release);
+        version
+    }
+
+    /// Creates a committed version stored as a delta against its newer neighbour
+    pub(crate) fn delta(wts: u64, delta: Vec<u8>) -> Self {
+        let mut version = Self::new_committed(wts, delta);
+        version.delta = true;
+        version
+    }
+
+    pub fn is_delta(&self) -> bool {
+        self.delta
+    }
+
+    /// Creates a pending version whose data is a compressed value
+    pub(crate) fn compressed(wts: u64, data: Vec<u8>) -> Self {
+        let mut version = Self::new(wts, data);
+        version.compressed = true;
+        version
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    pub fn is_blob_manifest(&self) -> bool {
+        self.blob_manifest
+    }
+
+    /// Creates a committed version carrying this version's timestamp,
+    /// deadline and kind of value with another encoding of its value
+    pub(crate) fn with_data(&self, data: Vec<u8>) -> Self {
+        let mut version = Self::new_committed(self.wts, data);
+        version.expires_at = self.expires_at;
+        version.blob_manifest = self.blob_manifest;
+        version
+    }
+
+    /// Whether the version has a deadline that has passed
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_at(Instant::now())
+    }
+
+    /// Whether the version has a deadline that is not after `now`
+    pub fn is_expired_at(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|deadline| deadline <= now)
+    }
+
+    pub fn expires_at(&self) -> Option<Instant> {
+        self.expires_at
+    }
+
+    pub fn is_committed(&self) -> bool {
+        self.status.load(Ordering::Acquire) == super::VERSION_STATUS_COMMITTED
+    }
+
     pub fn is_visible_to(&self, ts: u64) -> bool {
         let status = self.status.load(Ordering::Acquire);
         
@@ -71,6 +163,8 @@ impl Version {
     }
 }
 
+// Cloning deep-copies the payload. Version chains share versions through
+// `Arc` instead, so the read path never clones.
 impl Clone for Version {
     fn clone(&self) -> Self {
         Self {
@@ -78,7 +172,21 @@ impl Clone for Version {
             rts: AtomicU64::new(self.rts.load(Ordering::Relaxed)),
             status: AtomicU8::new(self.status.load(Ordering::Relaxed)),
             data: self.data.clone(),
-            next: self.next.clone(),
+            tombstone: self.tombstone,
+            delta: self.delta,
+            compressed: self.compressed,
+            blob_manifest: self.blob_manifest,
+            arena: None,
+            expires_at: self.expires_at,
+            ttl: self.ttl,
+        }
+    }
+}
+
+impl Drop for Version {
+    fn drop(&mut self) {
+        if let Some(arena) = self.arena.take() {
+            arena.recycle(std::mem::take(&mut self.data));
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,103 @@
+// src/document/mod.rs
+//
+// JSON documents stored as record values. Documents are kept as JSON text,
+// so they stay readable by other tools, and parsed when a transaction or an
+// index needs to look inside them.
+use serde_json::Value as JsonValue;
+use crate::error::{MaemioError, Result};
+use crate::index::{value_to_key, IndexKey};
+use crate::schema::Value;
+
+mod path;
+
+pub use path::JsonPath;
+
+/// A JSON document held in a record
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Document(JsonValue);
+
+impl Document {
+    pub fn new(value: JsonValue) -> Self {
+        Self(value)
+    }
+
+    /// Parses a record's bytes; fails with `MaemioError::Decode` if they are
+    /// not JSON
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        serde_json::from_slice(data)
+            .map(Self)
+            .map_err(|err| MaemioError::Decode(format!("Invalid JSON document: {}", err)))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.to_string().into_bytes()
+    }
+
+    pub fn value(&self) -> &JsonValue {
+        &self.0
+    }
+
+    pub fn into_value(self) -> JsonValue {
+        self.0
+    }
+
+    /// The value at `path`, if the document has one
+    pub fn get(&self, path: &str) -> Result<Option<&JsonValue>> {
+        Ok(JsonPath::parse(path)?.get(&self.0))
+    }
+
+    /// Puts `value` at `path`, creating missing object members on the way
+    pub fn set(&mut self, path: &str, value: JsonValue) -> Result<()> {
+        JsonPath::parse(path)?.set(&mut self.0, value)
+    }
+
+    /// Removes the value at `path` and returns it
+    pub fn remove(&mut self, path: &str) -> Result<Option<JsonValue>> {
+        JsonPath::parse(path)?.remove(&mut self.0)
+    }
+}
+
+impl From<JsonValue> for Document {
+    fn from(value: JsonValue) -> Self {
+        Self(value)
+    }
+}
+
+/// Converts a JSON scalar into an index key that sorts like the scalar.
+/// Numbers with an integral value share a key however they are written, so
+/// `1` and `1.0` match. Null, arrays and objects are not indexed.
+pub(crate) fn json_to_key(value: &JsonValue) -> Option<IndexKey> {
+    match value {
+        JsonValue::Bool(v) => value_to_key(&Value::Bool(*v)),
+        JsonValue::Number(n) => match n.as_i64() {
+            Some(v) => value_to_key(&Value::Int(v)),
+            None => {
+                let v = n.as_f64()?;
+                if v.fract() == 0.0 && v >= i64::MIN as f64 && v < i64::MAX as f64 {
+                    value_to_key(&Value::Int(v as i64))
+                } else {
+                    value_to_key(&Value::Float(v))
+                }
+            }
+        },
+        JsonValue::String(s) => Some(IndexKey::String(s.clone())),
+        JsonValue::Null | JsonValue::Array(_) | JsonValue::Object(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_json_keys() {
+        assert_eq!(json_to_key(&json!(1)), json_to_key(&json!(1.0)));
+        assert_eq!(json_to_key(&json!(-3)), json_to_key(&json!(-3.0)));
+        assert_ne!(json_to_key(&json!(1)), json_to_key(&json!(1.5)));
+        assert_eq!(json_to_key(&json!(true)), Some(IndexKey::Bool(true)));
+        assert_ne!(json_to_key(&json!(true)), json_to_key(&json!(1)));
+        assert_ne!(json_to_key(&json!(false)), json_to_key(&json!(0)));
+        assert_eq!(json_to_key(&json!(null)), None);
+    }
+}
@@ -0,0 +1,168 @@
+// src/document/path.rs
+//
+// Paths into JSON documents in the common `$.a.b[0]["c d"]` notation: `$` is
+// the whole document, `.name` and `["name"]` select an object member and
+// `[n]` selects an array element.
+use std::fmt;
+use std::str::FromStr;
+use serde_json::{Map, Value as JsonValue};
+use crate::error::{MaemioError, Result};
+
+/// One step of a path
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// A parsed path into a JSON document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonPath {
+    text: String,
+    segments: Vec<Segment>,
+}
+
+impl JsonPath {
+    pub fn parse(text: &str) -> Result<Self> {
+        let invalid = |reason: &str| MaemioError::InvalidPath(format!("{}: {}", text, reason));
+        let mut rest = text.strip_prefix('$').ok_or_else(|| invalid("must start with $"))?;
+        let mut segments = Vec::new();
+        while let Some(c) = rest.chars().next() {
+            match c {
+                '.' => {
+                    let end = rest[1..].find(['.', '[']).map_or(rest.len(), |i| i + 1);
+                    let name = &rest[1..end];
+                    if name.is_empty() {
+                        return Err(invalid("empty member name"));
+                    }
+                    segments.push(Segment::Key(name.to_string()));
+                    rest = &rest[end..];
+                }
+                '[' => {
+                    let end = rest.find(']').ok_or_else(|| invalid("unclosed ["))?;
+                    let inner = &rest[1..end];
+                    let quoted = inner.len() >= 2
+                        && (inner.starts_with('"') && inner.ends_with('"')
+                            || inner.starts_with('\'') && inner.ends_with('\''));
+                    if quoted {
+                        segments.push(Segment::Key(inner[1..inner.len() - 1].to_string()));
+                    } else {
+                        let index = inner.parse().map_err(|_| invalid("expected an array index or quoted name"))?;
+                        segments.push(Segment::Index(index));
+                    }
+                    rest = &rest[end + 1..];
+                }
+                _ => return Err(invalid("expected . or [")),
+            }
+        }
+        Ok(Self { text: text.to_string(), segments })
+    }
+
+    /// The value at this path, if the document has one
+    pub fn get<'a>(&self, document: &'a JsonValue) -> Option<&'a JsonValue> {
+        self.segments.iter().try_fold(document, |value, segment| match segment {
+            Segment::Key(key) => value.get(key),
+            Segment::Index(index) => value.get(*index),
+        })
+    }
+
+    /// Puts `new_value` at this path. Missing object members along the way
+    /// are created; array indexes may address an element or the end of the
+    /// array, which appends.
+    pub fn set(&self, document: &mut JsonValue, new_value: JsonValue) -> Result<()> {
+        let mut value = document;
+        for segment in &self.segments {
+            value = match segment {
+                Segment::Key(key) => {
+                    if value.is_null() {
+                        *value = JsonValue::Object(Map::new());
+                    }
+                    let object = value.as_object_mut().ok_or_else(|| self.mismatch("an object"))?;
+                    object.entry(key.as_str()).or_insert(JsonValue::Null)
+                }
+                Segment::Index(index) => {
+                    let array = value.as_array_mut().ok_or_else(|| self.mismatch("an array"))?;
+                    if *index == array.len() {
+                        array.push(JsonValue::Null);
+                    }
+                    array.get_mut(*index).ok_or_else(|| self.mismatch("an existing array element"))?
+                }
+            };
+        }
+        *value = new_value;
+        Ok(())
+    }
+
+    /// Removes the value at this path and returns it
+    pub fn remove(&self, document: &mut JsonValue) -> Result<Option<JsonValue>> {
+        let Some((last, parents)) = self.segments.split_last() else {
+            return Err(MaemioError::InvalidPath("$: the document root cannot be removed".into()));
+        };
+        let mut value = document;
+        for segment in parents {
+            let child = match segment {
+                Segment::Key(key) => value.get_mut(key),
+                Segment::Index(index) => value.get_mut(*index),
+            };
+            let Some(child) = child else {
+                return Ok(None);
+            };
+            value = child;
+        }
+        Ok(match (last, value) {
+            (Segment::Key(key), JsonValue::Object(object)) => object.remove(key),
+            (Segment::Index(index), JsonValue::Array(array)) if *index < array.len() => Some(array.remove(*index)),
+            _ => None,
+        })
+    }
+
+    fn mismatch(&self, expected: &str) -> MaemioError {
+        MaemioError::InvalidPath(format!("{}: does not lead through {}", self.text, expected))
+    }
+}
+
+impl FromStr for JsonPath {
+    type Err = MaemioError;
+
+    fn from_str(text: &str) -> Result<Self> {
+        Self::parse(text)
+    }
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_paths() {
+        let mut document = json!({"user": {"email": "a@b.c", "tags": ["x", "y"]}, "odd key": 1});
+        let path = |text: &str| JsonPath::parse(text).unwrap();
+        assert_eq!(path("$.user.email").get(&document), Some(&json!("a@b.c")));
+        assert_eq!(path("$.user.tags[1]").get(&document), Some(&json!("y")));
+        assert_eq!(path("$['odd key']").get(&document), Some(&json!(1)));
+        assert_eq!(path("$").get(&document), Some(&document.clone()));
+        assert_eq!(path("$.user.missing.deeper").get(&document), None);
+
+        path("$.user.address.city").set(&mut document, json!("Oslo")).unwrap();
+        path("$.user.tags[2]").set(&mut document, json!("z")).unwrap();
+        assert_eq!(document["user"]["address"], json!({"city": "Oslo"}));
+        assert_eq!(document["user"]["tags"], json!(["x", "y", "z"]));
+        assert!(matches!(path("$.user.tags[5]").set(&mut document, json!(0)), Err(MaemioError::InvalidPath(_))));
+        assert!(matches!(path("$.user.email.local").set(&mut document, json!(0)), Err(MaemioError::InvalidPath(_))));
+
+        assert_eq!(path("$.user.tags[0]").remove(&mut document).unwrap(), Some(json!("x")));
+        assert_eq!(path("$.nothing.here").remove(&mut document).unwrap(), None);
+        assert!(matches!(path("$").remove(&mut document), Err(MaemioError::InvalidPath(_))));
+
+        for bad in ["user", "$.", "$[x]", "$[1", "$..a"] {
+            assert!(matches!(JsonPath::parse(bad), Err(MaemioError::InvalidPath(_))), "{}", bad);
+        }
+    }
+}
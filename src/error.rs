@@ -25,7 +25,28 @@ pub enum MaemioError {
 
     #[error("Version installation failed")]
     VersionInstallationFailed,
-    
+
+    #[error("Sequence not found: {0}")]
+    SequenceNotFound(String),
+
+    #[error("Schema violation: {0}")]
+    SchemaViolation(String),
+
+    #[error("Unique constraint violated: {0}")]
+    UniqueViolation(String),
+
+    #[error("Constraint violated: {0}")]
+    ConstraintViolation(String),
+
+    #[error("Memory quota exceeded: {0}")]
+    QuotaExceeded(String),
+
+    #[error("Decode failed: {0}")]
+    Decode(String),
+
+    #[error("Invalid JSON path: {0}")]
+    InvalidPath(String),
+
 }
 // Implementation to convert unit error () into MaemioError
 impl From<()> for MaemioError {
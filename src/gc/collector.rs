@@ -1,14 +1,27 @@
 // src/gc/collector.rs
 use std::sync::Arc;
 use std::collections::VecDeque;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use parking_lot::Mutex;
 use crate::error::Result;
 use crate::clock::ClockManager;
-use crate::data::{Version, RecordHead};
+use crate::data::RecordHead;
+use crate::transaction::TransactionManager;
 
+/// Reclaims versions that no transaction can read anymore.
+///
+/// Committing transactions queue the records they wrote; each collection
+/// cycle unlinks the unreachable versions of queued records. Payload buffers
+/// of those versions go back to the arena of the thread that wrote them once
+/// the epoch collector frees them.
+///
+/// With expiry enabled, the collector also deletes records whose time to
+/// live has passed, through transactions on a clock of its own, and removes
+/// deleted records nothing refers to anymore, such as dropped blob chunks,
+/// from their tables.
 pub struct GarbageCollector {
     queue: Mutex<VecDeque<(Arc<RecordHead>, u64)>>,
+    transactions: Option<Arc<TransactionManager>>,
     clock_manager: Arc<ClockManager>,
     gc_interval: Duration,
 }
@@ -17,76 +30,119 @@ impl GarbageCollector {
     pub fn new(clock_manager: Arc<ClockManager>, gc_interval_micros: u64) -> Self {
         Self {
             queue: Mutex::new(VecDeque::new()),
+            transactions: None,
             clock_manager,
             gc_interval: Duration::from_micros(gc_interval_micros),
         }
     }
 
+    /// Lets the collector delete expired records using transactions from
+    /// `transactions`, and prune the removed index entries of their tables
+    pub fn with_expiry(mut self, transactions: Arc<TransactionManager>) -> Self {
+        self.transactions = Some(transactions);
+        self
+    }
+
+    /// Deletes records whose deadline has passed and that were not written
+    /// again since. Returns how many records were deleted.
+    pub fn sweep_expired(&self) -> Result<usize> {
+        let Some(transactions) = &self.transactions else {
+            return Ok(0);
+        };
+        // Moves the idle sweeper clock past every committed write, so its
+        // transactions see the expiring values
+        self.clock_manager.update_min_timestamps();
+        let now = Instant::now();
+        let due: Vec<_> = transactions
+            .tables()
+            .tables()
+            .into_iter()
+            .flat_map(|table| {
+                let table_id = table.id();
+                table.take_due_expiries(now).into_iter().map(move |record_id| (table_id, record_id))
+            })
+            .collect();
+
+        // The sweeper has a clock of its own, so its transactions never share
+        // a worker's clock with that worker's transactions.
+        let thread_id = self.clock_manager.system_thread_id();
+        let mut deleted = 0;
+        for (table_id, record_id) in due {
+            // Dropped tables and missing records have nothing left to expire.
+            match transactions.execute_with_gc(thread_id, self, |tx| tx.delete_if_expired(table_id, record_id)) {
+                Ok(true) => deleted += 1,
+                Ok(false) => {}
+                Err(err) => tracing::debug!("Expiry of record {} in table {} skipped: {}", record_id, table_id, err),
+            }
+        }
+        Ok(deleted)
+    }
+
     pub fn track_version(&self, record: Arc<RecordHead>, wts: u64) {
         let mut queue = self.queue.lock();
         queue.push_back((record, wts));
     }
 
     pub fn collect_garbage(&self) -> Result<()> {
+        self.clock_manager.update_min_timestamps();
         let min_rts = self.clock_manager.get_min_read_ts();
+        // Removed index entries and dead records no snapshot can look for
+        // anymore
+        if let Some(transactions) = &self.transactions {
+            for table in transactions.tables().tables() {
+                for index in table.indexes() {
+                    index.prune_removed(min_rts);
+                }
+                let removed = table.reclaim_dead(min_rts);
+                if removed > 0 {
+                    tracing::debug!("Removed {} dead records from table {}", removed, table.name());
+                }
+            }
+        }
         let mut queue = self.queue.lock();
 
         let mut remaining = VecDeque::new();
         while let Some((record, wts)) = queue.pop_front() {
-            if wts >= min_rts {
-                remaining.push_back((record.clone(), wts));
-                continue;
+            // Records another collection is busy with are retried next cycle
+            if wts >= min_rts || !self.collect_record_versions(&record, min_rts) {
+                remaining.push_back((record, wts));
             }
-
-            if !record.try_gc_lock() {
-                remaining.push_back((record.clone(), wts));
-                continue;
-            }
-
-            self.collect_record_versions(&record, min_rts);
         }
 
         *queue = remaining;
+        drop(queue);
+
+        // Hand unlinked versions to the epoch collector right away instead of
+        // waiting for this thread's local buffer to fill up.
+        crossbeam_epoch::pin().flush();
         Ok(())
     }
 
-    fn collect_record_versions(&self, record: &RecordHead, min_rts: u64) {
+    /// Returns false if the record could not be collected right now
+    fn collect_record_versions(&self, record: &RecordHead, min_rts: u64) -> bool {
         record.update_min_wts(min_rts);
 
-        // Build new chain from old versions
-        let mut versions = Vec::new();
-        let mut current = record.get_current_version();
-
-        // First collect all versions we want to keep
-        while let Some(version) = current {
-            if version.wts >= min_rts {
-                versions.push(Version::new(
-                    version.wts,
-                    version.data.clone()
-                ));
+        // Unlink versions no reader can see; the epoch collector frees them
+        // once concurrent readers have moved on.
+        match record.collect_garbage(min_rts) {
+            Some(reclaimed) => {
+                if reclaimed > 0 {
+                    tracing::debug!("Reclaimed {} versions older than {}", reclaimed, min_rts);
+                }
+                true
             }
-            current = version.next;
-        }
-
-        // Then rebuild the chain in reverse order
-        let mut new_chain = None;
-        for version in versions.into_iter().rev() {
-            let mut boxed_version = Box::new(version);
-            boxed_version.next = new_chain;
-            new_chain = Some(boxed_version);
-        }
-
-        // Install the new chain if we have one
-        if let Some(chain) = new_chain {
-            let _ = record.install_version(*chain);
+            None => false,
         }
     }
 
-    pub fn start_collection(&self) -> std::thread::JoinHandle<()> {
+    /// Runs collection cycles on a background thread that shares this
+    /// collector, and so the records queued through it
+    pub fn start_collection(self: &Arc<Self>) -> std::thread::JoinHandle<()> {
         let gc = self.clone();
         std::thread::spawn(move || {
             loop {
                 let _ = gc.collect_garbage();
+                let _ = gc.sweep_expired();
                 std::thread::sleep(gc.gc_interval);
             }
         })
@@ -97,6 +153,7 @@ impl Clone for GarbageCollector {
     fn clone(&self) -> Self {
         Self {
             queue: Mutex::new(VecDeque::new()),
+            transactions: self.transactions.clone(),
             clock_manager: self.clock_manager.clone(),
             gc_interval: self.gc_interval,
         }
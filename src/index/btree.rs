@@ -1,11 +1,22 @@
 // src/index/btree.rs
+use std::collections::VecDeque;
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use parking_lot::RwLock;
 use super::{Index, IndexKey, IndexNode, MIN_DEGREE};
 use crate::error::{MaemioError, Result};
 
+const MAX_KEYS: usize = 2 * MIN_DEGREE - 1;
+
+/// Entries a cursor fetches each time it takes the root lock
+const CURSOR_BATCH: usize = 64;
+
+/// One entry of the tree, ordered like the tree orders them
+type Entry = (IndexKey, u64);
+
 struct BTreeNode {
-    // Multi-version node metadata
+    // Multi-version node metadata; `records[i]` belongs to `keys[i]`
     mv_node: Arc<IndexNode>,
     // Keys in sorted order
     keys: RwLock<Vec<IndexKey>>,
@@ -15,15 +26,34 @@ struct BTreeNode {
     is_leaf: bool,
 }
 
+/// B-tree index that allows several records per key.
+///
+/// Entries are ordered by `(key, record_id)`, so duplicate keys sit next to
+/// each other and a single entry can be removed exactly. Inserts split full
+/// nodes and removals borrow from or merge with siblings on the way down,
+/// so every node but the root stays at least half full.
+/// A unique tree rejects a second record for a key that is already taken.
+///
+/// Writers hold the root lock exclusively for the whole insert or removal.
+/// Both restructure nodes top-down in a single pass and may split, borrow
+/// or merge at any level, so latching only the affected subtree would have
+/// to lock every node on the path anyway. Index writes run during commit
+/// after the record locks are taken, so they are short and far less
+/// frequent than reads, which share the lock.
 pub struct BTreeIndex {
     root: RwLock<Arc<BTreeNode>>,
+    unique: bool,
+    // Sum of the entry sizes of all stored entries
+    entry_bytes: AtomicUsize,
+    // Bumped by every write, so cursors know when their position is stale
+    version: AtomicU64,
 }
 
 impl BTreeNode {
     fn new(is_leaf: bool) -> Self {
         Self {
             mv_node: Arc::new(IndexNode::new()),
-            keys: RwLock::new(Vec::with_capacity(2 * MIN_DEGREE - 1)),
+            keys: RwLock::new(Vec::with_capacity(MAX_KEYS)),
             children: RwLock::new(if is_leaf {
                 Vec::new()
             } else {
@@ -33,185 +63,629 @@ impl BTreeNode {
         }
     }
 
+    fn len(&self) -> usize {
+        self.keys.read().len()
+    }
+
+    /// Position of the first entry not smaller than `(key, record_id)`
+    fn lower_bound(&self, key: &IndexKey, record_id: u64) -> usize {
+        self.partition_point(|entry| entry < (key, record_id))
+    }
+
+    /// Number of leading entries that satisfy `pred`, which must hold for a
+    /// prefix of the node's entries; found by binary search
+    fn partition_point<P>(&self, pred: P) -> usize
+    where
+        P: Fn((&IndexKey, u64)) -> bool,
+    {
+        let keys = self.keys.read();
+        let records = self.mv_node.records.read();
+        let (mut low, mut high) = (0, keys.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if pred((&keys[mid], records[mid])) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    }
+
+    fn entry(&self, idx: usize) -> Entry {
+        (self.keys.read()[idx].clone(), self.mv_node.records.read()[idx])
+    }
+
+    fn child(&self, idx: usize) -> Arc<BTreeNode> {
+        self.children.read()[idx].clone()
+    }
+
     fn split_child(&self, child_idx: usize, ts: u64) -> Result<()> {
-        let children = self.children.read();
-        let child = &children[child_idx];
-        
-        // Create new node
-        let mut new_node = BTreeNode::new(child.is_leaf);
+        let child = self.children.read()[child_idx].clone();
+        let new_node = BTreeNode::new(child.is_leaf);
         let mid = MIN_DEGREE - 1;
-        
-        // Copy keys and children
-        {
-            let child_keys = child.keys.read();
-            let mut new_keys = new_node.keys.write();
-            new_keys.extend_from_slice(&child_keys[mid + 1..]);
-        }
-        
+
+        // Move the upper half of the child's entries into the new node
+        let (median_key, median_record) = {
+            let mut child_keys = child.keys.write();
+            let mut child_records = child.mv_node.records.write();
+            new_node.keys.write().extend(child_keys.drain(mid + 1..));
+            new_node.mv_node.records.write().extend(child_records.drain(mid + 1..));
+            (child_keys.pop().unwrap(), child_records.pop().unwrap())
+        };
+
         if !child.is_leaf {
-            let child_children = child.children.read();
-            let mut new_children = new_node.children.write();
-            new_children.extend_from_slice(&child_children[mid + 1..]);
+            let mut child_children = child.children.write();
+            new_node.children.write().extend(child_children.drain(mid + 1..));
         }
-        
-        // Update parent
-        {
-            let child_keys = child.keys.read();
-            let mut parent_keys = self.keys.write();
-            let mut parent_children = self.children.write();
-            
-            parent_keys.insert(child_idx, child_keys[mid].clone());
-            parent_children.insert(child_idx + 1, Arc::new(new_node));
-        }
-        
+
+        // Lift the median entry into this node
+        self.keys.write().insert(child_idx, median_key);
+        self.mv_node.records.write().insert(child_idx, median_record);
+        self.children.write().insert(child_idx + 1, Arc::new(new_node));
+
         // Update timestamps
         self.mv_node.wts.store(ts, std::sync::atomic::Ordering::Release);
         child.mv_node.wts.store(ts, std::sync::atomic::Ordering::Release);
-        
+        self.children.read()[child_idx + 1]
+            .mv_node.wts.store(ts, std::sync::atomic::Ordering::Release);
+
         Ok(())
     }
+
+    /// Largest entry in this subtree
+    fn max_entry(&self) -> Option<Entry> {
+        if !self.is_leaf {
+            let last_child = self.children.read().last().cloned();
+            return last_child.and_then(|child| child.max_entry());
+        }
+        let keys = self.keys.read();
+        let records = self.mv_node.records.read();
+        Some((keys.last()?.clone(), *records.last()?))
+    }
+
+    /// Smallest entry in this subtree
+    fn min_entry(&self) -> Option<Entry> {
+        if !self.is_leaf {
+            let first_child = self.children.read().first().cloned();
+            return first_child.and_then(|child| child.min_entry());
+        }
+        let keys = self.keys.read();
+        let records = self.mv_node.records.read();
+        Some((keys.first()?.clone(), *records.first()?))
+    }
+
+    /// Replaces entry `idx` of this node
+    fn set_entry(&self, idx: usize, (key, record_id): Entry) {
+        self.keys.write()[idx] = key;
+        self.mv_node.records.write()[idx] = record_id;
+    }
+
+    /// Folds separator `idx` and child `idx + 1` into child `idx`
+    fn merge_children(&self, idx: usize, ts: u64) {
+        let (left, right) = {
+            let mut children = self.children.write();
+            (children[idx].clone(), children.remove(idx + 1))
+        };
+        let key = self.keys.write().remove(idx);
+        let record_id = self.mv_node.records.write().remove(idx);
+
+        left.keys.write().push(key);
+        left.mv_node.records.write().push(record_id);
+        left.keys.write().append(&mut right.keys.write());
+        left.mv_node.records.write().append(&mut right.mv_node.records.write());
+        if !left.is_leaf {
+            left.children.write().append(&mut right.children.write());
+        }
+        self.mv_node.wts.store(ts, Ordering::Release);
+        left.mv_node.wts.store(ts, Ordering::Release);
+    }
+
+    /// Gives child `idx` at least `MIN_DEGREE` entries, so removing one from
+    /// it cannot leave it underfull. Borrows an entry through the separator
+    /// from a sibling that can spare one, or else merges with a sibling.
+    fn fill_child(&self, idx: usize, ts: u64) {
+        let children = self.children.read().clone();
+        let child = &children[idx];
+
+        if idx > 0 && children[idx - 1].len() >= MIN_DEGREE {
+            let left = &children[idx - 1];
+            let borrowed_key = left.keys.write().pop().unwrap();
+            let borrowed_record = left.mv_node.records.write().pop().unwrap();
+            let separator_key = std::mem::replace(&mut self.keys.write()[idx - 1], borrowed_key);
+            let separator_record = std::mem::replace(&mut self.mv_node.records.write()[idx - 1], borrowed_record);
+            child.keys.write().insert(0, separator_key);
+            child.mv_node.records.write().insert(0, separator_record);
+            if !child.is_leaf {
+                let moved = left.children.write().pop().unwrap();
+                child.children.write().insert(0, moved);
+            }
+            left.mv_node.wts.store(ts, Ordering::Release);
+        } else if idx + 1 < children.len() && children[idx + 1].len() >= MIN_DEGREE {
+            let right = &children[idx + 1];
+            let borrowed_key = right.keys.write().remove(0);
+            let borrowed_record = right.mv_node.records.write().remove(0);
+            let separator_key = std::mem::replace(&mut self.keys.write()[idx], borrowed_key);
+            let separator_record = std::mem::replace(&mut self.mv_node.records.write()[idx], borrowed_record);
+            child.keys.write().push(separator_key);
+            child.mv_node.records.write().push(separator_record);
+            if !child.is_leaf {
+                let moved = right.children.write().remove(0);
+                child.children.write().push(moved);
+            }
+            right.mv_node.wts.store(ts, Ordering::Release);
+        } else if idx + 1 < children.len() {
+            self.merge_children(idx, ts);
+            return;
+        } else {
+            self.merge_children(idx - 1, ts);
+            return;
+        }
+        self.mv_node.wts.store(ts, Ordering::Release);
+        child.mv_node.wts.store(ts, Ordering::Release);
+    }
 }
 
 impl BTreeIndex {
     pub fn new() -> Self {
         Self {
             root: RwLock::new(Arc::new(BTreeNode::new(true))),
+            unique: false,
+            entry_bytes: AtomicUsize::new(0),
+            version: AtomicU64::new(0),
+        }
+    }
+
+    /// Creates a tree that allows at most one record per key
+    pub fn unique() -> Self {
+        Self {
+            unique: true,
+            ..Self::new()
+        }
+    }
+}
+
+impl BTreeIndex {
+    /// Iterates lazily over the entries with keys in `range`, in key order;
+    /// `.rev()` walks them from the back.
+    ///
+    /// The cursor holds no locks between calls. It fetches a small batch of
+    /// entries at a time and keeps its place in the tree between batches. If
+    /// the tree was written to in the meantime, it finds its place again from
+    /// the root, so writers are never blocked by a long scan and entries
+    /// inserted ahead of the cursor are seen.
+    pub fn cursor<R: RangeBounds<IndexKey>>(&self, range: R) -> BTreeCursor<'_> {
+        let low = match range.start_bound() {
+            Bound::Included(key) => Bound::Included((key.clone(), 0)),
+            Bound::Excluded(key) => Bound::Excluded((key.clone(), u64::MAX)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let high = match range.end_bound() {
+            Bound::Included(key) => Bound::Included((key.clone(), u64::MAX)),
+            Bound::Excluded(key) => Bound::Excluded((key.clone(), 0)),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        BTreeCursor {
+            index: self,
+            start: low.clone(),
+            end: high.clone(),
+            low,
+            high,
+            front: VecDeque::new(),
+            back: VecDeque::new(),
+            front_path: None,
+            back_path: None,
+        }
+    }
+}
+
+/// Where a cursor stands in the tree: the nodes from the root down to a
+/// leaf, each with the position of the next entry to return. Walking
+/// forwards that is entry `i`, after child `i`; walking backwards it is
+/// entry `i - 1`, after child `i`.
+struct Path {
+    nodes: Vec<(Arc<BTreeNode>, usize)>,
+    // Version of the tree the path was taken in
+    version: u64,
+}
+
+impl Path {
+    /// Descends to the first entry above `low`
+    fn seek_front(root: &Arc<BTreeNode>, low: &Bound<Entry>, version: u64) -> Self {
+        let mut path = Self { nodes: Vec::new(), version };
+        path.descend(root.clone(), |node| node.partition_point(|entry| !above_low(entry, low)));
+        path
+    }
+
+    /// Descends to the last entry below `high`
+    fn seek_back(root: &Arc<BTreeNode>, high: &Bound<Entry>, version: u64) -> Self {
+        let mut path = Self { nodes: Vec::new(), version };
+        path.descend(root.clone(), |node| node.partition_point(|entry| below_high(entry, high)));
+        path
+    }
+
+    /// Pushes `node` and the nodes down to a leaf, taking the child at the
+    /// position `position` picks in each
+    fn descend<F>(&mut self, mut node: Arc<BTreeNode>, position: F)
+    where
+        F: Fn(&BTreeNode) -> usize,
+    {
+        loop {
+            let i = position(&node);
+            let child = (!node.is_leaf).then(|| node.child(i));
+            self.nodes.push((node, i));
+            match child {
+                Some(child) => node = child,
+                None => return,
+            }
+        }
+    }
+
+    fn next(&mut self) -> Option<Entry> {
+        loop {
+            let (node, i) = self.nodes.last_mut()?;
+            if *i == node.len() {
+                self.nodes.pop();
+                continue;
+            }
+            let entry = node.entry(*i);
+            *i += 1;
+            if !node.is_leaf {
+                let child = node.child(*i);
+                self.descend(child, |_| 0);
+            }
+            return Some(entry);
+        }
+    }
+
+    fn next_back(&mut self) -> Option<Entry> {
+        loop {
+            let (node, i) = self.nodes.last_mut()?;
+            if *i == 0 {
+                self.nodes.pop();
+                continue;
+            }
+            *i -= 1;
+            let entry = node.entry(*i);
+            if !node.is_leaf {
+                let child = node.child(*i);
+                self.descend(child, BTreeNode::len);
+            }
+            return Some(entry);
         }
     }
 }
 
+fn above_low((key, record_id): (&IndexKey, u64), low: &Bound<Entry>) -> bool {
+    match low {
+        Bound::Included((k, r)) => (key, record_id) >= (k, *r),
+        Bound::Excluded((k, r)) => (key, record_id) > (k, *r),
+        Bound::Unbounded => true,
+    }
+}
+
+fn below_high((key, record_id): (&IndexKey, u64), high: &Bound<Entry>) -> bool {
+    match high {
+        Bound::Included((k, r)) => (key, record_id) <= (k, *r),
+        Bound::Excluded((k, r)) => (key, record_id) < (k, *r),
+        Bound::Unbounded => true,
+    }
+}
+
+/// Lazy iterator over a key range of a `BTreeIndex`; created by
+/// `BTreeIndex::cursor`. Yields `(key, record_id)` pairs.
+pub struct BTreeCursor<'a> {
+    index: &'a BTreeIndex,
+    // The range the cursor was created with
+    start: Bound<Entry>,
+    end: Bound<Entry>,
+    // What is left of the range after the entries returned from either end
+    low: Bound<Entry>,
+    high: Bound<Entry>,
+    // Entries fetched but not yet returned
+    front: VecDeque<Entry>,
+    back: VecDeque<Entry>,
+    // Where each end's next batch starts, unless the tree changed since
+    front_path: Option<Path>,
+    back_path: Option<Path>,
+}
+
+impl BTreeCursor<'_> {
+    /// Moves the front of the cursor to the first entry with a key not
+    /// smaller than `key`, within the cursor's range. The cursor may move
+    /// backwards as well as forwards.
+    pub fn seek(&mut self, key: &IndexKey) {
+        self.front.clear();
+        self.front_path = None;
+        // The back skips entries past its bound, which may have grown now
+        self.back_path = None;
+        self.low = if above_low((key, 0), &self.start) {
+            Bound::Included((key.clone(), 0))
+        } else {
+            self.start.clone()
+        };
+    }
+
+    /// Moves the back of the cursor to the last entry with a key not larger
+    /// than `key`, within the cursor's range
+    pub fn seek_back(&mut self, key: &IndexKey) {
+        self.back.clear();
+        self.back_path = None;
+        self.front_path = None;
+        self.high = if below_high((key, u64::MAX), &self.end) {
+            Bound::Included((key.clone(), u64::MAX))
+        } else {
+            self.end.clone()
+        };
+    }
+
+    /// Fetches the next batch of entries for one end. Writers are held off
+    /// only while the batch is read.
+    fn fetch(&mut self, reverse: bool) -> VecDeque<Entry> {
+        let mut batch = VecDeque::with_capacity(CURSOR_BATCH);
+        let root = self.index.root.read();
+        let version = self.index.version.load(Ordering::Acquire);
+        let (low, high) = (&self.low, &self.high);
+        let path = if reverse { &mut self.back_path } else { &mut self.front_path };
+        let path = match path {
+            Some(path) if path.version == version => path,
+            _ if reverse => path.insert(Path::seek_back(&root, high, version)),
+            _ => path.insert(Path::seek_front(&root, low, version)),
+        };
+        while batch.len() < CURSOR_BATCH {
+            let entry = if reverse { path.next_back() } else { path.next() };
+            let Some((key, record_id)) = entry else {
+                break;
+            };
+            let within = if reverse {
+                above_low((&key, record_id), low)
+            } else {
+                below_high((&key, record_id), high)
+            };
+            if !within {
+                break;
+            }
+            batch.push_back((key, record_id));
+        }
+        batch
+    }
+}
+
+impl Iterator for BTreeCursor<'_> {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.front.is_empty() {
+            self.front = self.fetch(false);
+        }
+        let (key, record_id) = self.front.pop_front()?;
+        // The back may have returned this entry since it was fetched
+        if !below_high((&key, record_id), &self.high) {
+            self.front.clear();
+            return None;
+        }
+        self.low = Bound::Excluded((key.clone(), record_id));
+        Some((key, record_id))
+    }
+}
+
+impl DoubleEndedIterator for BTreeCursor<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.back.is_empty() {
+            self.back = self.fetch(true);
+        }
+        let (key, record_id) = self.back.pop_front()?;
+        if !above_low((&key, record_id), &self.low) {
+            self.back.clear();
+            return None;
+        }
+        self.high = Bound::Excluded((key.clone(), record_id));
+        Some((key, record_id))
+    }
+}
+
+impl Default for BTreeIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Index for BTreeIndex {
     fn insert(&self, key: IndexKey, record_id: u64, ts: u64) -> Result<()> {
-        let root = self.root.read();
-        let mut current = root.clone();
-        
+        let mut root = self.root.write();
+        self.version.fetch_add(1, Ordering::Release);
+
+        if self.unique {
+            let mut existing = Vec::new();
+            self.range_scan_internal(&root, &key, &key, ts, &mut existing)?;
+            if let Some(&other) = existing.first() {
+                if other == record_id {
+                    return Ok(());
+                }
+                return Err(MaemioError::UniqueViolation(format!(
+                    "key {:?} already maps to record {}", key, other
+                )));
+            }
+        }
+
         // Split root if full
-        if current.keys.read().len() == 2 * MIN_DEGREE - 1 {
-            let mut new_root = BTreeNode::new(false);
-            new_root.children.write().push(current.clone());
+        if root.len() == MAX_KEYS {
+            let new_root = BTreeNode::new(false);
+            new_root.children.write().push(root.clone());
             new_root.split_child(0, ts)?;
-            *self.root.write() = Arc::new(new_root);
-            current = self.root.read().clone();
+            *root = Arc::new(new_root);
         }
-        
+
         // Insert non-full
-        self.insert_non_full(current, key, record_id, ts)
+        let entry_size = key.entry_size();
+        self.insert_non_full(root.clone(), key, record_id, ts)?;
+        self.entry_bytes.fetch_add(entry_size, Ordering::Relaxed);
+        Ok(())
     }
-    
+
     fn remove(&self, key: &IndexKey, ts: u64) -> Result<()> {
-        let root = self.root.read().clone();
-        self.remove_key(root, key, ts)
+        let record_id = self.get(key, ts)?
+            .ok_or(MaemioError::RecordNotFound(0))?;
+        self.remove_entry(key, record_id, ts)
     }
-    
+
+    fn remove_entry(&self, key: &IndexKey, record_id: u64, ts: u64) -> Result<()> {
+        let mut root = self.root.write();
+        self.version.fetch_add(1, Ordering::Release);
+        let removed = self.remove_internal(&root, key, record_id, ts);
+
+        // A merge can drain the root; its only child becomes the new root
+        if !root.is_leaf && root.len() == 0 {
+            let child = root.children.read()[0].clone();
+            *root = child;
+        }
+
+        if removed {
+            self.entry_bytes.fetch_sub(key.entry_size(), Ordering::Relaxed);
+            Ok(())
+        } else {
+            Err(MaemioError::RecordNotFound(record_id))
+        }
+    }
+
     fn get(&self, key: &IndexKey, ts: u64) -> Result<Option<u64>> {
         let root = self.root.read();
         self.search_key(&root, key, ts)
     }
-    
+
+    fn is_unique(&self) -> bool {
+        self.unique
+    }
+
     fn range_scan(&self, start: &IndexKey, end: &IndexKey, ts: u64) -> Result<Vec<u64>> {
         let mut result = Vec::new();
         let root = self.root.read();
         self.range_scan_internal(&root, start, end, ts, &mut result)?;
         Ok(result)
     }
-    
+
     fn get_validation_nodes(&self, start: &IndexKey, end: &IndexKey) -> Vec<Arc<IndexNode>> {
         let mut nodes = Vec::new();
         let root = self.root.read();
         self.collect_validation_nodes(&root, start, end, &mut nodes);
         nodes
     }
-    
+
     fn update_timestamps(&self, nodes: &[Arc<IndexNode>], ts: u64) {
         for node in nodes {
             node.update_rts(ts);
         }
     }
+
+    fn memory_usage(&self) -> usize {
+        self.entry_bytes.load(Ordering::Relaxed)
+    }
+
+    fn as_btree(&self) -> Option<&BTreeIndex> {
+        Some(self)
+    }
 }
 
 // Internal implementation methods
 impl BTreeIndex {
     fn insert_non_full(&self, node: Arc<BTreeNode>, key: IndexKey, record_id: u64, ts: u64) -> Result<()> {
-        let mut i = node.keys.read().len();
-        
+        let mut i = node.lower_bound(&key, record_id);
+
         if node.is_leaf {
-            let mut keys = node.keys.write();
-            let mut records = node.mv_node.records.write();
-            
-            while i > 0 && key < keys[i - 1] {
-                i -= 1;
-            }
-            
-            keys.insert(i, key);
-            records.push(record_id);
+            node.keys.write().insert(i, key);
+            node.mv_node.records.write().insert(i, record_id);
             node.mv_node.wts.store(ts, std::sync::atomic::Ordering::Release);
-            Ok(())
-        } else {
+            return Ok(());
+        }
+
+        let child = node.children.read()[i].clone();
+        if child.len() == MAX_KEYS {
+            node.split_child(i, ts)?;
             let keys = node.keys.read();
-            while i > 0 && key < keys[i - 1] {
-                i -= 1;
-            }
-            
-            let children = node.children.read();
-            let child = children[i].clone();
-            
-            if child.keys.read().len() == 2 * MIN_DEGREE - 1 {
-                node.split_child(i, ts)?;
-                let keys = node.keys.read();
-                if key > keys[i] {
-                    i += 1;
-                }
+            let records = node.mv_node.records.read();
+            if (&key, record_id) > (&keys[i], records[i]) {
+                i += 1;
             }
-            
-            let children = node.children.read();
-            self.insert_non_full(children[i].clone(), key, record_id, ts)
         }
+
+        let child = node.children.read()[i].clone();
+        self.insert_non_full(child, key, record_id, ts)
     }
-    
-    fn remove_key(&self, node: Arc<BTreeNode>, key: &IndexKey, ts: u64) -> Result<()> {
-        let mut i = 0;
-        let keys = node.keys.read();
-        
-        while i < keys.len() && key > &keys[i] {
-            i += 1;
-        }
-        
+
+    /// Removes one entry from the subtree under `node`, which is the root or
+    /// holds at least `MIN_DEGREE` entries. Children are topped up before the
+    /// walk descends into them, so every node but the root stays at least
+    /// half full.
+    fn remove_internal(&self, node: &BTreeNode, key: &IndexKey, record_id: u64, ts: u64) -> bool {
+        let i = node.lower_bound(key, record_id);
+        let found = {
+            let keys = node.keys.read();
+            let records = node.mv_node.records.read();
+            i < keys.len() && &keys[i] == key && records[i] == record_id
+        };
+
         if node.is_leaf {
-            if i < keys.len() && key == &keys[i] {
-                let mut keys = node.keys.write();
-                let mut records = node.mv_node.records.write();
-                keys.remove(i);
-                records.remove(i);
-                node.mv_node.wts.store(ts, std::sync::atomic::Ordering::Release);
-                Ok(())
-            } else {
-                Err(MaemioError::RecordNotFound(0))
+            if found {
+                node.keys.write().remove(i);
+                node.mv_node.records.write().remove(i);
+                node.mv_node.wts.store(ts, Ordering::Release);
             }
-        } else {
+            return found;
+        }
+
+        if !found {
+            let child = node.children.read()[i].clone();
+            if child.len() >= MIN_DEGREE {
+                return self.remove_internal(&child, key, record_id, ts);
+            }
+            // Filling the child reshapes this node; look the entry up again
+            node.fill_child(i, ts);
+            return self.remove_internal(node, key, record_id, ts);
+        }
+
+        // Replace the separator with its predecessor or successor taken from
+        // a child that can spare an entry, or merge the two children around it
+        let (left, right) = {
             let children = node.children.read();
-            self.remove_key(children[i].clone(), key, ts)
+            (children[i].clone(), children[i + 1].clone())
+        };
+        if left.len() >= MIN_DEGREE {
+            let (new_key, new_record) = left.max_entry().unwrap();
+            node.set_entry(i, (new_key.clone(), new_record));
+            node.mv_node.wts.store(ts, Ordering::Release);
+            self.remove_internal(&left, &new_key, new_record, ts)
+        } else if right.len() >= MIN_DEGREE {
+            let (new_key, new_record) = right.min_entry().unwrap();
+            node.set_entry(i, (new_key.clone(), new_record));
+            node.mv_node.wts.store(ts, Ordering::Release);
+            self.remove_internal(&right, &new_key, new_record, ts)
+        } else {
+            node.merge_children(i, ts);
+            self.remove_internal(&left, key, record_id, ts)
         }
     }
-    
+
     fn search_key(&self, node: &BTreeNode, key: &IndexKey, ts: u64) -> Result<Option<u64>> {
-        let mut i = 0;
-        let keys = node.keys.read();
-        
-        while i < keys.len() && key > &keys[i] {
-            i += 1;
+        let i = node.lower_bound(key, 0);
+        {
+            let keys = node.keys.read();
+            if node.is_leaf {
+                let records = node.mv_node.records.read();
+                return Ok((i < keys.len() && &keys[i] == key).then(|| records[i]));
+            }
         }
-        
-        if i < keys.len() && key == &keys[i] {
-            let records = node.mv_node.records.read();
-            Ok(Some(records[i]))
-        } else if node.is_leaf {
-            Ok(None)
-        } else {
-            let children = node.children.read();
-            self.search_key(&children[i], key, ts)
+
+        // Smaller entries with the same key may live in the left subtree
+        let child = node.children.read()[i].clone();
+        if let Some(record_id) = self.search_key(&child, key, ts)? {
+            return Ok(Some(record_id));
         }
+
+        let keys = node.keys.read();
+        let records = node.mv_node.records.read();
+        Ok((i < keys.len() && &keys[i] == key).then(|| records[i]))
     }
-    
+
     fn range_scan_internal(
         &self,
         node: &BTreeNode,
@@ -222,28 +696,28 @@ impl BTreeIndex {
     ) -> Result<()> {
         let keys = node.keys.read();
         let records = node.mv_node.records.read();
-        
+        let children = node.children.read();
+
         for i in 0..keys.len() {
-            if !node.is_leaf {
-                let children = node.children.read();
+            if !node.is_leaf && &keys[i] >= start {
                 self.range_scan_internal(&children[i], start, end, ts, result)?;
             }
-            
-            if &keys[i] >= start && &keys[i] <= end {
+
+            if &keys[i] > end {
+                return Ok(());
+            }
+            if &keys[i] >= start {
                 result.push(records[i]);
             }
         }
-        
-        if !node.is_leaf {
-            let children = node.children.read();
-            if let Some(last_child) = children.last() {
-                self.range_scan_internal(last_child, start, end, ts, result)?;
-            }
+
+        if let Some(last_child) = children.last() {
+            self.range_scan_internal(last_child, start, end, ts, result)?;
         }
-        
+
         Ok(())
     }
-    
+
     fn collect_validation_nodes(
         &self,
         node: &BTreeNode,
@@ -252,17 +726,227 @@ impl BTreeIndex {
         nodes: &mut Vec<Arc<IndexNode>>,
     ) {
         nodes.push(node.mv_node.clone());
-        
+
         if !node.is_leaf {
+            let keys = node.keys.read();
             let children = node.children.read();
-            for child in children.iter() {
-                let child_keys = child.keys.read();
-                if let (Some(min), Some(max)) = (child_keys.first(), child_keys.last()) {
-                    if min <= end && max >= start {
-                        self.collect_validation_nodes(child, start, end, nodes);
-                    }
+            for (i, child) in children.iter().enumerate() {
+                // Child i holds entries between separators i - 1 and i
+                let below_start = i < keys.len() && &keys[i] < start;
+                let above_end = i > 0 && &keys[i - 1] > end;
+                if !below_start && !above_end {
+                    self.collect_validation_nodes(child, start, end, nodes);
                 }
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_get_many() {
+        let index = BTreeIndex::new();
+        for i in 0..200 {
+            index.insert(IndexKey::Int((i * 37) % 200), i as u64, 1).unwrap();
+        }
+        for i in 0..200i64 {
+            let record_id = index.get(&IndexKey::Int((i * 37) % 200), 2).unwrap();
+            assert_eq!(record_id, Some(i as u64));
+        }
+        assert_eq!(index.get(&IndexKey::Int(500), 2).unwrap(), None);
+
+        let ids = index.range_scan(&IndexKey::Int(10), &IndexKey::Int(19), 2).unwrap();
+        assert_eq!(ids.len(), 10);
+    }
+
+    #[test]
+    fn test_duplicate_keys_and_exact_removal() {
+        let index = BTreeIndex::new();
+        for record_id in 0..50 {
+            index.insert(IndexKey::String("dup".into()), record_id, 1).unwrap();
+            index.insert(IndexKey::Int(record_id as i64), record_id, 1).unwrap();
+        }
+
+        let all = index.get_all(&IndexKey::String("dup".into()), 2).unwrap();
+        assert_eq!(all, (0..50).collect::<Vec<_>>());
+
+        for record_id in (0..50).step_by(2) {
+            index.remove_entry(&IndexKey::String("dup".into()), record_id, 3).unwrap();
+        }
+        let all = index.get_all(&IndexKey::String("dup".into()), 4).unwrap();
+        assert_eq!(all, (1..50).step_by(2).collect::<Vec<_>>());
+        assert!(index.remove_entry(&IndexKey::String("dup".into()), 0, 5).is_err());
+    }
+
+    #[test]
+    fn test_unique_rejects_second_record() {
+        let index = BTreeIndex::unique();
+        for i in 0..100 {
+            index.insert(IndexKey::Int(i), i as u64, 1).unwrap();
+        }
+        assert!(matches!(
+            index.insert(IndexKey::Int(42), 1000, 2),
+            Err(MaemioError::UniqueViolation(_))
+        ));
+        assert!(index.insert(IndexKey::Int(42), 42, 2).is_ok());
+        assert_eq!(index.get_all(&IndexKey::Int(42), 3).unwrap(), vec![42]);
+
+        index.remove_entry(&IndexKey::Int(42), 42, 3).unwrap();
+        index.insert(IndexKey::Int(42), 1000, 4).unwrap();
+        assert_eq!(index.get(&IndexKey::Int(42), 5).unwrap(), Some(1000));
+    }
+
+    #[test]
+    fn test_remove_everything() {
+        let index = BTreeIndex::new();
+        for i in 0..100 {
+            index.insert(IndexKey::Int(i), i as u64, 1).unwrap();
+        }
+        for i in (0..100).rev().step_by(3).chain((0..100).step_by(3)) {
+            let _ = index.remove(&IndexKey::Int(i), 2);
+        }
+        for i in 0..100 {
+            let _ = index.remove(&IndexKey::Int(i), 2);
+        }
+        assert!(index.range_scan(&IndexKey::Int(0), &IndexKey::Int(100), 3).unwrap().is_empty());
+
+        // The emptied tree still accepts inserts
+        index.insert(IndexKey::Int(7), 7, 4).unwrap();
+        assert_eq!(index.get(&IndexKey::Int(7), 5).unwrap(), Some(7));
+    }
+
+    /// Checks that every node below the root is at least half full and that
+    /// all leaves sit at the same depth, returning that depth
+    fn check_balanced(node: &BTreeNode, is_root: bool) -> usize {
+        if !is_root {
+            assert!(node.len() >= MIN_DEGREE - 1);
+        }
+        if node.is_leaf {
+            return 1;
+        }
+        let depths: Vec<usize> = node.children.read().iter().map(|child| check_balanced(child, false)).collect();
+        assert_eq!(depths.len(), node.len() + 1);
+        assert!(depths.iter().all(|&depth| depth == depths[0]));
+        depths[0] + 1
+    }
+
+    #[test]
+    fn test_removals_rebalance() {
+        let index = BTreeIndex::new();
+        for i in 0..2000 {
+            index.insert(IndexKey::Int(i), i as u64, 1).unwrap();
+        }
+        let full_depth = check_balanced(&index.root.read(), true);
+
+        for i in (0..2000).filter(|i| i % 10 != 0) {
+            index.remove_entry(&IndexKey::Int(i), i as u64, 2).unwrap();
+            if i % 97 == 0 {
+                check_balanced(&index.root.read(), true);
+            }
+        }
+        assert!(check_balanced(&index.root.read(), true) < full_depth);
+        let ids = index.range_scan(&IndexKey::Int(0), &IndexKey::Int(2000), 3).unwrap();
+        assert_eq!(ids, (0..2000).step_by(10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_matches_sorted_model() {
+        use rand::Rng;
+
+        let index = BTreeIndex::new();
+        let mut model: Vec<(i64, u64)> = Vec::new();
+        let mut rng = rand::rng();
+
+        for step in 0..3000u64 {
+            let key = rng.random_range(0..60);
+            if rng.random_bool(0.6) || model.is_empty() {
+                index.insert(IndexKey::Int(key), step, 1).unwrap();
+                model.push((key, step));
+            } else {
+                let (key, record_id) = model.swap_remove(rng.random_range(0..model.len()));
+                index.remove_entry(&IndexKey::Int(key), record_id, 1).unwrap();
+            }
+        }
+
+        check_balanced(&index.root.read(), true);
+        model.sort_unstable();
+        let ids = index.range_scan(&IndexKey::Int(0), &IndexKey::Int(60), 2).unwrap();
+        assert_eq!(ids, model.iter().map(|&(_, id)| id).collect::<Vec<_>>());
+        for key in 0..60 {
+            let expected = model.iter().find(|&&(k, _)| k == key).map(|&(_, id)| id);
+            assert_eq!(index.get(&IndexKey::Int(key), 2).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_cursor_bounds_and_order() {
+        let index = BTreeIndex::new();
+        let mut model = Vec::new();
+        for i in 0..500u64 {
+            let key = (i * 7) as i64 % 100;
+            index.insert(IndexKey::Int(key), i, 1).unwrap();
+            model.push((IndexKey::Int(key), i));
+        }
+        model.sort();
+        let within = |range: (Bound<i64>, Bound<i64>)| -> Vec<(IndexKey, u64)> {
+            model.iter()
+                .filter(|(key, _)| matches!(key, IndexKey::Int(k) if range.contains(k)))
+                .cloned()
+                .collect()
+        };
+        let key_bound = |bound: Bound<i64>| bound.map(IndexKey::Int);
+
+        let bounds = [Bound::Included(10), Bound::Excluded(10), Bound::Unbounded];
+        for start in bounds {
+            for end in [Bound::Included(90), Bound::Excluded(90), Bound::Unbounded] {
+                let expected = within((start, end));
+                let cursor = || index.cursor((key_bound(start), key_bound(end)));
+                assert_eq!(cursor().collect::<Vec<_>>(), expected);
+                let reversed: Vec<_> = expected.iter().rev().cloned().collect();
+                assert_eq!(cursor().rev().collect::<Vec<_>>(), reversed);
+            }
+        }
+        assert_eq!(index.cursor(IndexKey::Int(50)..IndexKey::Int(20)).count(), 0);
+
+        // Both ends meet in the middle without repeating an entry
+        let mut cursor = index.cursor(..);
+        let mut seen = Vec::new();
+        while let Some(entry) = cursor.next() {
+            seen.push(entry);
+            if let Some(entry) = cursor.next_back() {
+                seen.push(entry);
+            }
+        }
+        seen.sort();
+        assert_eq!(seen, model);
+
+        let mut cursor = index.cursor(IndexKey::Int(20)..IndexKey::Int(80));
+        cursor.seek(&IndexKey::Int(75));
+        assert_eq!(cursor.next().map(|(key, _)| key), Some(IndexKey::Int(75)));
+        cursor.seek(&IndexKey::Int(0));
+        assert_eq!(cursor.next().map(|(key, _)| key), Some(IndexKey::Int(20)));
+        cursor.seek_back(&IndexKey::Int(30));
+        assert_eq!(cursor.next_back().map(|(key, _)| key), Some(IndexKey::Int(30)));
+
+        // Entries inserted ahead of a running cursor are picked up
+        let mut cursor = index.cursor(IndexKey::Int(100)..);
+        index.insert(IndexKey::Int(100), 1000, 2).unwrap();
+        assert_eq!(cursor.next(), Some((IndexKey::Int(100), 1000)));
+        assert_eq!(cursor.next(), None);
+
+        // Writes between batches send the cursor back through the root
+        let mut cursor = index.cursor(..);
+        let first: Vec<_> = cursor.by_ref().take(CURSOR_BATCH).collect();
+        assert_eq!(first, model[..CURSOR_BATCH]);
+        index.remove_entry(&model[CURSOR_BATCH].0, model[CURSOR_BATCH].1, 3).unwrap();
+        index.insert(IndexKey::Int(99), 2000, 3).unwrap();
+        let rest: Vec<_> = cursor.collect();
+        assert_eq!(rest.len(), model.len() - CURSOR_BATCH + 1);
+        assert_eq!(rest[0], model[CURSOR_BATCH + 1]);
+        assert_eq!(rest.last(), Some(&(IndexKey::Int(100), 1000)));
+        assert!(rest.contains(&(IndexKey::Int(99), 2000)));
+    }
+}
@@ -0,0 +1,136 @@
+// src/index/extractor.rs
+use std::sync::Arc;
+use super::IndexKey;
+use crate::error::{MaemioError, Result};
+use crate::schema::{Schema, Value};
+#[cfg(feature = "json")]
+use crate::document::{json_to_key, JsonPath};
+
+/// Function computing an index key from a record's bytes; `None` leaves the record unindexed
+pub type KeyFn = dyn Fn(&[u8]) -> Option<IndexKey> + Send + Sync;
+
+/// Describes how an automatically maintained index derives its key from a record
+#[derive(Clone)]
+#[non_exhaustive]
+pub enum KeyExtractor {
+    /// Uses a column of the table's schema; null values are not indexed
+    Column(String),
+    /// Uses an arbitrary function over the record's bytes
+    Function(Arc<KeyFn>),
+    /// Uses the scalar at a path of a JSON document; records that are not
+    /// JSON or lack a scalar there are not indexed
+    #[cfg(feature = "json")]
+    JsonPath(JsonPath),
+}
+
+impl KeyExtractor {
+    pub fn column(name: &str) -> Self {
+        KeyExtractor::Column(name.to_string())
+    }
+
+    pub fn function<F>(f: F) -> Self
+    where
+        F: Fn(&[u8]) -> Option<IndexKey> + Send + Sync + 'static,
+    {
+        KeyExtractor::Function(Arc::new(f))
+    }
+
+    #[cfg(feature = "json")]
+    pub fn json_path(path: &str) -> Result<Self> {
+        Ok(KeyExtractor::JsonPath(JsonPath::parse(path)?))
+    }
+
+    /// Resolves the extractor against a table's schema
+    pub(crate) fn bind(&self, schema: Option<&Arc<Schema>>) -> Result<BoundExtractor> {
+        match self {
+            KeyExtractor::Function(f) => Ok(BoundExtractor::Function(f.clone())),
+            #[cfg(feature = "json")]
+            KeyExtractor::JsonPath(path) => Ok(BoundExtractor::JsonPath(path.clone())),
+            KeyExtractor::Column(name) => {
+                let schema = schema.ok_or_else(|| MaemioError::SchemaViolation(format!(
+                    "Column index on {} requires a table schema", name
+                )))?;
+                let column = schema.column_index(name)?;
+                Ok(BoundExtractor::Column(schema.clone(), column))
+            }
+        }
+    }
+}
+
+/// A key extractor resolved against a specific table
+pub(crate) enum BoundExtractor {
+    Column(Arc<Schema>, usize),
+    Function(Arc<KeyFn>),
+    #[cfg(feature = "json")]
+    JsonPath(JsonPath),
+}
+
+impl BoundExtractor {
+    pub(crate) fn extract(&self, data: &[u8]) -> Result<Option<IndexKey>> {
+        match self {
+            BoundExtractor::Function(f) => Ok(f(data)),
+            BoundExtractor::Column(schema, column) => {
+                let values = schema.decode_columns_at(data, &[*column])?;
+                Ok(values.first().and_then(value_to_key))
+            }
+            #[cfg(feature = "json")]
+            BoundExtractor::JsonPath(path) => {
+                let Ok(document) = serde_json::from_slice(data) else {
+                    return Ok(None);
+                };
+                Ok(path.get(&document).and_then(json_to_key))
+            }
+        }
+    }
+}
+
+/// Converts a column value into an index key that sorts like the value
+pub(crate) fn value_to_key(value: &Value) -> Option<IndexKey> {
+    match value {
+        Value::Null => None,
+        Value::Int(v) => Some(IndexKey::Int(*v)),
+        Value::Bool(v) => Some(IndexKey::Bool(*v)),
+        Value::String(v) => Some(IndexKey::String(v.clone())),
+        Value::Bytes(v) => Some(IndexKey::Bytes(v.clone())),
+        Value::Float(v) => {
+            // Flip the bits so byte order matches numeric order
+            let bits = v.to_bits();
+            let ordered = if bits >> 63 == 1 { !bits } else { bits | (1 << 63) };
+            Some(IndexKey::Bytes(ordered.to_be_bytes().to_vec()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::{Column, ColumnType, Row};
+
+    #[test]
+    fn test_column_extractor() {
+        let schema = Arc::new(Schema::new(vec![
+            Column::new("id", ColumnType::Int),
+            Column::nullable("email", ColumnType::String),
+        ]).unwrap());
+        let extractor = KeyExtractor::column("email").bind(Some(&schema)).unwrap();
+
+        let row = Row::new(vec![Value::Int(1), Value::String("a@b.c".into())]);
+        let key = extractor.extract(&schema.encode_row(&row).unwrap()).unwrap();
+        assert_eq!(key, Some(IndexKey::String("a@b.c".into())));
+
+        let row = Row::new(vec![Value::Int(1), Value::Null]);
+        assert_eq!(extractor.extract(&schema.encode_row(&row).unwrap()).unwrap(), None);
+
+        assert!(KeyExtractor::column("missing").bind(Some(&schema)).is_err());
+        assert!(KeyExtractor::column("email").bind(None).is_err());
+    }
+
+    #[test]
+    fn test_float_keys_sort_numerically() {
+        let keys: Vec<_> = [-10.5, -0.25, 0.0, 0.25, 3.0, 1e9]
+            .iter()
+            .map(|v| value_to_key(&Value::Float(*v)).unwrap())
+            .collect();
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
@@ -1,17 +1,33 @@
 // src/index/hash.rs
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use parking_lot::RwLock;
 use super::{Index, IndexKey, IndexNode};
 use crate::error::{MaemioError, Result};
 
+/// Hash index mapping each key to the records stored under it.
+///
+/// A unique index rejects a second record for a key that is already taken.
 pub struct HashIndex {
-    buckets: Vec<RwLock<(Arc<IndexNode>, HashMap<IndexKey, u64>)>>,
+    buckets: Vec<RwLock<(Arc<IndexNode>, HashMap<IndexKey, Vec<u64>>)>>,
     num_buckets: usize,
+    unique: bool,
+    // Sum of the entry sizes of all stored entries
+    entry_bytes: AtomicUsize,
 }
 
 impl HashIndex {
     pub fn new(capacity: usize) -> Self {
+        Self::with_uniqueness(capacity, false)
+    }
+
+    /// Creates an index that allows at most one record per key
+    pub fn unique(capacity: usize) -> Self {
+        Self::with_uniqueness(capacity, true)
+    }
+
+    fn with_uniqueness(capacity: usize, unique: bool) -> Self {
         let num_buckets = capacity.next_power_of_two();
         let mut buckets = Vec::with_capacity(num_buckets);
         
@@ -25,12 +41,15 @@ impl HashIndex {
         Self {
             buckets,
             num_buckets,
+            unique,
+            entry_bytes: AtomicUsize::new(0),
         }
     }
     
     fn get_bucket_index(&self, key: &IndexKey) -> usize {
         match key {
             IndexKey::Int(i) => (*i as usize) & (self.num_buckets - 1),
+            IndexKey::Bool(b) => (*b as usize) & (self.num_buckets - 1),
             IndexKey::String(s) => {
                 let hash: usize = s.as_bytes()
                     .iter()
@@ -50,35 +69,76 @@ impl Index for HashIndex {
     fn insert(&self, key: IndexKey, record_id: u64, ts: u64) -> Result<()> {
         let bucket_idx = self.get_bucket_index(&key);
         let mut bucket = self.buckets[bucket_idx].write();
-        
+
+        if self.unique {
+            if let Some(&existing) = bucket.1.get(&key).and_then(|ids| ids.first()) {
+                if existing == record_id {
+                    return Ok(());
+                }
+                return Err(MaemioError::UniqueViolation(format!(
+                    "key {:?} already maps to record {}", key, existing
+                )));
+            }
+        }
+
         bucket.0.wts.store(ts, std::sync::atomic::Ordering::Release);
-        bucket.1.insert(key, record_id);
-        
+        self.entry_bytes.fetch_add(key.entry_size(), Ordering::Relaxed);
+        bucket.1.entry(key).or_default().push(record_id);
+
         Ok(())
     }
     
     fn remove(&self, key: &IndexKey, ts: u64) -> Result<()> {
+        let bucket_idx = self.get_bucket_index(key);
+        let record_id = self.buckets[bucket_idx].read().1
+            .get(key)
+            .and_then(|ids| ids.first().copied())
+            .ok_or(MaemioError::RecordNotFound(0))?;
+        self.remove_entry(key, record_id, ts)
+    }
+    
+    fn remove_entry(&self, key: &IndexKey, record_id: u64, ts: u64) -> Result<()> {
         let bucket_idx = self.get_bucket_index(key);
         let mut bucket = self.buckets[bucket_idx].write();
-        
-        if bucket.1.remove(key).is_some() {
-            bucket.0.wts.store(ts, std::sync::atomic::Ordering::Release);
-            Ok(())
-        } else {
-            Err(MaemioError::RecordNotFound(0))
+
+        let ids = bucket.1.get_mut(key).ok_or(MaemioError::RecordNotFound(record_id))?;
+        let pos = ids.iter()
+            .position(|&id| id == record_id)
+            .ok_or(MaemioError::RecordNotFound(record_id))?;
+        ids.remove(pos);
+        self.entry_bytes.fetch_sub(key.entry_size(), Ordering::Relaxed);
+        if ids.is_empty() {
+            bucket.1.remove(key);
         }
+        bucket.0.wts.store(ts, std::sync::atomic::Ordering::Release);
+        Ok(())
     }
-    
+
     fn get(&self, key: &IndexKey, ts: u64) -> Result<Option<u64>> {
         let bucket_idx = self.get_bucket_index(key);
         let bucket = self.buckets[bucket_idx].read();
         
         if bucket.0.wts.load(std::sync::atomic::Ordering::Acquire) <= ts {
-            Ok(bucket.1.get(key).copied())
+            Ok(bucket.1.get(key).and_then(|ids| ids.first().copied()))
         } else {
             Ok(None)
         }
     }
+
+    fn get_all(&self, key: &IndexKey, ts: u64) -> Result<Vec<u64>> {
+        let bucket_idx = self.get_bucket_index(key);
+        let bucket = self.buckets[bucket_idx].read();
+
+        if bucket.0.wts.load(std::sync::atomic::Ordering::Acquire) <= ts {
+            Ok(bucket.1.get(key).cloned().unwrap_or_default())
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    fn is_unique(&self) -> bool {
+        self.unique
+    }
     
     fn range_scan(&self, _start: &IndexKey, _end: &IndexKey, _ts: u64) -> Result<Vec<u64>> {
         Err(MaemioError::System("Range scan not supported on hash index".into()))
@@ -99,4 +159,42 @@ impl Index for HashIndex {
             node.update_rts(ts);
         }
     }
-}
\ No newline at end of file
+
+    fn memory_usage(&self) -> usize {
+        self.entry_bytes.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicate_keys() {
+        let index = HashIndex::new(16);
+        index.insert(IndexKey::Int(1), 10, 1).unwrap();
+        index.insert(IndexKey::Int(1), 11, 1).unwrap();
+        assert_eq!(index.get_all(&IndexKey::Int(1), 2).unwrap(), vec![10, 11]);
+
+        index.remove_entry(&IndexKey::Int(1), 10, 2).unwrap();
+        assert_eq!(index.get(&IndexKey::Int(1), 3).unwrap(), Some(11));
+        assert_eq!(index.memory_usage(), IndexKey::Int(1).entry_size());
+        assert!(index.remove_entry(&IndexKey::Int(1), 10, 3).is_err());
+    }
+
+    #[test]
+    fn test_unique_rejects_second_record() {
+        let index = HashIndex::unique(16);
+        index.insert(IndexKey::String("a".into()), 1, 1).unwrap();
+        // Re-inserting the same mapping is harmless
+        index.insert(IndexKey::String("a".into()), 1, 1).unwrap();
+        assert!(matches!(
+            index.insert(IndexKey::String("a".into()), 2, 1),
+            Err(MaemioError::UniqueViolation(_))
+        ));
+
+        index.remove(&IndexKey::String("a".into()), 2).unwrap();
+        index.insert(IndexKey::String("a".into()), 2, 3).unwrap();
+        assert_eq!(index.get_all(&IndexKey::String("a".into()), 3).unwrap(), vec![2]);
+    }
+}
@@ -23,6 +23,15 @@ impl IndexManager {
     
     /// Creates a new index of the specified type
     pub fn create_index(&self, table_id: u64, name: &str, index_type: IndexType) -> Result<()> {
+        self.insert_index(table_id, name, index_type, false)
+    }
+
+    /// Creates an index that rejects a second record for an existing key
+    pub fn create_unique_index(&self, table_id: u64, name: &str, index_type: IndexType) -> Result<()> {
+        self.insert_index(table_id, name, index_type, true)
+    }
+
+    fn insert_index(&self, table_id: u64, name: &str, index_type: IndexType, unique: bool) -> Result<()> {
         let mut indexes = self.indexes.write();
         
         // Check if index already exists
@@ -33,9 +42,12 @@ impl IndexManager {
         }
         
         // Create the appropriate index type
-        let index: Arc<dyn Index> = match index_type {
-            IndexType::BTree => Arc::new(BTreeIndex::new()),
-            IndexType::Hash => Arc::new(HashIndex::new(1024)), // Default initial capacity
+        let index: Arc<dyn Index> = match (index_type, unique) {
+            (IndexType::BTree, false) => Arc::new(BTreeIndex::new()),
+            (IndexType::BTree, true) => Arc::new(BTreeIndex::unique()),
+            // Default initial capacity
+            (IndexType::Hash, false) => Arc::new(HashIndex::new(1024)),
+            (IndexType::Hash, true) => Arc::new(HashIndex::unique(1024)),
         };
         
         indexes.insert(
@@ -59,15 +71,69 @@ impl IndexManager {
     
     /// Drops an existing index
     pub fn drop_index(&self, table_id: u64, name: &str) -> Result<()> {
+        self.take_index(table_id, name).map(|_| ())
+    }
+
+    /// Removes an index, handing it back so a rolled-back drop can restore it
+    pub(crate) fn take_index(&self, table_id: u64, name: &str) -> Result<(IndexType, Arc<dyn Index>)> {
         let mut indexes = self.indexes.write();
-        
+
         indexes.remove(&(table_id, name.to_string()))
-            .map(|_| ())
             .ok_or_else(|| MaemioError::System(format!(
                 "Index {} not found for table {}", name, table_id
             )))
     }
+
+    /// Puts back an index taken out by `take_index`
+    pub(crate) fn restore_index(
+        &self,
+        table_id: u64,
+        name: &str,
+        index_type: IndexType,
+        index: Arc<dyn Index>,
+    ) -> Result<()> {
+        let mut indexes = self.indexes.write();
+        if indexes.contains_key(&(table_id, name.to_string())) {
+            return Err(MaemioError::System(format!(
+                "Index {} already exists for table {}", name, table_id
+            )));
+        }
+        indexes.insert((table_id, name.to_string()), (index_type, index));
+        Ok(())
+    }
     
+    /// Drops every index belonging to a table
+    pub fn drop_table_indexes(&self, table_id: u64) {
+        self.indexes.write().retain(|(index_table, _), _| *index_table != table_id);
+    }
+
+    /// Removes every index belonging to a table, handing them back so a
+    /// rolled-back drop can restore them
+    pub(crate) fn take_table_indexes(&self, table_id: u64) -> Vec<(String, IndexType, Arc<dyn Index>)> {
+        let mut indexes = self.indexes.write();
+        let names: Vec<String> = indexes.keys()
+            .filter(|(index_table, _)| *index_table == table_id)
+            .map(|(_, name)| name.clone())
+            .collect();
+        names.into_iter()
+            .filter_map(|name| {
+                let (index_type, index) = indexes.remove(&(table_id, name.clone()))?;
+                Some((name, index_type, index))
+            })
+            .collect()
+    }
+
+    /// Returns the names of all indexes on a table
+    pub fn table_indexes(&self, table_id: u64) -> Vec<String> {
+        let mut names: Vec<String> = self.indexes.read()
+            .keys()
+            .filter(|(index_table, _)| *index_table == table_id)
+            .map(|(_, name)| name.clone())
+            .collect();
+        names.sort();
+        names
+    }
+
     /// Validates all affected index nodes for a given operation
     pub fn validate_index_access(
         &self,
@@ -169,4 +235,17 @@ mod tests {
         // Update timestamps
         manager.update_index_timestamps(&nodes, 2);
     }
+
+    #[test]
+    fn test_drop_table_indexes() {
+        let manager = IndexManager::new();
+        manager.create_index(1, "a", IndexType::BTree).unwrap();
+        manager.create_index(1, "b", IndexType::Hash).unwrap();
+        manager.create_index(2, "a", IndexType::BTree).unwrap();
+        assert_eq!(manager.table_indexes(1), vec!["a".to_string(), "b".to_string()]);
+
+        manager.drop_table_indexes(1);
+        assert!(manager.table_indexes(1).is_empty());
+        assert!(manager.get_index(2, "a").is_ok());
+    }
 }
\ No newline at end of file
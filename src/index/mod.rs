@@ -18,8 +18,20 @@ pub enum IndexKey {
     Int(i64),
     String(String),
     Bytes(Vec<u8>),
+    Bool(bool),
 }
 
+impl IndexKey {
+    /// Approximate bytes one index entry under this key takes, record id included
+    pub(crate) fn entry_size(&self) -> usize {
+        let heap = match self {
+            IndexKey::Int(_) | IndexKey::Bool(_) => 0,
+            IndexKey::String(s) => s.len(),
+            IndexKey::Bytes(b) => b.len(),
+        };
+        std::mem::size_of::<IndexKey>() + heap + std::mem::size_of::<u64>()
+    }
+}
 
 /// Node structure used in multi-version indexes
 #[derive(Debug)]
@@ -58,9 +70,22 @@ pub trait Index: Send + Sync {
     
     /// Removes a key-value pair from the index
     fn remove(&self, key: &IndexKey, ts: u64) -> Result<()>;
+
+    /// Removes the entry mapping `key` to `record_id`, leaving other records under the same key
+    fn remove_entry(&self, key: &IndexKey, record_id: u64, ts: u64) -> Result<()>;
     
     /// Looks up a single key
     fn get(&self, key: &IndexKey, ts: u64) -> Result<Option<u64>>;
+
+    /// Returns every record stored under a key
+    fn get_all(&self, key: &IndexKey, ts: u64) -> Result<Vec<u64>> {
+        self.range_scan(key, key, ts)
+    }
+
+    /// Whether the index rejects a second record for an existing key
+    fn is_unique(&self) -> bool {
+        false
+    }
     
     /// Performs a range scan
     fn range_scan(&self, start: &IndexKey, end: &IndexKey, ts: u64) -> Result<Vec<u64>>;
@@ -70,6 +95,14 @@ pub trait Index: Send + Sync {
     
     /// Updates timestamps after successful validation
     fn update_timestamps(&self, nodes: &[Arc<IndexNode>], ts: u64);
+
+    /// Approximate bytes held by the index's entries
+    fn memory_usage(&self) -> usize;
+
+    /// The index as a B-tree, for ordered cursors; `None` for other kinds
+    fn as_btree(&self) -> Option<&BTreeIndex> {
+        None
+    }
 }
 
 // Common constants for index management
@@ -90,8 +123,11 @@ pub enum IndexStatus {
 mod btree;
 mod hash;
 mod manager;
+mod extractor;
 
 // And re-export the public interface
-pub use self::btree::BTreeIndex;
+pub use self::btree::{BTreeCursor, BTreeIndex};
 pub use self::hash::HashIndex;
-pub use self::manager::IndexManager;
\ No newline at end of file
+pub use self::manager::IndexManager;
+pub use self::extractor::{KeyExtractor, KeyFn};
+pub(crate) use self::extractor::{value_to_key, BoundExtractor};
\ No newline at end of file
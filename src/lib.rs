@@ -10,14 +10,40 @@ mod transaction;
 mod gc;
 mod contention;
 mod index;
+mod sequence;
+mod table;
+mod schema;
+mod catalog;
+mod map;
+mod queue;
+#[cfg(feature = "serde")]
+mod codec;
+#[cfg(feature = "json")]
+mod document;
 
 pub use error::{MaemioError, Result};
-pub use transaction::{Transaction, TransactionManager};
+pub use transaction::{BlobReader, Scan, Transaction, TransactionManager, BLOB_CHUNK_SIZE};
 pub use gc::GarbageCollector;
 pub use contention::ContentionManager;
-pub use index::{Index, IndexType, IndexKey, IndexManager};
+pub use index::{BTreeCursor, BTreeIndex, Index, IndexType, IndexKey, IndexManager, KeyExtractor};
+pub use sequence::{IdStrategy, Sequence, SequenceManager};
+pub use data::{ArenaStats, Compression, CompressionStats, MemoryUsage, PayloadArena, MAX_INLINE_SIZE};
+pub use table::{ForeignKey, OnDelete, Table, TableManager, DEFAULT_TABLE_ID};
+pub use catalog::{Catalog, CatalogEntry, ObjectKind, CATALOG_TABLE_ID};
+pub use schema::{Column, ColumnType, Constraint, Row, Schema, Value};
+pub use map::{KeyCodec, Map, MapRange, ValueCodec};
+pub use queue::{Queue, QueueMessage, Receipt};
+#[cfg(feature = "serde")]
+pub use codec::{Binary, Codec, Json};
+#[cfg(feature = "json")]
+pub use document::{Document, JsonPath};
+#[cfg(feature = "json")]
+pub use serde_json::Value as JsonValue;
 
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
+use parking_lot::RwLock;
 
 /// Configuration options for the database instance
 pub struct MaemioConfig {
@@ -29,6 +55,9 @@ pub struct MaemioConfig {
     pub clock_sync_interval: u64,
     /// Initial index capacity (for hash indexes)
     pub initial_index_capacity: usize,
+    /// Size from which superseded versions of new tables are stored as
+    /// deltas, or `None` to keep full copies; tables can change their own
+    pub delta_threshold: Option<usize>,
 }
 
 impl Default for MaemioConfig {
@@ -38,6 +67,7 @@ impl Default for MaemioConfig {
             gc_interval: 10,  // 10 microseconds
             clock_sync_interval: 100,  // 100 microseconds
             initial_index_capacity: 1024,
+            delta_threshold: None,
         }
     }
 }
@@ -51,7 +81,19 @@ pub struct Maemio {
     
     // Index management component
     index_manager: Arc<IndexManager>,
+
+    // Tables and their records
+    tables: Arc<TableManager>,
+
+    // Named sequences
+    sequences: Arc<SequenceManager>,
+
+    // System catalog and DDL coordination
+    catalog: Arc<Catalog>,
     
+    // Queues by name, sharing consumer claims between handles
+    queues: RwLock<HashMap<String, Queue>>,
+
     // Configuration
     config: MaemioConfig,
 }
@@ -77,9 +119,29 @@ impl Maemio {
             contention::DEFAULT_BACKOFF_STEP,
         ));
 
+        // Create the table catalog; a few record shards per worker keeps
+        // lock contention on record lookup low
+        let tables = Arc::new(
+            TableManager::new(config.thread_count * 4).with_delta_threshold(config.delta_threshold),
+        );
+
+        // Create the sequence manager shared by all transactions
+        let sequences = Arc::new(SequenceManager::new());
+
+        // Create the index manager
+        let index_manager = Arc::new(IndexManager::new());
+
+        // The catalog ties together everything DDL changes
+        let catalog = Arc::new(Catalog::new(
+            tables.clone(),
+            index_manager.clone(),
+            sequences.clone(),
+        ));
+
         // Create the transaction manager
         let transaction_manager = Arc::new(TransactionManager::new(
             clock_manager.clone(),
+            catalog.clone(),
             config.thread_count,
         )?);
 
@@ -87,18 +149,27 @@ impl Maemio {
         let gc = Some(Arc::new(GarbageCollector::new(
             clock_manager.clone(),
             config.gc_interval
-        )));
+        ).with_expiry(transaction_manager.clone())));
 
-        // Create the index manager
-        let index_manager = Arc::new(IndexManager::new());
-
-        Ok(Self {
+        let db = Self {
             transaction_manager,
             gc,
             contention_manager,
             index_manager,
+            tables,
+            sequences,
+            catalog,
+            queues: RwLock::new(HashMap::new()),
             config,
-        })
+        };
+
+        // Describe the system tables in the catalog itself
+        db.execute(0, |tx| {
+            tx.insert_catalog_entry(&CatalogEntry::table(&db.tables.default_table()))?;
+            tx.insert_catalog_entry(&CatalogEntry::table(&db.tables.catalog_table()))
+        })?;
+
+        Ok(db)
     }
 
     /// Starts all background maintenance tasks
@@ -114,14 +185,138 @@ impl Maemio {
         Ok(())
     }
 
+    /// Creates a new table with its own record id space
+    pub fn create_table(&self, name: &str) -> Result<Arc<Table>> {
+        self.execute(0, |tx| tx.create_table(name))
+    }
+
+    /// Creates a new table whose rows are validated against `schema`
+    pub fn create_table_with_schema(&self, name: &str, schema: Schema) -> Result<Arc<Table>> {
+        self.execute(0, |tx| tx.create_table_with_schema(name, schema.clone()))
+    }
+
+    /// Looks up a table by name
+    pub fn get_table(&self, name: &str) -> Result<Arc<Table>> {
+        self.tables.get_table_by_name(name)
+    }
+
+    /// Drops a table together with its records, indexes and foreign keys.
+    ///
+    /// Tables still referenced by another table's foreign key cannot be dropped.
+    pub fn drop_table(&self, name: &str) -> Result<()> {
+        self.execute(0, |tx| tx.drop_table(name))
+    }
+
     /// Creates a new index for a table
     pub fn create_index(&self, table_id: u64, name: &str, index_type: IndexType) -> Result<()> {
-        self.index_manager.create_index(table_id, name, index_type)
+        self.execute(0, |tx| tx.create_index(table_id, name, index_type))
+    }
+
+    /// Creates an index that transactions keep in sync with the table's records.
+    ///
+    /// Existing records are indexed right away; afterwards every committed
+    /// write or delete updates the index, removing entries whose key changed.
+    pub fn create_index_with_key(
+        &self,
+        table_id: u64,
+        name: &str,
+        index_type: IndexType,
+        key: KeyExtractor,
+    ) -> Result<()> {
+        self.execute(0, |tx| tx.create_index_with_key(table_id, name, index_type, key.clone()))
+    }
+
+    /// Creates a maintained index on the scalar at `path` of a table's JSON
+    /// documents, for use with `Transaction::find_documents`
+    #[cfg(feature = "json")]
+    pub fn create_path_index(&self, table_id: u64, name: &str, index_type: IndexType, path: &str) -> Result<()> {
+        self.create_index_with_key(table_id, name, index_type, KeyExtractor::json_path(path)?)
+    }
+
+    /// Creates a maintained index that allows at most one record per key.
+    ///
+    /// Commits that would give a second record the same key fail with
+    /// `UniqueViolation`. Creation fails if existing records already collide.
+    pub fn create_unique_index(
+        &self,
+        table_id: u64,
+        name: &str,
+        index_type: IndexType,
+        key: KeyExtractor,
+    ) -> Result<()> {
+        self.execute(0, |tx| tx.create_unique_index(table_id, name, index_type, key.clone()))
+    }
+
+    /// Declares that `column` of the child table holds record ids of the parent table.
+    ///
+    /// Commits writing a child row must reference an existing parent, and
+    /// deleting a parent either fails or deletes its children depending on
+    /// `on_delete`. Creation fails if existing rows already break the reference.
+    pub fn create_foreign_key(
+        &self,
+        name: &str,
+        child_table_id: u64,
+        column: &str,
+        parent_table_id: u64,
+        on_delete: OnDelete,
+    ) -> Result<()> {
+        self.execute(0, |tx| tx.create_foreign_key(name, child_table_id, column, parent_table_id, on_delete))
+    }
+
+    /// Drops a foreign key declared on a child table
+    pub fn drop_foreign_key(&self, child_table_id: u64, name: &str) -> Result<()> {
+        self.execute(0, |tx| tx.drop_foreign_key(child_table_id, name))
     }
 
     /// Drops an existing index
     pub fn drop_index(&self, table_id: u64, name: &str) -> Result<()> {
-        self.index_manager.drop_index(table_id, name)
+        self.execute(0, |tx| tx.drop_index(table_id, name))
+    }
+
+    /// Creates a named sequence starting at `start` and advancing by `increment`
+    pub fn create_sequence(&self, name: &str, start: u64, increment: u64) -> Result<()> {
+        self.execute(0, |tx| tx.create_sequence(name, start, increment))
+    }
+
+    /// Drops an existing sequence
+    pub fn drop_sequence(&self, name: &str) -> Result<()> {
+        self.execute(0, |tx| tx.drop_sequence(name))
+    }
+
+    /// Returns every catalog entry: tables, indexes and sequences
+    pub fn catalog_entries(&self) -> Result<Vec<CatalogEntry>> {
+        self.execute(0, |tx| tx.catalog_entries())
+    }
+
+    /// Describes all tables, including the system tables
+    pub fn list_tables(&self) -> Result<Vec<CatalogEntry>> {
+        self.catalog_entries_of(ObjectKind::Table, |_| true)
+    }
+
+    /// Describes the indexes of a table
+    pub fn list_indexes(&self, table_id: u64) -> Result<Vec<CatalogEntry>> {
+        self.catalog_entries_of(ObjectKind::Index, |entry| entry.table_id == Some(table_id))
+    }
+
+    /// Describes all sequences
+    pub fn list_sequences(&self) -> Result<Vec<CatalogEntry>> {
+        self.catalog_entries_of(ObjectKind::Sequence, |_| true)
+    }
+
+    fn catalog_entries_of<F>(&self, kind: ObjectKind, filter: F) -> Result<Vec<CatalogEntry>>
+    where
+        F: Fn(&CatalogEntry) -> bool,
+    {
+        Ok(self.catalog_entries()?
+            .into_iter()
+            .filter(|entry| entry.kind == kind && filter(entry))
+            .collect())
+    }
+
+    /// Sets how record ids are generated for a table
+    pub fn set_id_strategy(&self, table_id: u64, strategy: IdStrategy) -> Result<()> {
+        self.tables.get_table(table_id)?.set_id_strategy(strategy);
+        Ok(())
     }
 
     /// Begins a new transaction for the given thread
@@ -142,11 +337,87 @@ impl Maemio {
         }
     }
 
-    /// Creates a new record in the database
+    /// Creates a new record in the default table
     pub fn create_record(&self, record_id: u64) -> Result<()> {
         self.transaction_manager.create_record(record_id)
     }
 
+    /// Creates a new record in the given table
+    pub fn create_record_in(&self, table_id: u64, record_id: u64) -> Result<()> {
+        self.transaction_manager.create_record_in(table_id, record_id)
+    }
+
+    /// Creates a new record with an id generated by the table's id allocator
+    pub fn create_record_auto(&self, table_id: u64) -> Result<u64> {
+        self.transaction_manager.create_record_auto(table_id)
+    }
+
+    /// Opens the typed map stored in the table `name`, creating both on first
+    /// use. Entries are read and written through the map's methods inside
+    /// transactions.
+    pub fn open_map<K: KeyCodec, V: ValueCodec>(&self, name: &str) -> Result<Map<K, V>> {
+        let table = match self.tables.get_table_by_name(name) {
+            Ok(table) => table,
+            Err(_) => {
+                let created = self.execute(0, |tx| {
+                    let table = tx.create_table(name)?;
+                    tx.create_map_index(table.id())?;
+                    Ok(table)
+                });
+                // A concurrent open may have created the map first
+                match created {
+                    Ok(table) => table,
+                    Err(err) => self.tables.get_table_by_name(name).map_err(|_| err)?,
+                }
+            }
+        };
+        let primary = table.indexes()
+            .into_iter()
+            .find(|index| index.name == map::MAP_PRIMARY_INDEX)
+            .ok_or_else(|| MaemioError::SchemaViolation(format!("Table {} is not a map", name)))?;
+        Ok(Map::new(name, table.id(), primary))
+    }
+
+    /// Creates a queue stored in a new table `name`. Dequeued messages stay
+    /// hidden for `visibility_timeout` unless acknowledged first.
+    pub fn create_queue(&self, name: &str, visibility_timeout: Duration) -> Result<Queue> {
+        let queue = self.execute(0, |tx| tx.create_queue(name, visibility_timeout))?;
+        self.queues.write().insert(name.to_string(), queue.clone());
+        Ok(queue)
+    }
+
+    /// Returns a queue created by `create_queue`
+    pub fn queue(&self, name: &str) -> Result<Queue> {
+        let cached = self.queues.read().get(name).cloned();
+        if let Some(queue) = cached.filter(|queue| self.tables.get_table(queue.table_id()).is_ok()) {
+            return Ok(queue);
+        }
+        let entry = self.catalog_entries_of(ObjectKind::Queue, |entry| entry.name == name)?
+            .pop()
+            .ok_or_else(|| MaemioError::TableNotFound(name.to_string()))?;
+        let queue = Queue::from_catalog_entry(&entry)?;
+        queue.recover_head(&*self.tables.get_table(queue.table_id())?)?;
+        // Handles share consumer claims, so a concurrent lookup's handle wins
+        let mut queues = self.queues.write();
+        match queues.get(name) {
+            Some(cached) if cached.table_id() == queue.table_id() => Ok(cached.clone()),
+            _ => {
+                queues.insert(name.to_string(), queue.clone());
+                Ok(queue)
+            }
+        }
+    }
+
+    /// Describes all queues
+    pub fn list_queues(&self) -> Result<Vec<CatalogEntry>> {
+        self.catalog_entries_of(ObjectKind::Queue, |_| true)
+    }
+
+    /// Allocation counters of a worker thread's payload arena
+    pub fn arena_stats(&self, thread_id: usize) -> ArenaStats {
+        self.transaction_manager.clock_manager().get_clock(thread_id).arena().stats()
+    }
+
     /// Gets a reference to the index manager
     pub fn index_manager(&self) -> Arc<IndexManager> {
         self.index_manager.clone()
@@ -208,6 +479,7 @@ mod tests {
             gc_interval: 20,
             clock_sync_interval: 200,
             initial_index_capacity: 2048,
+            delta_threshold: Some(4096),
         };
         
         let db = Maemio::with_config(config).unwrap();
@@ -244,4 +516,1112 @@ mod tests {
 
         db.shutdown().unwrap();
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_sequences_and_auto_ids() {
+        let db = Maemio::new().unwrap();
+        db.create_sequence("invoice_no", 100, 1).unwrap();
+
+        let values = db.execute(0, |tx| {
+            Ok((tx.next_value("invoice_no")?, tx.next_value("invoice_no")?))
+        }).unwrap();
+        assert_eq!(values, (100, 101));
+
+        // Hand-picked ids are skipped by the allocator
+        db.create_record(1).unwrap();
+        let id = db.create_record_auto(DEFAULT_TABLE_ID).unwrap();
+        assert_eq!(id, 2);
+
+        let sessions = db.create_table("sessions").unwrap();
+        db.set_id_strategy(sessions.id(), IdStrategy::Uuid).unwrap();
+        let first = db.create_record_auto(sessions.id()).unwrap();
+        let second = db.create_record_auto(sessions.id()).unwrap();
+        assert_ne!(first, second);
+
+        db.execute(0, |tx| {
+            tx.write(id, vec![9])?;
+            Ok(())
+        }).unwrap();
+    }
+
+    #[test]
+    fn test_sequences_across_workers() {
+        let config = MaemioConfig {
+            thread_count: 3,
+            ..MaemioConfig::default()
+        };
+        let db = Maemio::with_config(config).unwrap();
+        db.create_sequence("ticket", 1, 1).unwrap();
+
+        let mut values: Vec<u64> = (0..3)
+            .flat_map(|thread_id| {
+                db.execute(thread_id, |tx| Ok([tx.next_value("ticket")?, tx.next_value("ticket")?])).unwrap()
+            })
+            .collect();
+        values.sort_unstable();
+        assert_eq!(values, (1..=6).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_tables() {
+        let db = Maemio::new().unwrap();
+        let users = db.create_table("users").unwrap();
+        let orders = db.create_table("orders").unwrap();
+
+        // Both tables can hold record 5
+        db.create_record_in(users.id(), 5).unwrap();
+        db.create_record_in(orders.id(), 5).unwrap();
+        db.create_index(users.id(), "by_name", IndexType::Hash).unwrap();
+
+        db.execute(0, |tx| {
+            tx.write_in(users.id(), 5, b"alice".to_vec())?;
+            tx.write_in(orders.id(), 5, b"order".to_vec())?;
+            Ok(())
+        }).unwrap();
+
+        db.execute(0, |tx| {
+            assert_eq!(tx.read_in(users.id(), 5)?.data, b"alice".to_vec());
+            assert_eq!(tx.read_in(orders.id(), 5)?.data, b"order".to_vec());
+            Ok(())
+        }).unwrap();
+
+        db.drop_table("users").unwrap();
+        assert!(db.get_table("users").is_err());
+        assert!(db.index_manager().get_index(users.id(), "by_name").is_err());
+        let key = KeyExtractor::function(|data| Some(IndexKey::Bytes(data.to_vec())));
+        assert!(db.create_index_with_key(users.id(), "by_name", IndexType::Hash, key).is_err());
+        assert!(db.execute(0, |tx| tx.read_in(users.id(), 5)).is_err());
+        assert_eq!(db.get_table("orders").unwrap().record_count(), 1);
+    }
+
+    #[test]
+    fn test_schema_rows() {
+        let db = Maemio::new().unwrap();
+        let schema = Schema::new(vec![
+            Column::new("name", ColumnType::String),
+            Column::nullable("age", ColumnType::Int),
+        ]).unwrap();
+        let people = db.create_table_with_schema("people", schema).unwrap();
+        db.create_record_in(people.id(), 1).unwrap();
+
+        let row = Row::new(vec![Value::String("ada".into()), Value::Int(36)]);
+        db.execute(0, |tx| tx.write_row(people.id(), 1, &row)).unwrap();
+        db.execute(0, |tx| {
+            assert_eq!(tx.read_row(people.id(), 1)?, row);
+            Ok(())
+        }).unwrap();
+
+        // Rows and raw bytes that do not match the schema are rejected on write
+        let result = db.execute(0, |tx| tx.write_row(people.id(), 1, &Row::new(vec![Value::Int(1), Value::Null])));
+        assert!(matches!(result, Err(MaemioError::SchemaViolation(_))));
+        let result = db.execute(0, |tx| tx.write_in(people.id(), 1, vec![0xFF]));
+        assert!(matches!(result, Err(MaemioError::SchemaViolation(_))));
+    }
+
+    #[test]
+    fn test_automatic_index_maintenance() {
+        let db = Maemio::new().unwrap();
+        let schema = Schema::new(vec![
+            Column::new("email", ColumnType::String),
+            Column::new("age", ColumnType::Int),
+        ]).unwrap();
+        let users = db.create_table_with_schema("users", schema).unwrap();
+        for id in 1..=3 {
+            db.create_record_in(users.id(), id).unwrap();
+        }
+        let user = |email: &str, age: i64| Row::new(vec![Value::String(email.into()), Value::Int(age)]);
+
+        // Record 1 exists before the index and is backfilled
+        db.execute(0, |tx| tx.write_row(users.id(), 1, &user("a@x", 30))).unwrap();
+        db.create_index_with_key(users.id(), "by_email", IndexType::Hash, KeyExtractor::column("email")).unwrap();
+        db.create_index_with_key(users.id(), "by_age", IndexType::BTree, KeyExtractor::column("age")).unwrap();
+
+        db.execute(0, |tx| {
+            tx.write_row(users.id(), 2, &user("b@x", 30))?;
+            tx.write_row(users.id(), 3, &user("c@x", 40))?;
+            Ok(())
+        }).unwrap();
+
+        let by_email = db.index_manager().get_index(users.id(), "by_email").unwrap();
+        let by_age = db.index_manager().get_index(users.id(), "by_age").unwrap();
+        db.execute(0, |tx| {
+            let ts = tx.get_timestamp();
+            assert_eq!(by_email.get(&IndexKey::String("a@x".into()), ts)?, Some(1));
+            assert_eq!(by_email.get(&IndexKey::String("c@x".into()), ts)?, Some(3));
+            assert_eq!(by_age.range_scan(&IndexKey::Int(30), &IndexKey::Int(30), ts)?, vec![1, 2]);
+            Ok(())
+        }).unwrap();
+
+        // Changing a key moves the entry; deleting removes it
+        db.execute(0, |tx| {
+            tx.write_row(users.id(), 2, &user("b2@x", 31))?;
+            tx.delete_in(users.id(), 3)?;
+            Ok(())
+        }).unwrap();
+
+        db.execute(0, |tx| {
+            let ts = tx.get_timestamp();
+            assert_eq!(by_email.get(&IndexKey::String("b@x".into()), ts)?, None);
+            assert_eq!(by_email.get(&IndexKey::String("b2@x".into()), ts)?, Some(2));
+            assert_eq!(by_email.get(&IndexKey::String("c@x".into()), ts)?, None);
+            assert_eq!(by_age.range_scan(&IndexKey::Int(0), &IndexKey::Int(100), ts)?, vec![1, 2]);
+            assert!(matches!(tx.read_in(users.id(), 3), Err(MaemioError::RecordNotFound(3))));
+            Ok(())
+        }).unwrap();
+
+        // Function extractors work on schemaless tables
+        let docs = db.create_table("docs").unwrap();
+        db.create_record_in(docs.id(), 1).unwrap();
+        db.create_index_with_key(docs.id(), "by_len", IndexType::BTree, KeyExtractor::function(|data| {
+            Some(IndexKey::Int(data.len() as i64))
+        })).unwrap();
+        db.execute(0, |tx| tx.write_in(docs.id(), 1, vec![0; 5])).unwrap();
+        let by_len = db.index_manager().get_index(docs.id(), "by_len").unwrap();
+        assert_eq!(by_len.get(&IndexKey::Int(5), u64::MAX).unwrap(), Some(1));
+
+        db.drop_index(docs.id(), "by_len").unwrap();
+        db.execute(0, |tx| tx.write_in(docs.id(), 1, vec![0; 6])).unwrap();
+        assert!(db.index_manager().get_index(docs.id(), "by_len").is_err());
+    }
+
+    #[test]
+    fn test_index_creation_races_commits() {
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+        let config = MaemioConfig {
+            thread_count: 3,
+            ..MaemioConfig::default()
+        };
+        let db = Maemio::with_config(config).unwrap();
+        let items = db.create_table("items").unwrap().id();
+        let inserted = AtomicUsize::new(0);
+        let created = AtomicBool::new(false);
+
+        // Writers insert before, during and after the index is created
+        let written: Vec<(u64, i64)> = std::thread::scope(|scope| {
+            let writers: Vec<_> = (1..3)
+                .map(|thread_id| {
+                    let (db, inserted, created) = (&db, &inserted, &created);
+                    scope.spawn(move || {
+                        let mut written = Vec::new();
+                        let mut after = 0;
+                        for i in 0.. {
+                            if created.load(Ordering::Acquire) {
+                                after += 1;
+                                if after > 20 {
+                                    break;
+                                }
+                            }
+                            let value = (thread_id * 100_000 + i) as i64;
+                            let id = db
+                                .execute(thread_id, |tx| tx.insert_in(items, value.to_le_bytes().to_vec()))
+                                .unwrap();
+                            written.push((id, value));
+                            inserted.fetch_add(1, Ordering::Release);
+                        }
+                        written
+                    })
+                })
+                .collect();
+
+            while inserted.load(Ordering::Acquire) < 50 {
+                std::thread::yield_now();
+            }
+            db.create_index_with_key(items, "by_value", IndexType::BTree, KeyExtractor::function(|data| {
+                Some(IndexKey::Int(i64::from_le_bytes(data.try_into().ok()?)))
+            })).unwrap();
+            created.store(true, Ordering::Release);
+
+            writers.into_iter().flat_map(|writer| writer.join().unwrap()).collect()
+        });
+
+        // Every record is indexed, whether the backfill or its commit added it
+        let by_value = db.index_manager().get_index(items, "by_value").unwrap();
+        for (id, value) in written {
+            assert_eq!(by_value.get_all(&IndexKey::Int(value), u64::MAX).unwrap(), vec![id]);
+        }
+    }
+
+    #[test]
+    fn test_unique_and_check_constraints() {
+        let db = Maemio::new().unwrap();
+        let schema = Schema::new(vec![
+            Column::new("email", ColumnType::String),
+            Column::new("nickname", ColumnType::String),
+            Column::new("age", ColumnType::Int),
+        ]).unwrap()
+            .with_constraint(Constraint::check("adult", |row| {
+                matches!(row.get(2), Some(Value::Int(age)) if *age >= 18)
+            })).unwrap();
+        let users = db.create_table_with_schema("users", schema).unwrap();
+        for id in 1..=3 {
+            db.create_record_in(users.id(), id).unwrap();
+        }
+        let user = |email: &str, age: i64| Row::new(vec![
+            Value::String(email.into()),
+            Value::String("nick".into()),
+            Value::Int(age),
+        ]);
+        db.create_unique_index(users.id(), "by_email", IndexType::Hash, KeyExtractor::column("email")).unwrap();
+
+        db.execute(0, |tx| tx.write_row(users.id(), 1, &user("a@x", 30))).unwrap();
+
+        // A second record with the same email aborts without side effects
+        let result = db.execute(0, |tx| tx.write_row(users.id(), 2, &user("a@x", 40)));
+        assert!(matches!(result, Err(MaemioError::UniqueViolation(_))));
+        let result = db.execute(0, |tx| tx.read_row(users.id(), 2));
+        assert!(matches!(result, Err(MaemioError::NoVisibleVersion)));
+
+        // Two records swapping emails in one transaction is fine
+        db.execute(0, |tx| tx.write_row(users.id(), 2, &user("b@x", 40))).unwrap();
+        db.execute(0, |tx| {
+            tx.write_row(users.id(), 1, &user("b@x", 30))?;
+            tx.write_row(users.id(), 2, &user("a@x", 40))
+        }).unwrap();
+        let by_email = db.index_manager().get_index(users.id(), "by_email").unwrap();
+        assert_eq!(by_email.get(&IndexKey::String("a@x".into()), u64::MAX).unwrap(), Some(2));
+
+        // Check constraints are enforced at commit, non-nullable columns on write
+        let result = db.execute(0, |tx| tx.write_row(users.id(), 3, &user("c@x", 12)));
+        assert!(matches!(result, Err(MaemioError::ConstraintViolation(_))));
+        let nameless = Row::new(vec![Value::String("c@x".into()), Value::Null, Value::Int(20)]);
+        let result = db.execute(0, |tx| tx.write_row(users.id(), 3, &nameless));
+        assert!(matches!(result, Err(MaemioError::SchemaViolation(_))));
+
+        // Existing duplicates prevent creating a unique index
+        let result = db.create_unique_index(users.id(), "by_age", IndexType::BTree, KeyExtractor::function(|_| {
+            Some(IndexKey::Int(0))
+        }));
+        assert!(matches!(result, Err(MaemioError::UniqueViolation(_))));
+        assert!(db.index_manager().get_index(users.id(), "by_age").is_err());
+    }
+
+    #[test]
+    fn test_foreign_keys() {
+        let db = Maemio::new().unwrap();
+        let authors = db.create_table("authors").unwrap();
+        let books = db.create_table_with_schema("books", Schema::new(vec![
+            Column::new("title", ColumnType::String),
+            Column::nullable("author", ColumnType::Int),
+        ]).unwrap()).unwrap();
+        let reviews = db.create_table_with_schema("reviews", Schema::new(vec![
+            Column::new("book", ColumnType::Int),
+        ]).unwrap()).unwrap();
+        for id in 1..=3 {
+            db.create_record_in(authors.id(), id).unwrap();
+            db.create_record_in(books.id(), id).unwrap();
+            db.create_record_in(reviews.id(), id).unwrap();
+        }
+        db.create_foreign_key("book_author", books.id(), "author", authors.id(), OnDelete::Restrict).unwrap();
+        db.create_foreign_key("review_book", reviews.id(), "book", books.id(), OnDelete::Cascade).unwrap();
+        let book = |author: Option<i64>| Row::new(vec![
+            Value::String("title".into()),
+            author.map_or(Value::Null, Value::Int),
+        ]);
+
+        // Children must point at an existing parent; null references are fine
+        let result = db.execute(0, |tx| tx.write_row(books.id(), 1, &book(Some(1))));
+        assert!(matches!(result, Err(MaemioError::ConstraintViolation(_))));
+        let result = db.execute(0, |tx| tx.write_row(books.id(), 1, &book(Some(-1))));
+        assert!(matches!(result, Err(MaemioError::ConstraintViolation(_))));
+        db.execute(0, |tx| {
+            tx.write_in(authors.id(), 1, b"ann".to_vec())?;
+            tx.write_row(books.id(), 1, &book(Some(1)))?;
+            tx.write_row(books.id(), 2, &book(None))
+        }).unwrap();
+
+        // Restrict blocks deleting a referenced author until the book moves away
+        let result = db.execute(0, |tx| tx.delete_in(authors.id(), 1));
+        assert!(matches!(result, Err(MaemioError::ConstraintViolation(_))));
+        db.execute(0, |tx| {
+            tx.write_row(books.id(), 1, &book(None))?;
+            tx.delete_in(authors.id(), 1)
+        }).unwrap();
+
+        // Cascade deletes the reviews of a deleted book
+        let review = |book: i64| Row::new(vec![Value::Int(book)]);
+        db.execute(0, |tx| {
+            tx.write_row(reviews.id(), 1, &review(1))?;
+            tx.write_row(reviews.id(), 2, &review(1))?;
+            tx.write_row(reviews.id(), 3, &review(2))
+        }).unwrap();
+        db.execute(0, |tx| tx.delete_in(books.id(), 1)).unwrap();
+        db.execute(0, |tx| {
+            assert!(matches!(tx.read_in(reviews.id(), 1), Err(MaemioError::RecordNotFound(1))));
+            assert!(matches!(tx.read_in(reviews.id(), 2), Err(MaemioError::RecordNotFound(2))));
+            assert!(tx.read_row(reviews.id(), 3).is_ok());
+            Ok(())
+        }).unwrap();
+
+        // Foreign keys are recorded in the catalog
+        let foreign_keys = || db.catalog_entries_of(ObjectKind::ForeignKey, |_| true).unwrap();
+        assert_eq!(foreign_keys()[1].definition, format!("book REFERENCES {} ON DELETE Cascade", books.id()));
+
+        // Referenced tables cannot be dropped while the foreign key exists
+        assert!(db.drop_table("books").is_err());
+        db.drop_foreign_key(reviews.id(), "review_book").unwrap();
+        assert!(db.drop_table("books").is_ok());
+        assert!(foreign_keys().is_empty());
+    }
+
+    #[test]
+    fn test_transactional_ddl_and_catalog() {
+        let db = Maemio::new().unwrap();
+        let names = |entries: Vec<CatalogEntry>| entries.into_iter().map(|e| e.name).collect::<Vec<_>>();
+        assert_eq!(names(db.list_tables().unwrap()), vec!["default", "__catalog"]);
+
+        // A table, its index and its first rows commit together
+        let schema = Schema::new(vec![Column::new("sku", ColumnType::String)]).unwrap();
+        let items = db.execute(0, |tx| {
+            let items = tx.create_table_with_schema("items", schema.clone())?;
+            tx.create_unique_index(items.id(), "by_sku", IndexType::Hash, KeyExtractor::column("sku"))?;
+            tx.create_record_in(items.id(), 1)?;
+            tx.write_row(items.id(), 1, &Row::new(vec![Value::String("a".into())]))?;
+            tx.create_sequence("item_ids", 10, 5)?;
+            Ok(items)
+        }).unwrap();
+        let by_sku = db.index_manager().get_index(items.id(), "by_sku").unwrap();
+        assert_eq!(by_sku.get(&IndexKey::String("a".into()), u64::MAX).unwrap(), Some(1));
+        assert_eq!(db.list_indexes(items.id()).unwrap()[0].definition, "Hash UNIQUE ON sku");
+        assert_eq!(db.list_sequences().unwrap()[0].definition, "START 10 INCREMENT 5");
+        assert_eq!(db.list_tables().unwrap()[2].definition, "sku String");
+
+        // A failing commit rolls back every schema change it carried
+        let result = db.execute(0, |tx| {
+            let orders = tx.create_table_with_schema("orders", schema.clone())?;
+            tx.create_unique_index(orders.id(), "by_sku", IndexType::BTree, KeyExtractor::column("sku"))?;
+            tx.drop_sequence("item_ids")?;
+            for id in 1..=2 {
+                tx.create_record_in(orders.id(), id)?;
+                tx.write_row(orders.id(), id, &Row::new(vec![Value::String("dup".into())]))?;
+            }
+            Ok(())
+        });
+        assert!(matches!(result, Err(MaemioError::UniqueViolation(_))));
+        assert!(db.get_table("orders").is_err());
+        assert!(db.sequences.get_sequence("item_ids").is_ok());
+        assert_eq!(names(db.list_tables().unwrap()), vec!["default", "__catalog", "items"]);
+
+        // Drops only take effect on commit
+        let mut tx = db.begin_transaction(0);
+        tx.drop_table("items").unwrap();
+        assert!(tx.drop_table("items").is_err());
+        assert!(tx.catalog_entries().unwrap().iter().all(|entry| entry.table_id != Some(items.id())));
+        drop(tx);
+        assert!(db.get_table("items").is_ok());
+
+        // Objects a transaction creates stay invisible to others until it
+        // commits, and an abort leaves no catalog rows behind
+        let catalog_rows = db.tables.catalog_table().record_count();
+        let mut tx = db.begin_transaction(0);
+        tx.create_table("staged").unwrap();
+        assert!(db.get_table("staged").is_err());
+        assert!(names(db.list_tables().unwrap()).iter().all(|name| name != "staged"));
+        drop(tx);
+        assert_eq!(db.tables.catalog_table().record_count(), catalog_rows);
+
+        db.drop_table("items").unwrap();
+        assert!(db.get_table("items").is_err());
+        assert!(db.index_manager().get_index(items.id(), "by_sku").is_err());
+        assert!(db.list_indexes(items.id()).unwrap().is_empty());
+        assert!(db.drop_table("__catalog").is_err());
+    }
+
+    #[test]
+    fn test_partial_column_updates() {
+        let db = Maemio::new().unwrap();
+        let docs = db.create_table_with_schema("docs", Schema::new(vec![
+            Column::new("title", ColumnType::String),
+            Column::new("body", ColumnType::Bytes),
+            Column::new("views", ColumnType::Int),
+        ]).unwrap()).unwrap();
+        db.create_record_in(docs.id(), 1).unwrap();
+        db.create_index_with_key(docs.id(), "by_views", IndexType::BTree, KeyExtractor::column("views")).unwrap();
+
+        db.execute(0, |tx| tx.write_row(docs.id(), 1, &Row::new(vec![
+            Value::String("draft".into()),
+            Value::Bytes(vec![7; 4096]),
+            Value::Int(0),
+        ]))).unwrap();
+        let written = db.execute(0, |tx| Ok(tx.read_in(docs.id(), 1)?.wts)).unwrap();
+
+        db.execute(0, |tx| {
+            let views = tx.read_columns(docs.id(), 1, &["views"])?;
+            let Value::Int(views) = views[0] else { unreachable!() };
+            tx.update_columns(docs.id(), 1, &[("views", Value::Int(views + 1))])
+        }).unwrap();
+
+        // The updated version keeps only the old value of the changed column
+        let record = docs.get_record(1).unwrap();
+        assert!(record.stored_bytes() < 4096 + 100);
+        let old = record.find_visible_version(written).unwrap().unwrap();
+        let schema = docs.schema().unwrap();
+        assert_eq!(schema.decode_columns(&old.data, &["views"]).unwrap(), vec![Value::Int(0)]);
+        assert_eq!(schema.decode_row(&old.data).unwrap().get(1), Some(&Value::Bytes(vec![7; 4096])));
+
+        db.execute(0, |tx| {
+            assert_eq!(
+                tx.read_columns(docs.id(), 1, &["views", "title"])?,
+                vec![Value::Int(1), Value::String("draft".into())]
+            );
+            assert_eq!(tx.read_row(docs.id(), 1)?.get(1), Some(&Value::Bytes(vec![7; 4096])));
+            assert!(tx.update_columns(docs.id(), 1, &[("views", Value::Null)]).is_err());
+            assert!(tx.read_columns(docs.id(), 1, &["missing"]).is_err());
+            Ok(())
+        }).unwrap();
+
+        // Index maintenance sees column updates
+        let by_views = db.index_manager().get_index(docs.id(), "by_views").unwrap();
+        assert_eq!(by_views.get(&IndexKey::Int(1), u64::MAX).unwrap(), Some(1));
+        assert_eq!(by_views.get(&IndexKey::Int(0), u64::MAX).unwrap(), None);
+    }
+
+    #[test]
+    fn test_delta_encoded_versions() {
+        let db = Maemio::with_config(MaemioConfig {
+            delta_threshold: Some(4096),
+            ..MaemioConfig::default()
+        }).unwrap();
+        let blobs = db.create_table("blobs").unwrap();
+        assert_eq!(blobs.delta_threshold(), Some(4096));
+        db.create_record_in(blobs.id(), 1).unwrap();
+
+        let mut timestamps = Vec::new();
+        for i in 0..20u8 {
+            db.execute(0, |tx| {
+                let mut data = vec![0u8; 64 << 10];
+                data[i as usize] = i + 1;
+                tx.write_in(blobs.id(), 1, data)
+            }).unwrap();
+            timestamps.push(db.execute(0, |tx| Ok(tx.read_in(blobs.id(), 1)?.wts)).unwrap());
+        }
+
+        let record = blobs.get_record(1).unwrap();
+        assert_eq!(record.version_count(), 20);
+        assert!(record.stored_bytes() < 2 * (64 << 10));
+        for (i, ts) in timestamps.into_iter().enumerate() {
+            let version = record.find_visible_version(ts).unwrap().unwrap();
+            assert_eq!(version.data[i], i as u8 + 1);
+            assert_eq!(version.data.iter().filter(|&&b| b != 0).count(), 1);
+        }
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_compressed_values() {
+        let db = Maemio::new().unwrap();
+        let docs = db.create_table("docs").unwrap();
+        docs.set_compression(Compression::Lz4);
+        db.create_record_in(docs.id(), 1).unwrap();
+        db.create_record_in(docs.id(), 2).unwrap();
+
+        let json = br#"{"user":"ada","roles":["admin","dev"],"active":true}"#.repeat(40);
+        db.execute(0, |tx| {
+            tx.write_in(docs.id(), 1, json.clone())?;
+            tx.write_in(docs.id(), 2, b"{}".to_vec())
+        }).unwrap();
+
+        let record = docs.get_record(1).unwrap();
+        assert!(record.stored_bytes() < json.len() / 4);
+        db.execute(0, |tx| {
+            let first = tx.read_in(docs.id(), 1)?;
+            assert_eq!(first.data, json);
+            assert_eq!(tx.read_in(docs.id(), 2)?.data, b"{}");
+            // Decompressed once per transaction
+            assert!(Arc::ptr_eq(&tx.read_in(docs.id(), 1)?, &first));
+            Ok(())
+        }).unwrap();
+
+        // The small value was neither considered nor compressed
+        let stats = docs.compression_stats();
+        assert_eq!((stats.values, stats.compressed_values), (1, 1));
+        assert_eq!(stats.raw_bytes, json.len() as u64);
+        assert!(stats.ratio() > 4.0);
+        assert!(docs.get_record(2).unwrap().inline_wts().is_some());
+
+        // Values of failed commits are not counted
+        docs.set_memory_quota(Some(docs.memory_usage().total_bytes()));
+        let result = db.execute(0, |tx| tx.write_in(docs.id(), 1, json.repeat(2)));
+        assert!(matches!(result, Err(MaemioError::QuotaExceeded(_))));
+        assert_eq!(docs.compression_stats().values, 1);
+    }
+
+    #[test]
+    fn test_blobs() {
+        use std::io::Read;
+
+        let db = Maemio::new().unwrap();
+        let files = db.create_table("files").unwrap();
+        db.create_record_in(files.id(), 1).unwrap();
+        db.create_index_with_key(files.id(), "by_len", IndexType::BTree, KeyExtractor::function(|data| {
+            Some(IndexKey::Int(data.len() as i64))
+        })).unwrap();
+
+        let original: Vec<u8> = (0..3 * BLOB_CHUNK_SIZE + 100).map(|i| (i % 253) as u8).collect();
+        db.execute(0, |tx| tx.write_blob(files.id(), 1, &original)).unwrap();
+        // The four chunks are not records of the table
+        assert_eq!(files.record_count(), 1);
+        assert_eq!(files.memory_usage().records, 5);
+        // Nor are they indexed
+        let by_len = db.index_manager().get_index(files.id(), "by_len").unwrap();
+        assert!(by_len.get_all(&IndexKey::Int(BLOB_CHUNK_SIZE as i64), u64::MAX).unwrap().is_empty());
+        db.execute(0, |tx| {
+            assert_eq!(tx.scan(files.id())?.map(|(record_id, _)| record_id).collect::<Vec<_>>(), vec![1]);
+            assert!(tx.read_blob_range(files.id(), 1, u64::MAX, 2).is_err());
+            assert!(tx.write_blob_range(files.id(), 1, u64::MAX - 1, b"ab").is_err());
+            Ok(())
+        }).unwrap();
+
+        let mut snapshot = db.begin_transaction(0);
+        db.execute(0, |tx| {
+            assert_eq!(tx.read_blob(files.id(), 1)?, original);
+            let boundary = BLOB_CHUNK_SIZE as u64 - 2;
+            assert_eq!(tx.read_blob_range(files.id(), 1, boundary, 4)?, &original[boundary as usize..][..4]);
+            assert!(tx.read_blob_range(files.id(), 1, original.len() as u64, 10)?.is_empty());
+
+            // Patch across a chunk boundary and extend past the end with a gap
+            tx.write_blob_range(files.id(), 1, boundary, b"abcd")?;
+            tx.write_blob_range(files.id(), 1, original.len() as u64 + 5, b"tail")
+        }).unwrap();
+
+        let mut expected = original.clone();
+        let boundary = BLOB_CHUNK_SIZE - 2;
+        expected[boundary..boundary + 4].copy_from_slice(b"abcd");
+        expected.extend_from_slice(&[0; 5]);
+        expected.extend_from_slice(b"tail");
+        db.execute(0, |tx| {
+            let mut streamed = Vec::new();
+            let mut reader = tx.blob_reader(files.id(), 1)?;
+            assert_eq!(reader.len(), expected.len() as u64);
+            reader.read_to_end(&mut streamed).unwrap();
+            assert_eq!(streamed, expected);
+            Ok(())
+        }).unwrap();
+
+        // A transaction that started earlier still reads the old chunks
+        assert_eq!(snapshot.read_blob(files.id(), 1).unwrap(), original);
+        drop(snapshot);
+
+        // Shrinking drops the chunks that are no longer needed
+        db.execute(0, |tx| tx.write_blob(files.id(), 1, b"small")).unwrap();
+        db.execute(0, |tx| {
+            assert_eq!(tx.read_blob(files.id(), 1)?, b"small");
+            tx.delete_blob(files.id(), 1)
+        }).unwrap();
+        db.execute(0, |tx| {
+            assert!(tx.read_blob(files.id(), 1).is_err());
+            // Plain values are not mistaken for blobs, even when they look
+            // like a manifest
+            tx.write_in(files.id(), 1, vec![5, 64, 1, 2])?;
+            assert!(tx.blob_len(files.id(), 1).is_err());
+            Ok(())
+        }).unwrap();
+
+        // Dropped chunks leave the table once no snapshot can read them
+        db.gc.as_ref().unwrap().collect_garbage().unwrap();
+        assert_eq!(files.memory_usage().records, 1);
+    }
+
+    #[test]
+    fn test_memory_accounting_and_quotas() {
+        let db = Maemio::new().unwrap();
+        let users = db.create_table_with_schema("users", Schema::new(vec![
+            Column::new("name", ColumnType::String),
+            Column::new("bio", ColumnType::Bytes),
+        ]).unwrap()).unwrap();
+        db.create_index_with_key(users.id(), "by_name", IndexType::Hash, KeyExtractor::column("name")).unwrap();
+        let row = |name: &str| Row::new(vec![Value::String(name.into()), Value::Bytes(vec![0; 1000])]);
+
+        for id in 1..=3 {
+            db.create_record_in(users.id(), id).unwrap();
+            db.execute(0, |tx| tx.write_row(users.id(), id, &row(&format!("user{}", id)))).unwrap();
+        }
+        let usage = users.memory_usage();
+        assert_eq!((usage.records, usage.versions), (3, 3));
+        assert!(usage.data_bytes > 3000 && usage.data_bytes < 3100);
+        assert!(usage.version_overhead_bytes > 0 && usage.record_bytes > 0);
+        assert_eq!(users.index_memory_usage()[0].1, usage.index_bytes);
+        assert!(usage.index_bytes > 0);
+
+        // Room for roughly one more version
+        db.create_record_in(users.id(), 4).unwrap();
+        db.create_record_in(users.id(), 5).unwrap();
+        users.set_memory_quota(Some(users.memory_usage().total_bytes() + 1500));
+        db.execute(0, |tx| tx.write_row(users.id(), 4, &row("user4"))).unwrap();
+        let result = db.execute(0, |tx| tx.write_row(users.id(), 5, &row("user5")));
+        assert!(matches!(result, Err(MaemioError::QuotaExceeded(_))));
+        assert_eq!(users.memory_usage().versions, 4);
+
+        // Deletes go through, and collecting the superseded versions frees room
+        db.execute(0, |tx| tx.delete_in(users.id(), 1)).unwrap();
+        let record = users.get_record(1).unwrap();
+        assert_eq!(record.collect_garbage(u64::MAX), Some(1));
+        assert!(users.memory_usage().data_bytes < 3100);
+        db.execute(0, |tx| tx.write_row(users.id(), 5, &row("user5"))).unwrap();
+
+        users.set_memory_quota(None);
+        db.execute(0, |tx| tx.write_row(users.id(), 1, &row("user1"))).unwrap();
+    }
+
+    #[test]
+    fn test_gc_returns_payloads_to_arenas() {
+        let db = Maemio::with_config(MaemioConfig { thread_count: 1, ..Default::default() }).unwrap();
+        db.create_record(1).unwrap();
+        for i in 0..5u8 {
+            db.execute(0, |tx| tx.write_slice(DEFAULT_TABLE_ID, 1, &[i; 1000])).unwrap();
+        }
+        // A later transaction moves the oldest readable timestamp forward
+        db.execute(0, |tx| tx.read(1).map(|_| ())).unwrap();
+
+        let gc = db.gc.as_ref().unwrap();
+        gc.clone().collect_garbage().unwrap();
+        let record = db.tables.default_table().get_record(1).unwrap();
+        assert!(record.version_count() < 5);
+
+        for _ in 0..1000 {
+            if db.arena_stats(0).recycled > 0 {
+                break;
+            }
+            crossbeam_epoch::pin().flush();
+        }
+        assert!(db.arena_stats(0).recycled > 0);
+
+        let hits = db.arena_stats(0).hits;
+        db.execute(0, |tx| tx.write_slice(DEFAULT_TABLE_ID, 1, &[9; 1000])).unwrap();
+        assert_eq!(db.arena_stats(0).hits, hits + 1);
+    }
+
+    #[test]
+    fn test_gc_keeps_versions_of_active_transactions() {
+        let db = Maemio::with_config(MaemioConfig { thread_count: 1, ..Default::default() }).unwrap();
+        db.create_record(1).unwrap();
+        db.execute(0, |tx| tx.write_slice(DEFAULT_TABLE_ID, 1, &[1; 100])).unwrap();
+
+        // An older transaction on the same thread outlives later writes
+        let mut snapshot = db.begin_transaction(0);
+        for i in 2..6u8 {
+            db.execute(0, |tx| tx.write_slice(DEFAULT_TABLE_ID, 1, &[i; 100])).unwrap();
+        }
+        db.gc.as_ref().unwrap().collect_garbage().unwrap();
+        assert_eq!(snapshot.read(1).unwrap().data, vec![1; 100]);
+        drop(snapshot);
+
+        db.gc.as_ref().unwrap().collect_garbage().unwrap();
+        let record = db.tables.default_table().get_record(1).unwrap();
+        assert!(record.version_count() < 5);
+    }
+
+    #[test]
+    fn test_records_expire() {
+        let db = Maemio::new().unwrap();
+        let gc = db.gc.as_ref().unwrap();
+        db.create_record(1).unwrap();
+        db.create_record(2).unwrap();
+        let ttl = std::time::Duration::from_millis(50);
+        db.execute(0, |tx| {
+            tx.write_with_ttl(1, b"session".to_vec(), ttl)?;
+            tx.write_with_ttl(2, b"renewed".to_vec(), ttl)
+        }).unwrap();
+        assert_eq!(db.execute(0, |tx| tx.read(1)).unwrap().data, b"session");
+
+        // Writing again without a time to live keeps the record
+        db.execute(0, |tx| tx.write(2, b"kept".to_vec())).unwrap();
+        std::thread::sleep(ttl * 2);
+        assert!(matches!(db.execute(0, |tx| tx.read(1)), Err(MaemioError::RecordNotFound(1))));
+
+        assert_eq!(gc.sweep_expired().unwrap(), 1);
+        let record = db.tables.default_table().get_record(1).unwrap();
+        assert!(record.find_visible_version(u64::MAX).unwrap().unwrap().is_tombstone());
+        assert_eq!(db.execute(0, |tx| tx.read(2)).unwrap().data, b"kept");
+        assert_eq!(gc.sweep_expired().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_expiry_follows_commit_time() {
+        let db = Maemio::new().unwrap();
+        let gc = db.gc.as_ref().unwrap();
+        db.create_record(1).unwrap();
+        let ttl = std::time::Duration::from_millis(50);
+
+        // The deadline counts from the commit, not from the write
+        let mut tx = db.begin_transaction(0);
+        tx.write_with_ttl(1, b"session".to_vec(), ttl).unwrap();
+        std::thread::sleep(ttl * 2);
+        tx.commit().unwrap();
+        drop(tx);
+
+        // A transaction started before the deadline keeps reading the value
+        let mut reader = db.begin_transaction(0);
+        assert_eq!(reader.read(1).unwrap().data, b"session");
+        std::thread::sleep(ttl * 2);
+        assert_eq!(reader.read_in(DEFAULT_TABLE_ID, 1).unwrap().data, b"session");
+        assert!(reader.scan(DEFAULT_TABLE_ID).unwrap().any(|(id, _)| id == 1));
+        drop(reader);
+
+        // Deadlines of transactions committed outside `execute` are swept too
+        assert_eq!(gc.sweep_expired().unwrap(), 1);
+        assert!(matches!(db.execute(0, |tx| tx.read(1)), Err(MaemioError::RecordNotFound(1))));
+    }
+    #[test]
+    fn test_table_scans() {
+        let db = Maemio::new().unwrap();
+        let events = db.create_table("events").unwrap();
+        for id in 1..=100u64 {
+            db.create_record_in(events.id(), id).unwrap();
+        }
+        db.execute(0, |tx| {
+            for id in 1..=100u64 {
+                tx.write_in(events.id(), id, id.to_le_bytes().to_vec())?;
+            }
+            tx.delete_in(events.id(), 7)
+        }).unwrap();
+        let value = |version: &data::Version| u64::from_le_bytes(version.data[..8].try_into().unwrap());
+
+        let mut tx = db.begin_transaction(0);
+        tx.write_in(events.id(), 1, 1000u64.to_le_bytes().to_vec()).unwrap();
+        // Records the transaction inserts are scanned before they commit
+        let inserted = tx.insert_in(events.id(), 500u64.to_le_bytes().to_vec()).unwrap();
+        let mut ids: Vec<u64> = tx.scan(events.id()).unwrap().map(|(id, _)| id).collect();
+        ids.sort_unstable();
+        assert_eq!(ids.len(), 100);
+        assert!(!ids.contains(&7));
+        assert!(ids.contains(&inserted));
+        let (last, _) = tx.scan_range(events.id(), ..).unwrap().next_back().unwrap();
+        assert_eq!(last, inserted);
+
+        let large: Vec<u64> = tx.scan_where(events.id(), |_, version| value(version) > 95)
+            .unwrap()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(large.len(), 7);
+
+        let sums = tx.par_scan(events.id(), 4, |scan| scan.map(|(_, version)| value(&version)).sum::<u64>()).unwrap();
+        assert_eq!(sums.len(), 4);
+        assert_eq!(sums.iter().sum::<u64>(), (1..=100).sum::<u64>() - 7 - 1 + 1000 + 500);
+        let counts = tx.par_scan_where(events.id(), 3, |_, version| value(version) > 95, |scan| scan.count()).unwrap();
+        assert_eq!(counts.iter().sum::<usize>(), 7);
+        tx.commit().unwrap();
+        assert_eq!(db.execute(0, |tx| tx.read_in(events.id(), 1)).unwrap().data, 1000u64.to_le_bytes());
+        assert_eq!(db.execute(0, |tx| tx.read_in(events.id(), inserted)).unwrap().data, 500u64.to_le_bytes());
+    }
+
+    #[test]
+    fn test_scans_detect_phantoms() {
+        let db = Maemio::new().unwrap();
+        let events = db.create_table("events").unwrap();
+        db.execute(0, |tx| {
+            for id in 1..=10u64 {
+                tx.insert_in(events.id(), vec![id as u8])?;
+            }
+            Ok(())
+        }).unwrap();
+        let insert = |older: &mut Transaction, value: u8| {
+            older.insert_in(events.id(), vec![value]).unwrap();
+            older.commit().unwrap();
+        };
+
+        // An older transaction inserting into a range a newer one scanned
+        // completely makes the newer one conflict
+        let mut older = db.begin_transaction(0);
+        let mut tx = db.begin_transaction(0);
+        assert_eq!(tx.scan(events.id()).unwrap().count(), 10);
+        insert(&mut older, 11);
+        tx.write_in(events.id(), 1, vec![0]).unwrap();
+        assert!(matches!(tx.commit(), Err(MaemioError::Conflict)));
+        drop((older, tx));
+
+        // Inserts outside a scanned range, or past where a scan stopped, do not
+        let mut older = db.begin_transaction(0);
+        let mut tx = db.begin_transaction(0);
+        assert_eq!(tx.scan_range(events.id(), 1..=5).unwrap().count(), 5);
+        assert_eq!(tx.scan_range(events.id(), 6..).unwrap().take(2).count(), 2);
+        insert(&mut older, 12);
+        tx.write_in(events.id(), 1, vec![0]).unwrap();
+        tx.commit().unwrap();
+        drop((older, tx));
+
+        // The same holds for tables covered by every partition of a parallel scan
+        let mut older = db.begin_transaction(0);
+        let mut tx = db.begin_transaction(0);
+        let counts = tx.par_scan(events.id(), 3, |scan| scan.count()).unwrap();
+        assert_eq!(counts.iter().sum::<usize>(), 12);
+        insert(&mut older, 13);
+        assert!(matches!(tx.commit(), Err(MaemioError::Conflict)));
+
+        // Transactions newer than the insert see the record, so they serialize
+        // after it
+        let mut tx = db.begin_transaction(0);
+        assert_eq!(tx.scan(events.id()).unwrap().count(), 13);
+        tx.commit().unwrap();
+    }
+
+    #[test]
+    fn test_record_id_ranges() {
+        let db = Maemio::new().unwrap();
+        let orders = db.create_table("orders").unwrap();
+        for id in (0..50u64).rev() {
+            db.create_record_in(orders.id(), id * 100).unwrap();
+        }
+        db.execute(0, |tx| {
+            for id in 0..50u64 {
+                tx.write_in(orders.id(), id * 100, vec![id as u8])?;
+            }
+            Ok(())
+        }).unwrap();
+        let mut snapshot = db.begin_transaction(0);
+        db.execute(0, |tx| tx.delete_in(orders.id(), 1200)).unwrap();
+
+        let mut tx = db.begin_transaction(0);
+        let ids: Vec<u64> = tx.scan_range(orders.id(), 1000..2000).unwrap().map(|(id, _)| id).collect();
+        assert_eq!(ids, vec![1000, 1100, 1300, 1400, 1500, 1600, 1700, 1800, 1900]);
+        let last: Vec<u64> = tx.scan_range(orders.id(), ..).unwrap().rev().take(2).map(|(id, _)| id).collect();
+        assert_eq!(last, vec![4900, 4800]);
+        let reversed = (std::ops::Bound::Included(2000), std::ops::Bound::Excluded(1000));
+        assert_eq!(tx.scan_range(orders.id(), reversed).unwrap().count(), 0);
+
+        // Older snapshots still see the deleted record
+        let (id, version) = snapshot.scan_range(orders.id(), 1150..=1200).unwrap().next().unwrap();
+        assert_eq!((id, version.data.as_slice()), (1200, &[12u8][..]));
+    }
+
+    #[test]
+    fn test_typed_maps() {
+        let db = Maemio::new().unwrap();
+        let scores = db.open_map::<(String, u32), i64>("scores").unwrap();
+        db.execute(0, |tx| {
+            for (player, round, score) in [("bob", 2, 7), ("alice", 1, 10), ("bob", 1, -3), ("carol", 1, 4)] {
+                scores.put(tx, &(player.to_string(), round), &score)?;
+            }
+            Ok(())
+        }).unwrap();
+
+        let key = |player: &str, round| (player.to_string(), round);
+        let mut tx = db.begin_transaction(0);
+        assert_eq!(scores.get(&mut tx, &key("bob", 1)).unwrap(), Some(-3));
+        assert_eq!(scores.get(&mut tx, &key("dave", 1)).unwrap(), None);
+        let bobs: Vec<_> = scores.range(&mut tx, key("bob", 0)..key("bob", u32::MAX)).unwrap().collect::<Result<_>>().unwrap();
+        assert_eq!(bobs, vec![(key("bob", 1), -3), (key("bob", 2), 7)]);
+        let after_alice = scores.range(&mut tx, (std::ops::Bound::Excluded(key("alice", 1)), std::ops::Bound::Unbounded)).unwrap();
+        assert_eq!(after_alice.count(), 3);
+        let mut first = scores.range(&mut tx, ..).unwrap();
+        assert_eq!(first.next().unwrap().unwrap(), (key("alice", 1), 10));
+        drop(first);
+        tx.commit().unwrap();
+
+        // Reopening finds the same entries; removals are visible to later snapshots only
+        let reopened = db.open_map::<(String, u32), i64>("scores").unwrap();
+        let mut snapshot = db.begin_transaction(0);
+        assert!(db.execute(0, |tx| reopened.remove(tx, &key("alice", 1))).unwrap());
+        assert!(!db.execute(0, |tx| reopened.remove(tx, &key("alice", 1))).unwrap());
+        assert_eq!(reopened.get(&mut snapshot, &key("alice", 1)).unwrap(), Some(10));
+        assert_eq!(db.execute(0, |tx| reopened.range(tx, ..)?.collect::<Result<Vec<_>>>()).unwrap().len(), 3);
+
+        db.execute(0, |tx| reopened.put(tx, &key("alice", 1), &11)).unwrap();
+        assert_eq!(db.execute(0, |tx| reopened.get(tx, &key("alice", 1))).unwrap(), Some(11));
+        db.create_table("plain").unwrap();
+        assert!(db.open_map::<String, String>("plain").is_err());
+    }
+
+    #[test]
+    fn test_map_keys_follow_commits() {
+        let db = Maemio::new().unwrap();
+        let sessions = db.open_map::<String, u64>("sessions").unwrap();
+        let table = db.tables.get_table(sessions.table_id()).unwrap();
+        let primary = db.index_manager.get_index(sessions.table_id(), map::MAP_PRIMARY_INDEX).unwrap();
+        let key = |name: &str| name.to_string();
+
+        // A transaction sees its own new keys; an abort leaves nothing behind
+        let mut tx = db.begin_transaction(0);
+        sessions.put(&mut tx, &key("a"), &1).unwrap();
+        sessions.put(&mut tx, &key("a"), &2).unwrap();
+        assert_eq!(sessions.get(&mut tx, &key("a")).unwrap(), Some(2));
+        assert_eq!(sessions.range(&mut tx, ..).unwrap().collect::<Result<Vec<_>>>().unwrap(), vec![(key("a"), 2)]);
+        drop(tx);
+        assert_eq!(table.record_count(), 0);
+        assert_eq!(primary.memory_usage(), 0);
+
+        // Removing an entry removes its key from the index once no snapshot
+        // can see it anymore
+        db.execute(0, |tx| sessions.put(tx, &key("a"), &1)).unwrap();
+        let mut snapshot = db.begin_transaction(0);
+        assert!(db.execute(0, |tx| sessions.remove(tx, &key("a"))).unwrap());
+        assert_eq!(primary.memory_usage(), 0);
+        assert_eq!(sessions.get(&mut snapshot, &key("a")).unwrap(), Some(1));
+        assert_eq!(sessions.range(&mut snapshot, ..).unwrap().collect::<Result<Vec<_>>>().unwrap(), vec![(key("a"), 1)]);
+        drop(snapshot);
+        db.execute(0, |tx| sessions.put(tx, &key("a"), &3)).unwrap();
+        db.gc.as_ref().unwrap().collect_garbage().unwrap();
+        let index = table.indexes().pop().unwrap();
+        assert!(index.removed_records(&IndexKey::Bytes(b"a".to_vec())).is_empty());
+        assert_eq!(db.execute(0, |tx| sessions.get(tx, &key("a"))).unwrap(), Some(3));
+
+        // A key found absent that an older transaction then puts is a conflict
+        let mut older = db.begin_transaction(0);
+        let mut tx = db.begin_transaction(0);
+        assert_eq!(sessions.get(&mut tx, &key("b")).unwrap(), None);
+        sessions.put(&mut older, &key("b"), &1).unwrap();
+        older.commit().unwrap();
+        sessions.put(&mut tx, &key("c"), &1).unwrap();
+        assert!(matches!(tx.commit(), Err(MaemioError::Conflict)));
+        drop((older, tx));
+
+        // So is putting a key another transaction claimed first
+        let mut first = db.begin_transaction(0);
+        let mut second = db.begin_transaction(0);
+        sessions.put(&mut first, &key("d"), &1).unwrap();
+        sessions.put(&mut second, &key("d"), &2).unwrap();
+        second.commit().unwrap();
+        assert!(matches!(first.commit(), Err(MaemioError::Conflict)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_typed_values() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Account {
+            owner: String,
+            balance: i64,
+            tags: Vec<String>,
+        }
+
+        let db = Maemio::new().unwrap();
+        db.create_record(1).unwrap();
+        db.create_record(2).unwrap();
+        let account = Account { owner: "ada".into(), balance: 120, tags: vec!["vip".into()] };
+        db.execute(0, |tx| {
+            tx.put_typed(1, &account)?;
+            tx.put_encoded::<Json, _>(DEFAULT_TABLE_ID, 2, &account)
+        }).unwrap();
+
+        assert_eq!(db.execute(0, |tx| tx.get_typed::<Account>(1)).unwrap(), account);
+        let json = db.execute(0, |tx| tx.read(2)).unwrap();
+        assert!(json.data.starts_with(br#"{"owner":"ada""#));
+        assert_eq!(db.execute(0, |tx| tx.get_encoded::<Json, Account>(DEFAULT_TABLE_ID, 2)).unwrap(), account);
+
+        // Reading a value with the wrong type or codec reports a decode error
+        assert!(matches!(db.execute(0, |tx| tx.get_typed::<Account>(2)), Err(MaemioError::Decode(_))));
+        assert!(matches!(db.execute(0, |tx| tx.get_typed::<[u64; 16]>(1)), Err(MaemioError::Decode(_))));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_documents() {
+        use serde_json::json;
+
+        let db = Maemio::new().unwrap();
+        let docs = db.create_table("docs").unwrap();
+        for id in 1..=3 {
+            db.create_record_in(docs.id(), id).unwrap();
+        }
+        db.execute(0, |tx| {
+            tx.write_document_in(docs.id(), 1, &json!({"user": {"email": "a@x.io"}, "status": "active"}).into())?;
+            tx.write_document_in(docs.id(), 2, &json!({"user": {"email": "b@x.io"}, "status": "closed"}).into())?;
+            tx.write_in(docs.id(), 3, b"not json".to_vec())
+        }).unwrap();
+        db.create_path_index(docs.id(), "by_status", IndexType::Hash, "$.status").unwrap();
+        let entries = db.list_indexes(docs.id()).unwrap();
+        assert_eq!(entries[0].definition, "Hash ON $.status");
+
+        let email = db.execute(0, |tx| tx.get_path_in(docs.id(), 1, "$.user.email")).unwrap();
+        assert_eq!(email, Some(json!("a@x.io")));
+        assert!(matches!(db.execute(0, |tx| tx.read_document_in(docs.id(), 3)), Err(MaemioError::Decode(_))));
+
+        // Partial updates keep the rest of the document and maintain the index
+        db.execute(0, |tx| {
+            tx.set_path_in(docs.id(), 2, "$.status", json!("active"))?;
+            tx.set_path_in(docs.id(), 2, "$.user.name", json!("Bea"))?;
+            tx.remove_path_in(docs.id(), 1, "$.user.email").map(|_| ())
+        }).unwrap();
+        let doc = db.execute(0, |tx| tx.read_document_in(docs.id(), 2)).unwrap();
+        assert_eq!(doc.value(), &json!({"user": {"email": "b@x.io", "name": "Bea"}, "status": "active"}));
+
+        db.execute(0, |tx| tx.set_path_in(docs.id(), 1, "$.status", json!("closed"))).unwrap();
+        let active = db.execute(0, |tx| tx.find_documents(docs.id(), "by_status", &json!("active"))).unwrap();
+        assert_eq!(active.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![2]);
+        let by_index = db.index_manager().get_index(docs.id(), "by_status").unwrap();
+        assert_eq!(by_index.get_all(&IndexKey::String("closed".into()), u64::MAX).unwrap().len(), 1);
+
+        // Lookups see the transaction's own writes and its snapshot, not the
+        // index's latest state
+        let mut reader = db.begin_transaction(0);
+        db.execute(0, |tx| tx.set_path_in(docs.id(), 2, "$.status", json!("closed"))).unwrap();
+        let active = reader.find_documents(docs.id(), "by_status", &json!("active")).unwrap();
+        assert_eq!(active.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![2]);
+        reader.write_document_in(docs.id(), 3, &json!({"status": "active"}).into()).unwrap();
+        reader.set_path_in(docs.id(), 2, "$.status", json!("paused")).unwrap();
+        let active = reader.find_documents(docs.id(), "by_status", &json!("active")).unwrap();
+        assert_eq!(active.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn test_queues() {
+        let db = Maemio::new().unwrap();
+        let jobs = db.create_queue("jobs", Duration::from_millis(50)).unwrap();
+        db.execute(0, |tx| {
+            for job in [b"a", b"b", b"c"] {
+                tx.enqueue(&jobs, job)?;
+            }
+            Ok(())
+        }).unwrap();
+
+        // Two consumers take different messages and both commit
+        let mut first = db.begin_transaction(0);
+        let mut second = db.begin_transaction(0);
+        let a = first.dequeue(&jobs).unwrap().unwrap();
+        let b = second.dequeue(&jobs).unwrap().unwrap();
+        assert_eq!((a.payload.as_slice(), b.payload.as_slice()), (&b"a"[..], &b"b"[..]));
+        first.ack(&jobs, &a.receipt).unwrap();
+        first.commit().unwrap();
+        second.commit().unwrap();
+        drop((first, second));
+
+        // The unacknowledged message comes back after the visibility timeout
+        let queue = db.queue("jobs").unwrap();
+        let c = db.execute(0, |tx| tx.dequeue(&queue)).unwrap().unwrap();
+        assert_eq!(c.payload, b"c");
+        assert_eq!(db.execute(0, |tx| tx.dequeue(&queue)).unwrap(), None);
+        std::thread::sleep(Duration::from_millis(60));
+        let again = db.execute(0, |tx| tx.dequeue(&queue)).unwrap().unwrap();
+        assert_eq!((again.payload.as_slice(), again.receipt.delivery), (&b"b"[..], 2));
+
+        // Only the latest delivery can be acknowledged
+        assert!(db.execute(0, |tx| tx.ack(&queue, &b.receipt)).is_err());
+        db.execute(0, |tx| {
+            tx.ack(&queue, &again.receipt)?;
+            tx.ack(&queue, &c.receipt)
+        }).unwrap();
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(db.execute(0, |tx| tx.dequeue(&queue)).unwrap(), None);
+        assert!(db.queue("missing").is_err());
+
+        // Acknowledged messages leave the table once no snapshot can see them
+        db.gc.as_ref().unwrap().collect_garbage().unwrap();
+        assert_eq!(db.tables.get_table(queue.table_id()).unwrap().record_count(), 0);
+
+        // An aborted enqueue leaves a gap the head moves past
+        let mut aborted = db.begin_transaction(0);
+        aborted.enqueue(&queue, b"lost").unwrap();
+        drop(aborted);
+        let d = db.execute(0, |tx| tx.enqueue(&queue, b"d")).unwrap();
+        let message = db.execute(0, |tx| tx.dequeue(&queue)).unwrap().unwrap();
+        assert_eq!(message.payload, b"d");
+        db.execute(0, |tx| tx.ack(&queue, &message.receipt)).unwrap();
+        assert_eq!(db.execute(0, |tx| tx.dequeue(&queue)).unwrap(), None);
+        assert_eq!(queue.head(), d + 1);
+
+        // Handles rebuilt from the catalog start past acknowledged messages
+        let e = db.execute(0, |tx| tx.enqueue(&queue, b"e")).unwrap();
+        db.queues.write().clear();
+        let rebuilt = db.queue("jobs").unwrap();
+        assert_eq!(rebuilt.head(), e);
+        assert_eq!(db.execute(0, |tx| tx.dequeue(&rebuilt)).unwrap().unwrap().payload, b"e");
+
+        // Queues are recorded in the catalog and go with their table
+        let entries = db.list_queues().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].name.as_str(), entries[0].definition.as_str()), ("jobs", "VISIBILITY TIMEOUT 50 MS"));
+        db.drop_table("jobs").unwrap();
+        assert!(db.list_queues().unwrap().is_empty());
+        assert!(db.queue("jobs").is_err());
+    }
+}
@@ -0,0 +1,218 @@
+// src/map/codec.rs
+//
+// Conversions between typed map keys and values and the bytes stored in
+// records and the primary index. Key encodings sort like the keys they
+// encode, so byte-ordered index ranges are key-ordered ranges.
+use crate::error::{MaemioError, Result};
+
+/// Converts map keys to bytes that sort in key order.
+///
+/// Encodings are self-delimiting, so tuples of keys encode as the
+/// concatenation of their parts and still sort component by component.
+pub trait KeyCodec: Sized {
+    /// Appends the key's encoding to `out`
+    fn encode_key(&self, out: &mut Vec<u8>);
+
+    /// Reads a key from the front of `input`, advancing past it
+    fn decode_key(input: &mut &[u8]) -> Result<Self>;
+}
+
+/// Converts map values to and from record bytes
+pub trait ValueCodec: Sized {
+    fn encode_value(&self) -> Vec<u8>;
+
+    fn decode_value(data: &[u8]) -> Result<Self>;
+}
+
+/// Encodes a whole key
+pub(crate) fn key_bytes<K: KeyCodec>(key: &K) -> Vec<u8> {
+    let mut out = Vec::new();
+    key.encode_key(&mut out);
+    out
+}
+
+/// Decodes a whole key, rejecting trailing bytes
+pub(crate) fn key_from_bytes<K: KeyCodec>(mut bytes: &[u8]) -> Result<K> {
+    let key = K::decode_key(&mut bytes)?;
+    if !bytes.is_empty() {
+        return Err(invalid("key", "trailing bytes"));
+    }
+    Ok(key)
+}
+
+fn invalid(what: &str, reason: &str) -> MaemioError {
+    MaemioError::System(format!("Invalid map {} encoding: {}", what, reason))
+}
+
+fn take<const N: usize>(input: &mut &[u8]) -> Result<[u8; N]> {
+    let (head, rest) = input.split_first_chunk::<N>().ok_or_else(|| invalid("key", "truncated"))?;
+    *input = rest;
+    Ok(*head)
+}
+
+macro_rules! unsigned_key {
+    ($($ty:ty),*) => {$(
+        impl KeyCodec for $ty {
+            fn encode_key(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_be_bytes());
+            }
+
+            fn decode_key(input: &mut &[u8]) -> Result<Self> {
+                Ok(<$ty>::from_be_bytes(take(input)?))
+            }
+        }
+    )*};
+}
+
+// Signed integers flip the sign bit so negative numbers sort first
+macro_rules! signed_key {
+    ($($ty:ty => $unsigned:ty),*) => {$(
+        impl KeyCodec for $ty {
+            fn encode_key(&self, out: &mut Vec<u8>) {
+                let flipped = (*self as $unsigned) ^ (1 << (<$unsigned>::BITS - 1));
+                out.extend_from_slice(&flipped.to_be_bytes());
+            }
+
+            fn decode_key(input: &mut &[u8]) -> Result<Self> {
+                let flipped = <$unsigned>::from_be_bytes(take(input)?);
+                Ok((flipped ^ (1 << (<$unsigned>::BITS - 1))) as $ty)
+            }
+        }
+    )*};
+}
+
+unsigned_key!(u8, u16, u32, u64);
+signed_key!(i32 => u32, i64 => u64);
+
+/// Byte strings escape zero bytes as `00 FF` and end with `00 00`, which
+/// keeps them ordered and lets another key follow them.
+impl KeyCodec for Vec<u8> {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        for &byte in self {
+            out.push(byte);
+            if byte == 0 {
+                out.push(0xFF);
+            }
+        }
+        out.extend_from_slice(&[0, 0]);
+    }
+
+    fn decode_key(input: &mut &[u8]) -> Result<Self> {
+        let mut bytes = Vec::new();
+        let mut rest = *input;
+        loop {
+            match rest {
+                [0, 0, tail @ ..] => {
+                    *input = tail;
+                    return Ok(bytes);
+                }
+                [0, 0xFF, tail @ ..] => {
+                    bytes.push(0);
+                    rest = tail;
+                }
+                [0, ..] | [] => return Err(invalid("key", "unterminated byte string")),
+                [byte, tail @ ..] => {
+                    bytes.push(*byte);
+                    rest = tail;
+                }
+            }
+        }
+    }
+}
+
+impl KeyCodec for String {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        self.as_bytes().to_vec().encode_key(out);
+    }
+
+    fn decode_key(input: &mut &[u8]) -> Result<Self> {
+        String::from_utf8(Vec::decode_key(input)?).map_err(|_| invalid("key", "not UTF-8"))
+    }
+}
+
+macro_rules! tuple_key {
+    ($($name:ident),+) => {
+        impl<$($name: KeyCodec),+> KeyCodec for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode_key(&self, out: &mut Vec<u8>) {
+                let ($($name,)+) = self;
+                $($name.encode_key(out);)+
+            }
+
+            fn decode_key(input: &mut &[u8]) -> Result<Self> {
+                Ok(($($name::decode_key(input)?,)+))
+            }
+        }
+    };
+}
+
+tuple_key!(A, B);
+tuple_key!(A, B, C);
+tuple_key!(A, B, C, D);
+
+impl ValueCodec for Vec<u8> {
+    fn encode_value(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn decode_value(data: &[u8]) -> Result<Self> {
+        Ok(data.to_vec())
+    }
+}
+
+impl ValueCodec for String {
+    fn encode_value(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn decode_value(data: &[u8]) -> Result<Self> {
+        String::from_utf8(data.to_vec()).map_err(|_| invalid("value", "not UTF-8"))
+    }
+}
+
+macro_rules! number_value {
+    ($($ty:ty),*) => {$(
+        impl ValueCodec for $ty {
+            fn encode_value(&self) -> Vec<u8> {
+                self.to_le_bytes().to_vec()
+            }
+
+            fn decode_value(data: &[u8]) -> Result<Self> {
+                data.try_into()
+                    .map(<$ty>::from_le_bytes)
+                    .map_err(|_| invalid("value", "wrong length"))
+            }
+        }
+    )*};
+}
+
+number_value!(u32, u64, i32, i64, f64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_encodings_sort_like_keys() {
+        let ints = [i64::MIN, -5, -1, 0, 1, 300, i64::MAX];
+        let encoded: Vec<_> = ints.iter().map(key_bytes).collect();
+        assert!(encoded.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // Prefixes and embedded zero bytes keep their order inside tuples
+        let mut keys = vec![
+            ("ab".to_string(), 2u64),
+            ("a".to_string(), 9),
+            ("a\0".to_string(), 1),
+            ("ab".to_string(), 1),
+            (String::new(), 5),
+        ];
+        let mut encoded: Vec<_> = keys.iter().map(key_bytes).collect();
+        keys.sort();
+        encoded.sort();
+        let decoded: Vec<(String, u64)> = encoded.iter().map(|bytes| key_from_bytes(bytes).unwrap()).collect();
+        assert_eq!(decoded, keys);
+
+        assert!(key_from_bytes::<String>(b"abc").is_err());
+        assert!(key_from_bytes::<u32>(&[0, 0, 0, 1, 7]).is_err());
+    }
+}
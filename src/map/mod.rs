@@ -0,0 +1,273 @@
+// src/map/mod.rs
+//
+// Typed key-value maps built from a table and a unique primary index. The
+// record of an entry stores the encoded key followed by the encoded value,
+// and the primary index is maintained from that key when transactions commit,
+// so entries are versioned and validated like any record.
+//
+// Putting a new key inserts a record; removing an entry deletes its record,
+// and the commit removes the key from the index. The index holds the latest
+// state, so lookups also consult the entries it recently lost, which older
+// snapshots may still see. Whether a key is present at a transaction's
+// timestamp is then decided by the version chains of those records.
+use std::iter::Peekable;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+use std::vec;
+use crate::data::Version;
+use crate::error::{MaemioError, Result};
+use crate::index::{BTreeCursor, IndexKey, MAX_KEY_SIZE};
+use crate::schema::encoding::{read_varint, write_varint};
+use crate::table::TableIndex;
+use crate::transaction::Transaction;
+
+mod codec;
+
+pub use codec::{KeyCodec, ValueCodec};
+use codec::{key_bytes, key_from_bytes};
+
+/// Name of the unique index mapping a map's keys to its records
+pub(crate) const MAP_PRIMARY_INDEX: &str = "primary";
+
+/// A handle to a typed map stored in a table; created by `Maemio::open_map`
+pub struct Map<K, V> {
+    name: String,
+    table_id: u64,
+    primary: Arc<TableIndex>,
+    _types: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V> Clone for Map<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            table_id: self.table_id,
+            primary: self.primary.clone(),
+            _types: PhantomData,
+        }
+    }
+}
+
+impl<K: KeyCodec, V: ValueCodec> Map<K, V> {
+    pub(crate) fn new(name: &str, table_id: u64, primary: Arc<TableIndex>) -> Self {
+        Self {
+            name: name.to_string(),
+            table_id,
+            primary,
+            _types: PhantomData,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Id of the table holding the map's entries
+    pub fn table_id(&self) -> u64 {
+        self.table_id
+    }
+
+    /// Reads the value stored under `key`
+    pub fn get(&self, tx: &mut Transaction, key: &K) -> Result<Option<V>> {
+        match self.find(tx, &IndexKey::Bytes(key_bytes(key)))? {
+            Some((_, entry)) => V::decode_value(entry.value()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Stores `value` under `key`, replacing any previous value
+    pub fn put(&self, tx: &mut Transaction, key: &K, value: &V) -> Result<()> {
+        let key = key_bytes(key);
+        if key.len() > MAX_KEY_SIZE {
+            return Err(MaemioError::System(format!(
+                "Key of {} bytes exceeds the {} byte limit of map {}", key.len(), MAX_KEY_SIZE, self.name
+            )));
+        }
+        let value = value.encode_value();
+        let mut entry = Vec::with_capacity(key.len() + value.len() + 2);
+        write_varint(&mut entry, key.len() as u64);
+        entry.extend_from_slice(&key);
+        entry.extend_from_slice(&value);
+
+        let index_key = IndexKey::Bytes(key);
+        if let Some(record_id) = self.record_for(tx, &index_key)? {
+            return tx.write_in(self.table_id, record_id, entry);
+        }
+        // A new key, found absent above: the record joins the table and the
+        // key the index when the transaction commits
+        tx.insert_key(self.table_id, index_key, entry).map(|_| ())
+    }
+
+    /// Removes the entry under `key`. Returns whether there was one.
+    pub fn remove(&self, tx: &mut Transaction, key: &K) -> Result<bool> {
+        let Some((record_id, _)) = self.find(tx, &IndexKey::Bytes(key_bytes(key)))? else {
+            return Ok(false);
+        };
+        tx.delete_in(self.table_id, record_id)?;
+        Ok(true)
+    }
+
+    /// Iterates over the entries with keys in `range`, in key order. Entries
+    /// are read as the iterator advances, so stopping early skips the rest.
+    pub fn range<'a, R: RangeBounds<K>>(&'a self, tx: &'a mut Transaction, range: R) -> Result<MapRange<'a, K, V>> {
+        let bounds = (bound_key(range.start_bound()), bound_key(range.end_bound()));
+        let primary = self.primary.index.as_btree().ok_or_else(|| MaemioError::System(format!(
+            "Primary index of map {} is not ordered", self.name
+        )))?;
+        // Every record that may hold a key in range for this transaction: the
+        // index's, the ones it recently lost, and the transaction's inserts
+        let removed = self.primary.removed_in(bounds.clone());
+        let pending = tx.pending_keys_in(self.table_id, bounds.clone());
+        Ok(MapRange {
+            map: self,
+            tx,
+            indexed: primary.cursor(bounds).peekable(),
+            removed: removed.into_iter().peekable(),
+            pending: pending.into_iter().peekable(),
+        })
+    }
+
+    /// The record holding `key` that the transaction sees, with its entry;
+    /// remembered for validation if there is none
+    fn find(&self, tx: &mut Transaction, key: &IndexKey) -> Result<Option<(u64, Entry)>> {
+        let candidates = tx.pending_key(self.table_id, key)
+            .into_iter()
+            .chain(self.primary.index.get(key, tx.get_timestamp())?)
+            .chain(self.primary.removed_records(key));
+        for record_id in candidates.collect::<Vec<_>>() {
+            if let Some(entry) = self.read_entry(tx, record_id)? {
+                return Ok(Some((record_id, entry)));
+            }
+        }
+        tx.register_absent_key(self.table_id, self.primary.clone(), key.clone());
+        Ok(None)
+    }
+
+    /// The record a put of `key` writes to, if the key already has one
+    fn record_for(&self, tx: &mut Transaction, key: &IndexKey) -> Result<Option<u64>> {
+        if let Some(record_id) = tx.pending_key(self.table_id, key) {
+            return Ok(Some(record_id));
+        }
+        // A record of the latest state, even one this snapshot cannot see
+        // yet, so that concurrent puts of a key conflict on its record
+        if let Some(record_id) = self.primary.index.get(key, tx.get_timestamp())? {
+            return Ok(Some(record_id));
+        }
+        Ok(self.find(tx, key)?.map(|(record_id, _)| record_id))
+    }
+
+    /// Reads an entry's record; `None` if the entry is absent at the
+    /// transaction's timestamp
+    fn read_entry(&self, tx: &mut Transaction, record_id: u64) -> Result<Option<Entry>> {
+        let version = match tx.read_in(self.table_id, record_id) {
+            Ok(version) => version,
+            Err(MaemioError::RecordNotFound(_)) | Err(MaemioError::NoVisibleVersion) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let Some((key_start, key_end)) = key_span(&version.data) else {
+            return Err(MaemioError::System(format!(
+                "Record {} of map {} is not a map entry", record_id, self.name
+            )));
+        };
+        Ok(Some(Entry { version, key_start, key_end }))
+    }
+}
+
+/// Key of a stored map entry, for the primary index
+pub(crate) fn entry_key(data: &[u8]) -> Option<IndexKey> {
+    key_span(data).map(|(start, end)| IndexKey::Bytes(data[start..end].to_vec()))
+}
+
+/// Where the key lies in a stored map entry
+fn key_span(data: &[u8]) -> Option<(usize, usize)> {
+    let mut rest = data;
+    let key_len = read_varint(&mut rest).ok()? as usize;
+    let key_start = data.len() - rest.len();
+    (rest.len() >= key_len).then_some((key_start, key_start + key_len))
+}
+
+/// Iterator over a key range of a map; created by `Map::range`. Yields the
+/// entries the transaction sees, as `(key, value)` pairs.
+pub struct MapRange<'a, K, V> {
+    map: &'a Map<K, V>,
+    tx: &'a mut Transaction,
+    // Candidate records by key, from the three sources `Map::range` names,
+    // each in key order
+    indexed: Peekable<BTreeCursor<'a>>,
+    removed: Peekable<vec::IntoIter<(IndexKey, u64)>>,
+    pending: Peekable<vec::IntoIter<(IndexKey, u64)>>,
+}
+
+impl<K, V> MapRange<'_, K, V> {
+    /// Takes the candidate records of the smallest key left
+    fn next_candidates(&mut self) -> Option<Vec<u64>> {
+        let key = [self.indexed.peek(), self.removed.peek(), self.pending.peek()]
+            .into_iter()
+            .flatten()
+            .map(|(key, _)| key)
+            .min()?
+            .clone();
+        let mut record_ids = Vec::new();
+        take_key(&mut self.indexed, &key, &mut record_ids);
+        take_key(&mut self.removed, &key, &mut record_ids);
+        take_key(&mut self.pending, &key, &mut record_ids);
+        Some(record_ids)
+    }
+}
+
+impl<K: KeyCodec, V: ValueCodec> Iterator for MapRange<'_, K, V> {
+    type Item = Result<(K, V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(record_ids) = self.next_candidates() {
+            for record_id in record_ids {
+                match self.map.read_entry(self.tx, record_id) {
+                    Ok(Some(entry)) => {
+                        let pair = key_from_bytes(entry.key())
+                            .and_then(|key| Ok((key, V::decode_value(entry.value())?)));
+                        return Some(pair);
+                    }
+                    Ok(None) => {}
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Moves the records a source holds under `key` to `out`
+fn take_key<I>(source: &mut Peekable<I>, key: &IndexKey, out: &mut Vec<u64>)
+where
+    I: Iterator<Item = (IndexKey, u64)>,
+{
+    while let Some((_, record_id)) = source.next_if(|(k, _)| k == key) {
+        out.push(record_id);
+    }
+}
+
+/// A map entry as stored: the key's length, the key and the value
+struct Entry {
+    version: Arc<Version>,
+    key_start: usize,
+    key_end: usize,
+}
+
+impl Entry {
+    fn key(&self) -> &[u8] {
+        &self.version.data[self.key_start..self.key_end]
+    }
+
+    fn value(&self) -> &[u8] {
+        &self.version.data[self.key_end..]
+    }
+}
+
+fn bound_key<K: KeyCodec>(bound: Bound<&K>) -> Bound<IndexKey> {
+    match bound {
+        Bound::Included(key) => Bound::Included(IndexKey::Bytes(key_bytes(key))),
+        Bound::Excluded(key) => Bound::Excluded(IndexKey::Bytes(key_bytes(key))),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
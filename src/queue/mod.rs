@@ -0,0 +1,240 @@
+// src/queue/mod.rs
+//
+// FIFO queues stored in a table. Each message is a record with a sequential
+// id, so id order is enqueue order, holding a small header followed by the
+// payload. Dequeuing does not remove a message: it hides it for the queue's
+// visibility timeout and counts the delivery, and acknowledging it deletes
+// the record, which the garbage collector removes from the table once no
+// snapshot can see it any more. A message that is not acknowledged in time becomes visible
+// again and is delivered once more.
+//
+// There is no head pointer for consumers to fight over. A consumer walks the
+// messages in order and claims the first visible one in memory before
+// writing to it, skipping messages other consumers have claimed, and only
+// the message it takes joins its read set. Claims last for the visibility
+// timeout, so a message taken by a transaction that later aborts is
+// redelivered like one whose consumer never acknowledged it.
+//
+// Messages enter the table when the enqueuing transaction commits, so ids
+// can be missing for a while, or for good if it aborts. The queue remembers
+// which ids are still being enqueued; consumers only move the head past a
+// missing id once nobody is enqueuing it any more.
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use parking_lot::Mutex;
+use crate::catalog::CatalogEntry;
+use crate::error::{MaemioError, Result};
+use crate::table::Table;
+
+/// Size of the header in front of every message's payload
+const HEADER_SIZE: usize = 12;
+
+/// Claims kept before expired ones are pruned
+const MAX_CLAIMS: usize = 1024;
+
+/// Proof of one delivery of a message, needed to acknowledge it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Receipt {
+    pub message_id: u64,
+    /// Which delivery of the message this is, starting at 1
+    pub delivery: u32,
+}
+
+/// A message handed out by `Transaction::dequeue`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueMessage {
+    pub receipt: Receipt,
+    pub payload: Vec<u8>,
+}
+
+/// A handle to a queue; created by `Maemio::create_queue`
+#[derive(Clone)]
+pub struct Queue {
+    inner: Arc<QueueState>,
+}
+
+struct QueueState {
+    name: String,
+    table_id: u64,
+    visibility_timeout: Duration,
+    // Messages below this id are known to be acknowledged
+    head: AtomicU64,
+    // Messages taken by consumers, with the time in milliseconds when the
+    // claim lapses
+    claims: Mutex<HashMap<u64, u64>>,
+    // Ids of messages whose enqueuing transaction has not finished
+    enqueuing: Mutex<BTreeSet<u64>>,
+}
+
+impl Queue {
+    pub(crate) fn new(name: &str, table_id: u64, visibility_timeout: Duration) -> Self {
+        Self {
+            inner: Arc::new(QueueState {
+                name: name.to_string(),
+                table_id,
+                visibility_timeout,
+                head: AtomicU64::new(0),
+                claims: Mutex::new(HashMap::new()),
+                enqueuing: Mutex::new(BTreeSet::new()),
+            }),
+        }
+    }
+
+    /// Rebuilds a queue from its catalog entry
+    pub(crate) fn from_catalog_entry(entry: &CatalogEntry) -> Result<Self> {
+        let millis = entry.definition
+            .strip_prefix("VISIBILITY TIMEOUT ")
+            .and_then(|rest| rest.strip_suffix(" MS"))
+            .and_then(|millis| millis.parse().ok());
+        match (entry.table_id, millis) {
+            (Some(table_id), Some(millis)) => Ok(Self::new(&entry.name, table_id, Duration::from_millis(millis))),
+            _ => Err(MaemioError::System(format!("Malformed catalog entry for queue {}", entry.name))),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    /// Id of the table holding the queue's messages
+    pub fn table_id(&self) -> u64 {
+        self.inner.table_id
+    }
+
+    /// How long a dequeued message stays hidden before it is delivered again
+    pub fn visibility_timeout(&self) -> Duration {
+        self.inner.visibility_timeout
+    }
+
+    pub(crate) fn head(&self) -> u64 {
+        self.inner.head.load(Ordering::Relaxed)
+    }
+
+    /// Records that every message below `message_id` was acknowledged
+    pub(crate) fn advance_head(&self, message_id: u64) {
+        self.inner.head.fetch_max(message_id, Ordering::Relaxed);
+    }
+
+    /// Moves the head of a handle rebuilt from the catalog to the oldest
+    /// message of `table` that is not acknowledged, or past the last one
+    pub(crate) fn recover_head(&self, table: &Table) -> Result<()> {
+        let mut head = 0;
+        for (message_id, record) in table.records().range_iter(..) {
+            match record.find_visible_version(u64::MAX)? {
+                Some(version) if version.is_tombstone() => head = message_id + 1,
+                _ => break,
+            }
+        }
+        self.advance_head(head);
+        Ok(())
+    }
+
+    /// Claims a message for one consumer until `until`. Fails while another
+    /// consumer's claim on it is still running.
+    pub(crate) fn claim(&self, message_id: u64, now: u64, until: u64) -> bool {
+        let mut claims = self.inner.claims.lock();
+        if claims.get(&message_id).is_some_and(|&lapses| lapses > now) {
+            return false;
+        }
+        if claims.len() >= MAX_CLAIMS {
+            claims.retain(|_, &mut lapses| lapses > now);
+        }
+        claims.insert(message_id, until);
+        true
+    }
+
+    pub(crate) fn release(&self, message_id: u64) {
+        self.inner.claims.lock().remove(&message_id);
+    }
+
+    /// Runs `allocate`, which picks the id of a new message, and marks the id
+    /// as being enqueued before anyone can see a later one
+    pub(crate) fn start_enqueue<F>(&self, allocate: F) -> Result<u64>
+    where
+        F: FnOnce() -> Result<u64>,
+    {
+        let mut enqueuing = self.inner.enqueuing.lock();
+        let message_id = allocate()?;
+        enqueuing.insert(message_id);
+        Ok(message_id)
+    }
+
+    /// Called once the transaction enqueuing `message_id` has finished
+    pub(crate) fn finish_enqueue(&self, message_id: u64) {
+        self.inner.enqueuing.lock().remove(&message_id);
+    }
+
+    /// Whether a message with an id in `ids` is still being enqueued
+    pub(crate) fn enqueuing_in(&self, ids: Range<u64>) -> bool {
+        !ids.is_empty() && self.inner.enqueuing.lock().range(ids).next().is_some()
+    }
+}
+
+/// State kept in front of a message's payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct MessageHeader {
+    /// Milliseconds since the Unix epoch before which the message is hidden
+    pub(crate) invisible_until: u64,
+    /// How often the message was dequeued
+    pub(crate) deliveries: u32,
+}
+
+impl MessageHeader {
+    pub(crate) fn encode(&self, payload: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_SIZE + payload.len());
+        out.extend_from_slice(&self.invisible_until.to_le_bytes());
+        out.extend_from_slice(&self.deliveries.to_le_bytes());
+        out.extend_from_slice(payload);
+        out
+    }
+
+    /// Splits a message record into its header and payload
+    pub(crate) fn decode(message_id: u64, data: &[u8]) -> Result<(Self, &[u8])> {
+        if data.len() < HEADER_SIZE {
+            return Err(MaemioError::Decode(format!("Record {} is not a queue message", message_id)));
+        }
+        let (header, payload) = data.split_at(HEADER_SIZE);
+        let (until, deliveries) = header.split_at(8);
+        let header = Self {
+            invisible_until: u64::from_le_bytes(until.try_into().expect("8 byte slice")),
+            deliveries: u32::from_le_bytes(deliveries.try_into().expect("4 byte slice")),
+        };
+        Ok((header, payload))
+    }
+}
+
+/// Wall-clock milliseconds since the Unix epoch
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claims_lapse() {
+        let queue = Queue::new("jobs", 1, Duration::from_secs(1));
+        assert!(queue.claim(7, 100, 200));
+        assert!(!queue.claim(7, 150, 250));
+        assert!(queue.claim(7, 200, 300));
+        queue.release(7);
+        assert!(queue.claim(7, 210, 310));
+
+        let message_id = queue.start_enqueue(|| Ok(4)).unwrap();
+        assert!(queue.enqueuing_in(3..5));
+        assert!(!queue.enqueuing_in(5..9));
+        queue.finish_enqueue(message_id);
+        assert!(!queue.enqueuing_in(0..10));
+
+        let header = MessageHeader { invisible_until: 300, deliveries: 2 };
+        let data = header.encode(b"job");
+        assert_eq!(MessageHeader::decode(7, &data).unwrap(), (header, &b"job"[..]));
+        assert!(MessageHeader::decode(7, b"short").is_err());
+    }
+}
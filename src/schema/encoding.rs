@@ -0,0 +1,304 @@
+// src/schema/encoding.rs
+//
+// Binary row format: a null bitmap with one bit per column, followed by the
+// non-null values in column order. Integers are zigzag varints, floats are
+// 8 little-endian bytes, booleans are one byte, and strings and byte arrays
+// are a varint length followed by their bytes.
+use super::{Column, ColumnType, Row, Value};
+use crate::error::{MaemioError, Result};
+
+pub(crate) fn encode(row: &Row) -> Vec<u8> {
+    let values = row.values();
+    let mut out = vec![0u8; values.len().div_ceil(8)];
+
+    for (i, value) in values.iter().enumerate() {
+        if value.is_null() {
+            out[i / 8] |= 1 << (i % 8);
+        }
+        encode_value(&mut out, value);
+    }
+    out
+}
+
+pub(crate) fn decode(columns: &[Column], data: &[u8]) -> Result<Row> {
+    let (bitmap, mut rest) = split_bitmap(columns, data)?;
+
+    let mut values = Vec::with_capacity(columns.len());
+    for (i, column) in columns.iter().enumerate() {
+        if is_null(bitmap, i) {
+            values.push(Value::Null);
+            continue;
+        }
+        values.push(decode_value(column.column_type, &mut rest)?);
+    }
+
+    if !rest.is_empty() {
+        return Err(corrupt("trailing bytes"));
+    }
+    Ok(Row::new(values))
+}
+
+/// Decodes only the columns at `wanted`, in that order, skipping over the rest
+pub(crate) fn decode_columns(columns: &[Column], data: &[u8], wanted: &[usize]) -> Result<Vec<Value>> {
+    let (bitmap, mut rest) = split_bitmap(columns, data)?;
+    let last = wanted.iter().copied().max().map_or(0, |last| last + 1);
+
+    let mut decoded: Vec<Option<Value>> = vec![None; last.min(columns.len())];
+    for (i, column) in columns.iter().enumerate().take(last) {
+        if is_null(bitmap, i) {
+            decoded[i] = Some(Value::Null);
+        } else if wanted.contains(&i) {
+            decoded[i] = Some(decode_value(column.column_type, &mut rest)?);
+        } else {
+            skip_value(column.column_type, &mut rest)?;
+        }
+    }
+
+    wanted
+        .iter()
+        .map(|&i| decoded.get(i).cloned().flatten().ok_or_else(|| corrupt("missing column")))
+        .collect()
+}
+
+/// Re-encodes a row with some columns replaced, copying the bytes of all
+/// other columns without decoding them.
+///
+/// Also returns the changed spans of the new row as `(offset, len, old)`,
+/// where `old` are the bytes the span replaced in `data`.
+pub(crate) fn patch<'a>(
+    columns: &[Column],
+    data: &'a [u8],
+    updates: &[(usize, Value)],
+) -> Result<(Vec<u8>, Vec<(usize, usize, &'a [u8])>)> {
+    let (bitmap, mut rest) = split_bitmap(columns, data)?;
+    let mut out = bitmap.to_vec();
+    let mut spans = Vec::with_capacity(updates.len() + 1);
+
+    for (i, column) in columns.iter().enumerate() {
+        let old = if is_null(bitmap, i) {
+            &[][..]
+        } else {
+            let before = rest;
+            skip_value(column.column_type, &mut rest)?;
+            &before[..before.len() - rest.len()]
+        };
+
+        // The last update of a column wins
+        let start = out.len();
+        match updates.iter().rev().find(|(column, _)| *column == i) {
+            Some((_, Value::Null)) => out[i / 8] |= 1 << (i % 8),
+            Some((_, value)) => {
+                out[i / 8] &= !(1 << (i % 8));
+                encode_value(&mut out, value);
+            }
+            None => {
+                out.extend_from_slice(old);
+                continue;
+            }
+        }
+        if out[start..] != *old {
+            spans.push((start, out.len() - start, old));
+        }
+    }
+
+    if !rest.is_empty() {
+        return Err(corrupt("trailing bytes"));
+    }
+    if out[..bitmap.len()] != *bitmap {
+        spans.insert(0, (0, bitmap.len(), bitmap));
+    }
+    Ok((out, spans))
+}
+
+fn split_bitmap<'a>(columns: &[Column], data: &'a [u8]) -> Result<(&'a [u8], &'a [u8])> {
+    let bitmap_len = columns.len().div_ceil(8);
+    if data.len() < bitmap_len {
+        return Err(corrupt("truncated null bitmap"));
+    }
+    Ok(data.split_at(bitmap_len))
+}
+
+fn is_null(bitmap: &[u8], i: usize) -> bool {
+    bitmap[i / 8] & (1 << (i % 8)) != 0
+}
+
+fn encode_value(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => {}
+        Value::Int(v) => write_varint(out, zigzag(*v)),
+        Value::Float(v) => out.extend_from_slice(&v.to_le_bytes()),
+        Value::Bool(v) => out.push(*v as u8),
+        Value::String(v) => write_bytes(out, v.as_bytes()),
+        Value::Bytes(v) => write_bytes(out, v),
+    }
+}
+
+fn decode_value(column_type: ColumnType, rest: &mut &[u8]) -> Result<Value> {
+    Ok(match column_type {
+        ColumnType::Int => Value::Int(unzigzag(read_varint(rest)?)),
+        ColumnType::Float => {
+            let bytes = take(rest, 8)?;
+            Value::Float(f64::from_le_bytes(bytes.try_into().unwrap()))
+        }
+        ColumnType::Bool => match take(rest, 1)?[0] {
+            0 => Value::Bool(false),
+            1 => Value::Bool(true),
+            _ => return Err(corrupt("invalid boolean")),
+        },
+        ColumnType::String => {
+            let bytes = read_bytes(rest)?;
+            let s = std::str::from_utf8(bytes).map_err(|_| corrupt("invalid UTF-8"))?;
+            Value::String(s.to_string())
+        }
+        ColumnType::Bytes => Value::Bytes(read_bytes(rest)?.to_vec()),
+    })
+}
+
+/// Advances past one value without materializing it
+fn skip_value(column_type: ColumnType, rest: &mut &[u8]) -> Result<()> {
+    match column_type {
+        ColumnType::Int => {
+            read_varint(rest)?;
+        }
+        ColumnType::Float => {
+            take(rest, 8)?;
+        }
+        ColumnType::Bool => {
+            take(rest, 1)?;
+        }
+        ColumnType::String | ColumnType::Bytes => {
+            read_bytes(rest)?;
+        }
+    }
+    Ok(())
+}
+
+fn corrupt(reason: &str) -> MaemioError {
+    MaemioError::SchemaViolation(format!("Malformed row: {}", reason))
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn unzigzag(v: u64) -> i64 {
+    ((v >> 1) as i64) ^ -((v & 1) as i64)
+}
+
+pub(crate) fn write_varint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+pub(crate) fn read_varint(data: &mut &[u8]) -> Result<u64> {
+    let mut result = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = take(data, 1)?[0];
+        result |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+    }
+    Err(corrupt("varint too long"))
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn read_bytes<'a>(data: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len = read_varint(data)? as usize;
+    take(data, len)
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if data.len() < len {
+        return Err(corrupt("truncated value"));
+    }
+    let (head, tail) = data.split_at(len);
+    *data = tail;
+    Ok(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::delta;
+
+    #[test]
+    fn test_zigzag_varints() {
+        for v in [0, 1, -1, 63, -64, i64::MAX, i64::MIN] {
+            let mut out = Vec::new();
+            write_varint(&mut out, zigzag(v));
+            let mut slice = out.as_slice();
+            assert_eq!(unzigzag(read_varint(&mut slice).unwrap()), v);
+            assert!(slice.is_empty());
+        }
+
+        // Small magnitudes take a single byte
+        let mut out = Vec::new();
+        write_varint(&mut out, zigzag(-3));
+        assert_eq!(out.len(), 1);
+    }
+
+    #[test]
+    fn test_malformed_rows_are_rejected() {
+        let columns = vec![
+            Column::new("a", ColumnType::Int),
+            Column::new("b", ColumnType::String),
+        ];
+        let encoded = encode(&Row::new(vec![Value::Int(7), Value::String("xyz".into())]));
+
+        assert!(decode(&columns, &encoded).is_ok());
+        assert!(decode(&columns, &encoded[..encoded.len() - 1]).is_err());
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        assert!(decode(&columns, &trailing).is_err());
+        assert!(decode(&columns, &[]).is_err());
+    }
+
+    #[test]
+    fn test_projection_and_patch() {
+        let columns = vec![
+            Column::new("a", ColumnType::Int),
+            Column::nullable("b", ColumnType::String),
+            Column::new("c", ColumnType::Bytes),
+            Column::new("d", ColumnType::Bool),
+        ];
+        let row = Row::new(vec![
+            Value::Int(-5),
+            Value::Null,
+            Value::Bytes(vec![9; 300]),
+            Value::Bool(true),
+        ]);
+        let encoded = encode(&row);
+
+        assert_eq!(
+            decode_columns(&columns, &encoded, &[3, 0, 1]).unwrap(),
+            vec![Value::Bool(true), Value::Int(-5), Value::Null]
+        );
+
+        let (patched, spans) = patch(&columns, &encoded, &[
+            (1, Value::String("set".into())),
+            (0, Value::Null),
+            (0, Value::Int(8)),
+        ]).unwrap();
+        let expected = Row::new(vec![
+            Value::Int(8),
+            Value::String("set".into()),
+            Value::Bytes(vec![9; 300]),
+            Value::Bool(true),
+        ]);
+        assert_eq!(patched, encode(&expected));
+        assert_eq!(decode(&columns, &patched).unwrap(), expected);
+
+        // The bitmap and the two replaced columns changed; the spans restore
+        // the original row from the patched one
+        assert_eq!(spans.len(), 3);
+        assert_eq!(delta::apply(&patched, &delta::encode_spans(&spans)).unwrap(), encoded);
+    }
+}
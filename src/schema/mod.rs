@@ -0,0 +1,428 @@
+// src/schema/mod.rs
+pub(crate) mod encoding;
+
+use std::fmt;
+use std::sync::Arc;
+use crate::data::delta;
+use crate::error::{MaemioError, Result};
+
+/// Type of a column's values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Int,
+    Float,
+    String,
+    Bytes,
+    Bool,
+}
+
+/// A single column value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Int(i64),
+    Float(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    Bool(bool),
+}
+
+impl Value {
+    /// Returns the column type this value belongs to, or `None` for null
+    pub fn column_type(&self) -> Option<ColumnType> {
+        match self {
+            Value::Null => None,
+            Value::Int(_) => Some(ColumnType::Int),
+            Value::Float(_) => Some(ColumnType::Float),
+            Value::String(_) => Some(ColumnType::String),
+            Value::Bytes(_) => Some(ColumnType::Bytes),
+            Value::Bool(_) => Some(ColumnType::Bool),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+}
+
+/// A named, typed column
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub column_type: ColumnType,
+    pub nullable: bool,
+}
+
+impl Column {
+    /// Creates a column that must always hold a value
+    pub fn new(name: &str, column_type: ColumnType) -> Self {
+        Self {
+            name: name.to_string(),
+            column_type,
+            nullable: false,
+        }
+    }
+
+    /// Creates a column that may hold null
+    pub fn nullable(name: &str, column_type: ColumnType) -> Self {
+        Self {
+            nullable: true,
+            ..Self::new(name, column_type)
+        }
+    }
+}
+
+/// A structured record value laid out according to a schema
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    values: Vec<Value>,
+}
+
+impl Row {
+    pub fn new(values: Vec<Value>) -> Self {
+        Self { values }
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    pub fn into_values(self) -> Vec<Value> {
+        self.values
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&Value> {
+        self.values.get(idx)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// Predicate a row must satisfy for a check constraint to pass
+pub type CheckFn = dyn Fn(&Row) -> bool + Send + Sync;
+
+/// Declarative rule over a table's rows, enforced when a transaction commits.
+///
+/// Nulls are ruled out per column with `Column::new`, on write.
+#[derive(Clone)]
+pub enum Constraint {
+    /// Rows must satisfy the predicate
+    Check { name: String, predicate: Arc<CheckFn> },
+}
+
+impl Constraint {
+    pub fn check<F>(name: &str, predicate: F) -> Self
+    where
+        F: Fn(&Row) -> bool + Send + Sync + 'static,
+    {
+        Constraint::Check {
+            name: name.to_string(),
+            predicate: Arc::new(predicate),
+        }
+    }
+}
+
+impl fmt::Debug for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constraint::Check { name, .. } => f.debug_struct("Check").field("name", name).finish(),
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constraint::Check { name, .. } => write!(f, "CHECK {}", name),
+        }
+    }
+}
+
+/// Check constraints are the same when they share a name and a predicate
+impl PartialEq for Constraint {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                Constraint::Check { name, predicate },
+                Constraint::Check { name: other_name, predicate: other_predicate },
+            ) => name == other_name && Arc::ptr_eq(predicate, other_predicate),
+        }
+    }
+}
+
+/// Ordered set of columns describing the rows of a table
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schema {
+    columns: Vec<Column>,
+    constraints: Vec<Constraint>,
+}
+
+impl Schema {
+    /// Creates a schema, rejecting empty or duplicate column names
+    pub fn new(columns: Vec<Column>) -> Result<Self> {
+        for (i, column) in columns.iter().enumerate() {
+            if column.name.is_empty() {
+                return Err(MaemioError::SchemaViolation("Column names must not be empty".into()));
+            }
+            if columns[..i].iter().any(|other| other.name == column.name) {
+                return Err(MaemioError::SchemaViolation(format!(
+                    "Duplicate column {}", column.name
+                )));
+            }
+        }
+        Ok(Self { columns, constraints: Vec::new() })
+    }
+
+    /// Adds a constraint checked when transactions commit
+    pub fn with_constraint(mut self, constraint: Constraint) -> Result<Self> {
+        self.constraints.push(constraint);
+        Ok(self)
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    pub fn constraints(&self) -> &[Constraint] {
+        &self.constraints
+    }
+
+    /// Returns the position of a column by name
+    pub fn column_index(&self, name: &str) -> Result<usize> {
+        self.columns
+            .iter()
+            .position(|column| column.name == name)
+            .ok_or_else(|| MaemioError::SchemaViolation(format!("Unknown column {}", name)))
+    }
+
+    /// Looks up a column's value in a row by name
+    pub fn get<'r>(&self, row: &'r Row, name: &str) -> Result<&'r Value> {
+        let idx = self.column_index(name)?;
+        row.get(idx)
+            .ok_or_else(|| MaemioError::SchemaViolation(format!("Row has no column {}", name)))
+    }
+
+    /// Checks that a row has one value of the right type per column
+    pub fn validate(&self, row: &Row) -> Result<()> {
+        if row.len() != self.columns.len() {
+            return Err(MaemioError::SchemaViolation(format!(
+                "Expected {} columns, got {}", self.columns.len(), row.len()
+            )));
+        }
+
+        for (column, value) in self.columns.iter().zip(row.values()) {
+            self.validate_value(column, value)?;
+        }
+        Ok(())
+    }
+
+    fn validate_value(&self, column: &Column, value: &Value) -> Result<()> {
+        match value.column_type() {
+            None if !column.nullable => Err(MaemioError::SchemaViolation(format!(
+                "Column {} is not nullable", column.name
+            ))),
+            Some(value_type) if value_type != column.column_type => Err(MaemioError::SchemaViolation(format!(
+                "Column {} expects {:?}, got {:?}", column.name, column.column_type, value_type
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks a row against the schema's declared constraints
+    pub fn check_constraints(&self, row: &Row) -> Result<()> {
+        for constraint in &self.constraints {
+            match constraint {
+                Constraint::Check { name, predicate } => {
+                    if !predicate(row) {
+                        return Err(MaemioError::ConstraintViolation(format!(
+                            "Check {} failed", name
+                        )));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates a row and encodes it in the binary row format
+    pub fn encode_row(&self, row: &Row) -> Result<Vec<u8>> {
+        self.validate(row)?;
+        Ok(encoding::encode(row))
+    }
+
+    /// Decodes only the named columns of an encoded row, in the order given
+    pub fn decode_columns(&self, data: &[u8], names: &[&str]) -> Result<Vec<Value>> {
+        let indexes = names
+            .iter()
+            .map(|name| self.column_index(name))
+            .collect::<Result<Vec<_>>>()?;
+        self.decode_columns_at(data, &indexes)
+    }
+
+    /// Decodes and validates only the columns at the given positions of an
+    /// encoded row
+    pub(crate) fn decode_columns_at(&self, data: &[u8], indexes: &[usize]) -> Result<Vec<Value>> {
+        let values = encoding::decode_columns(&self.columns, data, indexes)?;
+        for (&idx, value) in indexes.iter().zip(&values) {
+            self.validate_value(&self.columns[idx], value)?;
+        }
+        Ok(values)
+    }
+
+    /// Replaces some columns of an encoded row, leaving the bytes of the
+    /// other columns untouched
+    pub fn patch_row(&self, data: &[u8], updates: &[(&str, Value)]) -> Result<Vec<u8>> {
+        let (patched, _) = encoding::patch(&self.columns, data, &self.update_positions(updates)?)?;
+        Ok(patched)
+    }
+
+    /// Patches a row like `patch_row`, also returning the original row
+    /// encoded as a delta against the patched one. The delta holds only the
+    /// replaced columns.
+    pub(crate) fn patch_row_with_delta(&self, data: &[u8], updates: &[(&str, Value)]) -> Result<(Vec<u8>, Vec<u8>)> {
+        let (patched, spans) = encoding::patch(&self.columns, data, &self.update_positions(updates)?)?;
+        let delta = delta::encode_spans(&spans);
+        Ok((patched, delta))
+    }
+
+    fn update_positions(&self, updates: &[(&str, Value)]) -> Result<Vec<(usize, Value)>> {
+        let mut positions = Vec::with_capacity(updates.len());
+        for (name, value) in updates {
+            let idx = self.column_index(name)?;
+            self.validate_value(&self.columns[idx], value)?;
+            positions.push((idx, value.clone()));
+        }
+        Ok(positions)
+    }
+
+    /// Decodes and validates a row in the binary row format
+    pub fn decode_row(&self, data: &[u8]) -> Result<Row> {
+        let row = encoding::decode(&self.columns, data)?;
+        self.validate(&row)?;
+        Ok(row)
+    }
+}
+
+/// Renders the schema as `name Type [NULL], ...` followed by its constraints
+impl fmt::Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let columns = self.columns.iter().map(|column| {
+            let null = if column.nullable { " NULL" } else { "" };
+            format!("{} {:?}{}", column.name, column.column_type, null)
+        });
+        let constraints = self.constraints.iter().map(|constraint| constraint.to_string());
+        let parts: Vec<String> = columns.chain(constraints).collect();
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_schema() -> Schema {
+        Schema::new(vec![
+            Column::new("id", ColumnType::Int),
+            Column::new("name", ColumnType::String),
+            Column::nullable("email", ColumnType::String),
+            Column::new("score", ColumnType::Float),
+            Column::new("active", ColumnType::Bool),
+            Column::nullable("avatar", ColumnType::Bytes),
+        ]).unwrap()
+    }
+
+    #[test]
+    fn test_row_round_trip() {
+        let schema = user_schema();
+        let row = Row::new(vec![
+            Value::Int(-42),
+            Value::String("alice".into()),
+            Value::Null,
+            Value::Float(1.5),
+            Value::Bool(true),
+            Value::Bytes(vec![0, 255]),
+        ]);
+
+        let encoded = schema.encode_row(&row).unwrap();
+        assert_eq!(schema.decode_row(&encoded).unwrap(), row);
+        assert_eq!(schema.get(&row, "name").unwrap(), &Value::String("alice".into()));
+    }
+
+    #[test]
+    fn test_validation() {
+        let schema = user_schema();
+
+        // Wrong arity
+        assert!(schema.encode_row(&Row::new(vec![Value::Int(1)])).is_err());
+
+        // Null in a non-nullable column
+        let row = Row::new(vec![
+            Value::Null,
+            Value::String("bob".into()),
+            Value::Null,
+            Value::Float(0.0),
+            Value::Bool(false),
+            Value::Null,
+        ]);
+        assert!(matches!(schema.encode_row(&row), Err(MaemioError::SchemaViolation(_))));
+
+        // Projections validate the columns they decode
+        let encoded = encoding::encode(&row);
+        assert!(matches!(schema.decode_columns(&encoded, &["id"]), Err(MaemioError::SchemaViolation(_))));
+        assert!(schema.decode_columns(&encoded, &["name"]).is_ok());
+
+        // Wrong type
+        let row = Row::new(vec![
+            Value::Int(1),
+            Value::Int(2),
+            Value::Null,
+            Value::Float(0.0),
+            Value::Bool(false),
+            Value::Null,
+        ]);
+        assert!(schema.encode_row(&row).is_err());
+
+        // Duplicate columns
+        assert!(Schema::new(vec![
+            Column::new("a", ColumnType::Int),
+            Column::new("a", ColumnType::Bool),
+        ]).is_err());
+    }
+
+    #[test]
+    fn test_constraints() {
+        let schema = user_schema()
+            .with_constraint(Constraint::check("positive_score", |row| {
+                matches!(row.get(3), Some(Value::Float(score)) if *score >= 0.0)
+            })).unwrap();
+        assert_eq!(schema.clone(), schema);
+        assert_ne!(schema, user_schema());
+
+        let mut values = vec![
+            Value::Int(1),
+            Value::String("carol".into()),
+            Value::String("carol@example.com".into()),
+            Value::Float(2.0),
+            Value::Bool(true),
+            Value::Null,
+        ];
+        assert!(schema.check_constraints(&Row::new(values.clone())).is_ok());
+
+        values[3] = Value::Float(-1.0);
+        assert!(matches!(
+            schema.check_constraints(&Row::new(values.clone())),
+            Err(MaemioError::ConstraintViolation(_))
+        ));
+
+        // Constraints are deferred to commit, so encoding still succeeds
+        let row = Row::new(values);
+        assert!(schema.encode_row(&row).is_ok());
+        assert!(schema.check_constraints(&row).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+// src/sequence/allocator.rs
+use super::Sequence;
+use crate::error::Result;
+use parking_lot::RwLock;
+use uuid::Uuid;
+
+/// Strategy used to generate record ids for a table
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdStrategy {
+    /// Increasing ids starting at 1
+    Sequential,
+    /// Random ids derived from a version 4 UUID
+    Uuid,
+}
+
+/// Hands out record ids for a single table.
+///
+/// Ids are unique among the ids this allocator produced. Callers that also
+/// pick ids by hand should retry on collision, which `Maemio::create_record_auto`
+/// does for them.
+pub struct IdAllocator {
+    strategy: RwLock<IdStrategy>,
+    // Kept across strategy changes so sequential ids never repeat
+    sequence: Sequence,
+}
+
+impl IdAllocator {
+    pub fn new(strategy: IdStrategy) -> Self {
+        Self {
+            strategy: RwLock::new(strategy),
+            sequence: Sequence::new("record_id", 1, 1),
+        }
+    }
+
+    pub fn strategy(&self) -> IdStrategy {
+        *self.strategy.read()
+    }
+
+    /// Switches strategies; sequential ids continue where they left off
+    pub fn set_strategy(&self, strategy: IdStrategy) {
+        *self.strategy.write() = strategy;
+    }
+
+    /// Returns the next record id
+    pub fn next_id(&self) -> Result<u64> {
+        match self.strategy() {
+            IdStrategy::Sequential => self.sequence.next_value(),
+            IdStrategy::Uuid => Ok(Self::uuid_to_id(Uuid::new_v4())),
+        }
+    }
+
+    /// Folds the 128-bit UUID into the 64-bit record id space
+    fn uuid_to_id(uuid: Uuid) -> u64 {
+        let (high, low) = uuid.as_u64_pair();
+        high ^ low
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_sequential_ids() {
+        let allocator = IdAllocator::new(IdStrategy::Sequential);
+        assert_eq!(allocator.next_id().unwrap(), 1);
+        assert_eq!(allocator.next_id().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_strategy_change_keeps_counter() {
+        let allocator = IdAllocator::new(IdStrategy::Sequential);
+        assert_eq!(allocator.next_id().unwrap(), 1);
+        allocator.set_strategy(IdStrategy::Uuid);
+        allocator.next_id().unwrap();
+        allocator.set_strategy(IdStrategy::Sequential);
+        assert_eq!(allocator.next_id().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_uuid_ids() {
+        let allocator = IdAllocator::new(IdStrategy::Uuid);
+        let ids: HashSet<u64> = (0..1000).map(|_| allocator.next_id().unwrap()).collect();
+        assert_eq!(ids.len(), 1000);
+    }
+}
@@ -0,0 +1,181 @@
+// src/sequence/mod.rs
+mod allocator;
+pub use allocator::{IdAllocator, IdStrategy};
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use parking_lot::RwLock;
+use crate::error::{MaemioError, Result};
+
+/// A named, monotonically increasing counter.
+///
+/// Values are handed out atomically and are never reused, even when the
+/// transaction that drew them aborts. This keeps concurrent writers from
+/// conflicting on the sequence at the cost of occasional gaps.
+pub struct Sequence {
+    name: String,
+    next: AtomicU64,
+    increment: u64,
+}
+
+impl Sequence {
+    pub fn new(name: &str, start: u64, increment: u64) -> Self {
+        Self {
+            name: name.to_string(),
+            next: AtomicU64::new(start),
+            increment,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn increment(&self) -> u64 {
+        self.increment
+    }
+
+    /// Returns the next value and advances the sequence
+    pub fn next_value(&self) -> Result<u64> {
+        self.next
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+                current.checked_add(self.increment)
+            })
+            .map_err(|_| MaemioError::System(format!(
+                "Sequence {} exhausted", self.name
+            )))
+    }
+
+    /// Returns the value the next call to `next_value` will hand out
+    pub fn peek(&self) -> u64 {
+        self.next.load(Ordering::Acquire)
+    }
+}
+
+/// Manages named sequences
+pub struct SequenceManager {
+    sequences: RwLock<HashMap<String, Arc<Sequence>>>,
+}
+
+impl SequenceManager {
+    pub fn new() -> Self {
+        Self {
+            sequences: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Creates a new sequence starting at `start` and advancing by `increment`
+    pub fn create_sequence(&self, name: &str, start: u64, increment: u64) -> Result<()> {
+        if increment == 0 {
+            return Err(MaemioError::System(format!(
+                "Sequence {} must have a non-zero increment", name
+            )));
+        }
+
+        let mut sequences = self.sequences.write();
+        if sequences.contains_key(name) {
+            return Err(MaemioError::System(format!(
+                "Sequence {} already exists", name
+            )));
+        }
+
+        sequences.insert(name.to_string(), Arc::new(Sequence::new(name, start, increment)));
+        Ok(())
+    }
+
+    /// Gets an existing sequence
+    pub fn get_sequence(&self, name: &str) -> Result<Arc<Sequence>> {
+        self.sequences.read()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| MaemioError::SequenceNotFound(name.to_string()))
+    }
+
+    /// Drops an existing sequence
+    pub fn drop_sequence(&self, name: &str) -> Result<()> {
+        self.remove_sequence(name).map(|_| ())
+    }
+
+    /// Removes a sequence, handing it back so a rolled-back drop can restore it
+    pub(crate) fn remove_sequence(&self, name: &str) -> Result<Arc<Sequence>> {
+        self.sequences.write()
+            .remove(name)
+            .ok_or_else(|| MaemioError::SequenceNotFound(name.to_string()))
+    }
+
+    /// Puts back a sequence taken out by `remove_sequence`
+    pub(crate) fn restore_sequence(&self, sequence: Arc<Sequence>) -> Result<()> {
+        let mut sequences = self.sequences.write();
+        if sequences.contains_key(sequence.name()) {
+            return Err(MaemioError::System(format!(
+                "Sequence {} already exists", sequence.name()
+            )));
+        }
+        sequences.insert(sequence.name().to_string(), sequence);
+        Ok(())
+    }
+
+    /// Draws the next value from a named sequence
+    pub fn next_value(&self, name: &str) -> Result<u64> {
+        self.get_sequence(name)?.next_value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence_values() {
+        let manager = SequenceManager::new();
+        manager.create_sequence("orders", 10, 5).unwrap();
+
+        assert_eq!(manager.next_value("orders").unwrap(), 10);
+        assert_eq!(manager.next_value("orders").unwrap(), 15);
+        assert_eq!(manager.get_sequence("orders").unwrap().peek(), 20);
+
+        // Duplicate and missing sequences are rejected
+        assert!(manager.create_sequence("orders", 0, 1).is_err());
+        assert!(matches!(
+            manager.next_value("missing"),
+            Err(MaemioError::SequenceNotFound(_))
+        ));
+
+        manager.drop_sequence("orders").unwrap();
+        assert!(manager.get_sequence("orders").is_err());
+    }
+
+    #[test]
+    fn test_sequence_exhaustion() {
+        let manager = SequenceManager::new();
+        manager.create_sequence("tiny", u64::MAX - 1, 1).unwrap();
+
+        assert_eq!(manager.next_value("tiny").unwrap(), u64::MAX - 1);
+        assert!(manager.next_value("tiny").is_err());
+    }
+
+    #[test]
+    fn test_concurrent_sequence_values_are_unique() {
+        let manager = Arc::new(SequenceManager::new());
+        manager.create_sequence("ids", 1, 1).unwrap();
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let manager = manager.clone();
+                std::thread::spawn(move || {
+                    (0..1000)
+                        .map(|_| manager.next_value("ids").unwrap())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        let mut values: Vec<u64> = handles.into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        values.sort_unstable();
+        values.dedup();
+        assert_eq!(values.len(), 4000);
+    }
+}
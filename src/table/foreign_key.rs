@@ -0,0 +1,85 @@
+// src/table/foreign_key.rs
+use std::sync::Arc;
+use super::TableIndex;
+use crate::error::{MaemioError, Result};
+use crate::index::IndexKey;
+
+/// What deleting a referenced parent record does to the children pointing at it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnDelete {
+    /// The delete fails while children still reference the parent
+    Restrict,
+    /// The children are deleted in the same transaction
+    Cascade,
+}
+
+/// Reference from an integer column of a child table to record ids of a parent table.
+///
+/// Null references are allowed. The child column is indexed so deletes of a
+/// parent can find its children without scanning the child table.
+pub struct ForeignKey {
+    name: String,
+    child_table: u64,
+    parent_table: u64,
+    on_delete: OnDelete,
+    // Maintained index on the child column; its commit lock also serializes
+    // reference checks against deletes of parents
+    pub(crate) index: Arc<TableIndex>,
+}
+
+impl ForeignKey {
+    pub(crate) fn new(
+        name: &str,
+        child_table: u64,
+        parent_table: u64,
+        on_delete: OnDelete,
+        index: Arc<TableIndex>,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            child_table,
+            parent_table,
+            on_delete,
+            index,
+        }
+    }
+
+    /// Name of the index maintained on the child column of a foreign key
+    pub(crate) fn index_name(name: &str) -> String {
+        format!("fk_{}", name)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn child_table(&self) -> u64 {
+        self.child_table
+    }
+
+    pub fn parent_table(&self) -> u64 {
+        self.parent_table
+    }
+
+    pub fn on_delete(&self) -> OnDelete {
+        self.on_delete
+    }
+
+    /// Returns the parent record a child row points at, if any. Negative
+    /// values cannot name a record and are rejected.
+    pub(crate) fn parent_of(&self, data: &[u8]) -> Result<Option<u64>> {
+        match self.index.key.extract(data)? {
+            Some(IndexKey::Int(parent_id)) => u64::try_from(parent_id).map(Some).map_err(|_| {
+                MaemioError::ConstraintViolation(format!(
+                    "Foreign key {}: {} is not a record id", self.name, parent_id
+                ))
+            }),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the committed children currently pointing at a parent record
+    pub(crate) fn children(&self, parent_id: u64) -> Result<Vec<u64>> {
+        self.index.index.get_all(&IndexKey::Int(parent_id as i64), u64::MAX)
+    }
+}
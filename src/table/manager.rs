@@ -0,0 +1,185 @@
+// src/table/manager.rs
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use parking_lot::RwLock;
+use super::{Table, DEFAULT_TABLE_ID, DEFAULT_TABLE_NAME};
+use crate::catalog::{catalog_schema, CATALOG_TABLE_ID, CATALOG_TABLE_NAME};
+use crate::error::{MaemioError, Result};
+use crate::schema::Schema;
+
+#[derive(Default)]
+struct TableMaps {
+    by_id: HashMap<u64, Arc<Table>>,
+    by_name: HashMap<String, u64>,
+}
+
+/// Tracks all tables in the database
+pub struct TableManager {
+    tables: RwLock<TableMaps>,
+    next_table_id: AtomicU64,
+    shard_count: usize,
+    // Delta threshold new tables start with
+    delta_threshold: Option<usize>,
+}
+
+impl TableManager {
+    /// Creates a manager holding the default table and the system catalog
+    pub fn new(shard_count: usize) -> Self {
+        let manager = Self {
+            tables: RwLock::new(TableMaps::default()),
+            next_table_id: AtomicU64::new(DEFAULT_TABLE_ID + 1),
+            shard_count,
+            delta_threshold: None,
+        };
+
+        let default_table = Arc::new(Table::new(DEFAULT_TABLE_ID, DEFAULT_TABLE_NAME, shard_count, None));
+        let catalog_table = Arc::new(Table::new(
+            CATALOG_TABLE_ID, CATALOG_TABLE_NAME, shard_count, Some(catalog_schema()),
+        ));
+        let mut tables = manager.tables.write();
+        for table in [default_table, catalog_table] {
+            tables.by_name.insert(table.name().to_string(), table.id());
+            tables.by_id.insert(table.id(), table);
+        }
+        drop(tables);
+
+        manager
+    }
+
+    /// Makes new tables and the default table store superseded versions of
+    /// at least `min_size` bytes as deltas; see `Table::set_delta_threshold`
+    pub fn with_delta_threshold(mut self, min_size: Option<usize>) -> Self {
+        self.delta_threshold = min_size;
+        if let Ok(default_table) = self.get_table(DEFAULT_TABLE_ID) {
+            default_table.set_delta_threshold(min_size);
+        }
+        self
+    }
+
+    /// Creates a new table with the next free table id
+    pub fn create_table(&self, name: &str) -> Result<Arc<Table>> {
+        self.create_table_with_schema(name, None)
+    }
+
+    /// Creates a new table whose rows must follow `schema`
+    pub fn create_table_with_schema(&self, name: &str, schema: Option<Schema>) -> Result<Arc<Table>> {
+        let mut tables = self.tables.write();
+        if tables.by_name.contains_key(name) {
+            return Err(MaemioError::System(format!("Table {} already exists", name)));
+        }
+
+        let table = self.allocate_table(name, schema);
+        tables.by_name.insert(name.to_string(), table.id());
+        tables.by_id.insert(table.id(), table.clone());
+        Ok(table)
+    }
+
+    /// Builds a table under a fresh id without making it visible yet
+    pub(crate) fn allocate_table(&self, name: &str, schema: Option<Schema>) -> Arc<Table> {
+        let table_id = self.next_table_id.fetch_add(1, Ordering::Relaxed);
+        let table = Table::new(table_id, name, self.shard_count, schema);
+        table.set_delta_threshold(self.delta_threshold);
+        Arc::new(table)
+    }
+
+    /// Makes an allocated or previously dropped table visible under its name
+    pub(crate) fn register_table(&self, table: Arc<Table>) -> Result<()> {
+        let mut tables = self.tables.write();
+        if tables.by_name.contains_key(table.name()) || tables.by_id.contains_key(&table.id()) {
+            return Err(MaemioError::System(format!("Table {} already exists", table.name())));
+        }
+        tables.by_name.insert(table.name().to_string(), table.id());
+        tables.by_id.insert(table.id(), table);
+        Ok(())
+    }
+
+    /// Gets a table by id
+    pub fn get_table(&self, table_id: u64) -> Result<Arc<Table>> {
+        self.tables.read()
+            .by_id
+            .get(&table_id)
+            .cloned()
+            .ok_or_else(|| MaemioError::TableNotFound(table_id.to_string()))
+    }
+
+    /// Gets a table by name
+    pub fn get_table_by_name(&self, name: &str) -> Result<Arc<Table>> {
+        let tables = self.tables.read();
+        tables.by_name
+            .get(name)
+            .and_then(|id| tables.by_id.get(id))
+            .cloned()
+            .ok_or_else(|| MaemioError::TableNotFound(name.to_string()))
+    }
+
+    pub fn default_table(&self) -> Arc<Table> {
+        self.get_table(DEFAULT_TABLE_ID)
+            .expect("default table always exists")
+    }
+
+    pub fn catalog_table(&self) -> Arc<Table> {
+        self.get_table(CATALOG_TABLE_ID)
+            .expect("catalog table always exists")
+    }
+
+    pub fn contains(&self, table_id: u64) -> bool {
+        self.tables.read().by_id.contains_key(&table_id)
+    }
+
+    /// Removes a table. Its records are freed once no transaction holds them.
+    pub fn drop_table(&self, table_id: u64) -> Result<Arc<Table>> {
+        if table_id == DEFAULT_TABLE_ID || table_id == CATALOG_TABLE_ID {
+            return Err(MaemioError::System("System tables cannot be dropped".into()));
+        }
+
+        let mut tables = self.tables.write();
+        let table = tables.by_id
+            .remove(&table_id)
+            .ok_or_else(|| MaemioError::TableNotFound(table_id.to_string()))?;
+        tables.by_name.remove(table.name());
+        Ok(table)
+    }
+
+    /// Lists all tables ordered by id
+    pub fn tables(&self) -> Vec<Arc<Table>> {
+        let mut tables: Vec<_> = self.tables.read().by_id.values().cloned().collect();
+        tables.sort_by_key(|table| table.id());
+        tables
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_lifecycle() {
+        let manager = TableManager::new(4);
+        assert_eq!(manager.default_table().name(), DEFAULT_TABLE_NAME);
+
+        let users = manager.create_table("users").unwrap();
+        assert_eq!(users.id(), 1);
+        assert!(manager.create_table("users").is_err());
+        assert_eq!(manager.get_table_by_name("users").unwrap().id(), 1);
+
+        manager.drop_table(users.id()).unwrap();
+        assert!(matches!(manager.get_table(1), Err(MaemioError::TableNotFound(_))));
+        assert!(manager.get_table_by_name("users").is_err());
+        assert!(manager.drop_table(DEFAULT_TABLE_ID).is_err());
+
+        // Table ids are never reused
+        assert_eq!(manager.create_table("users").unwrap().id(), 2);
+    }
+
+    #[test]
+    fn test_tables_namespace_record_ids() {
+        let manager = TableManager::new(4);
+        let a = manager.create_table("a").unwrap();
+        let b = manager.create_table("b").unwrap();
+
+        a.create_record(5, 0).unwrap();
+        b.create_record(5, 0).unwrap();
+        assert!(!Arc::ptr_eq(&a.get_record(5).unwrap(), &b.get_record(5).unwrap()));
+    }
+}
@@ -0,0 +1,578 @@
+// src/table/mod.rs
+mod manager;
+mod foreign_key;
+pub use manager::TableManager;
+pub use foreign_key::{ForeignKey, OnDelete};
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::ops::RangeBounds;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use parking_lot::{Mutex, RwLock};
+use crate::data::{
+    Compression, CompressionCounters, CompressionStats, MemoryCounters, MemoryUsage, RecordHead, RecordTable,
+    Version, MAX_INLINE_SIZE, RECORD_OVERHEAD, VERSION_OVERHEAD,
+};
+use crate::error::{MaemioError, Result};
+use crate::index::{BoundExtractor, Index, IndexKey};
+use crate::schema::Schema;
+use crate::sequence::{IdAllocator, IdStrategy};
+
+/// Id of the table that holds records created without naming a table
+pub const DEFAULT_TABLE_ID: u64 = 0;
+
+/// Name of the default table
+pub const DEFAULT_TABLE_NAME: &str = "default";
+
+/// An index kept in sync with a table's records when transactions commit
+pub(crate) struct TableIndex {
+    pub(crate) name: String,
+    pub(crate) index: Arc<dyn Index>,
+    pub(crate) key: BoundExtractor,
+    // Serializes uniqueness checks of committing transactions
+    pub(crate) commit_lock: Mutex<()>,
+    // Entries removed from the index, with the timestamp of the commit that
+    // removed them. The index only holds the latest state; older snapshots
+    // find the records they can still see here.
+    removed: Mutex<BTreeMap<IndexKey, Vec<(u64, u64)>>>,
+}
+
+impl TableIndex {
+    pub(crate) fn new(name: &str, index: Arc<dyn Index>, key: BoundExtractor) -> Self {
+        Self {
+            name: name.to_string(),
+            index,
+            key,
+            commit_lock: Mutex::new(()),
+            removed: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Remembers an entry a commit at `ts` removed from the index
+    pub(crate) fn record_removal(&self, key: IndexKey, record_id: u64, ts: u64) {
+        self.removed.lock().entry(key).or_default().push((record_id, ts));
+    }
+
+    /// Records that `key` mapped to before removals not yet pruned
+    pub(crate) fn removed_records(&self, key: &IndexKey) -> Vec<u64> {
+        self.removed
+            .lock()
+            .get(key)
+            .map_or_else(Vec::new, |entries| entries.iter().map(|&(record_id, _)| record_id).collect())
+    }
+
+    /// Removed entries with keys in `range`, in key order
+    pub(crate) fn removed_in<R: RangeBounds<IndexKey>>(&self, range: R) -> Vec<(IndexKey, u64)> {
+        self.removed
+            .lock()
+            .range(range)
+            .flat_map(|(key, entries)| entries.iter().map(move |&(record_id, _)| (key.clone(), record_id)))
+            .collect()
+    }
+
+    /// Forgets removals older than every snapshot still reading
+    pub(crate) fn prune_removed(&self, min_rts: u64) {
+        self.removed.lock().retain(|_, entries| {
+            entries.retain(|&(_, ts)| ts >= min_rts);
+            !entries.is_empty()
+        });
+    }
+}
+
+/// A named collection of records with its own record id space
+pub struct Table {
+    id: u64,
+    name: String,
+    records: RecordTable,
+    // Records holding blob chunks, kept apart so scans and counts only see
+    // the table's own records; they share the table's id space
+    chunks: RecordTable,
+    id_allocator: IdAllocator,
+    // Row layout enforced on writes; `None` for tables of opaque bytes
+    schema: Option<Arc<Schema>>,
+    // Indexes maintained automatically on commit
+    indexes: RwLock<Vec<Arc<TableIndex>>>,
+    // Bumped whenever an index is attached, so commits notice indexes they
+    // did not prepare entries for
+    index_generation: AtomicU64,
+    // Commits between their index generation check and their last index entry
+    commits_in_flight: AtomicUsize,
+    // Foreign keys declared on this table's columns
+    foreign_keys: RwLock<Vec<Arc<ForeignKey>>>,
+    // Foreign keys of other tables pointing at this table's records
+    referenced_by: RwLock<Vec<Arc<ForeignKey>>>,
+    // Minimum size of versions stored as deltas; 0 keeps full copies only
+    delta_threshold: AtomicUsize,
+    compression: RwLock<Compression>,
+    compression_counters: CompressionCounters,
+    // Versions and payload bytes of all records, kept up to date by the records
+    memory: Arc<MemoryCounters>,
+    // Most bytes the table may use; 0 means unlimited
+    memory_quota: AtomicUsize,
+    // Bytes claimed by commits that passed the quota check but are not
+    // counted in the table's memory use yet
+    quota_reserved: AtomicUsize,
+    // Deadlines of committed expiring writes, soonest first
+    expiry: Mutex<BinaryHeap<Reverse<(Instant, u64)>>>,
+    // Committed deletes of records nothing refers to anymore, by write
+    // timestamp, oldest first
+    dead: Mutex<BinaryHeap<Reverse<(u64, u64)>>>,
+}
+
+/// Part of a table's memory quota claimed by a committing transaction,
+/// handed back when dropped
+pub(crate) struct QuotaReservation {
+    table: Arc<Table>,
+    bytes: usize,
+}
+
+impl Drop for QuotaReservation {
+    fn drop(&mut self) {
+        self.table.quota_reserved.fetch_sub(self.bytes, Ordering::AcqRel);
+    }
+}
+
+/// A commit registered as in flight on a table, released when dropped
+pub(crate) struct CommitPass {
+    table: Arc<Table>,
+}
+
+impl Drop for CommitPass {
+    fn drop(&mut self) {
+        self.table.commits_in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Table {
+    pub(crate) fn new(id: u64, name: &str, shard_count: usize, schema: Option<Schema>) -> Self {
+        Self {
+            id,
+            name: name.to_string(),
+            records: RecordTable::new(shard_count),
+            chunks: RecordTable::new(shard_count),
+            id_allocator: IdAllocator::new(IdStrategy::Sequential),
+            schema: schema.map(Arc::new),
+            indexes: RwLock::new(Vec::new()),
+            index_generation: AtomicU64::new(0),
+            commits_in_flight: AtomicUsize::new(0),
+            foreign_keys: RwLock::new(Vec::new()),
+            referenced_by: RwLock::new(Vec::new()),
+            delta_threshold: AtomicUsize::new(0),
+            compression: RwLock::new(Compression::None),
+            compression_counters: CompressionCounters::default(),
+            memory: Arc::default(),
+            memory_quota: AtomicUsize::new(0),
+            quota_reserved: AtomicUsize::new(0),
+            expiry: Mutex::new(BinaryHeap::new()),
+            dead: Mutex::new(BinaryHeap::new()),
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn schema(&self) -> Option<&Arc<Schema>> {
+        self.schema.as_ref()
+    }
+
+    /// Returns the table's schema or an error for schemaless tables
+    pub(crate) fn require_schema(&self) -> Result<&Arc<Schema>> {
+        self.schema.as_ref().ok_or_else(|| MaemioError::SchemaViolation(format!(
+            "Table {} has no schema", self.name
+        )))
+    }
+
+    pub(crate) fn attach_index(&self, index: TableIndex) -> Arc<TableIndex> {
+        let index = Arc::new(index);
+        let mut indexes = self.indexes.write();
+        indexes.push(index.clone());
+        self.index_generation.fetch_add(1, Ordering::SeqCst);
+        index
+    }
+
+    pub(crate) fn detach_index(&self, name: &str) -> Option<Arc<TableIndex>> {
+        let mut indexes = self.indexes.write();
+        let pos = indexes.iter().position(|index| index.name == name)?;
+        Some(indexes.remove(pos))
+    }
+
+    /// Re-attaches an index removed by `detach_index`
+    pub(crate) fn reattach_index(&self, index: Arc<TableIndex>) {
+        self.indexes.write().push(index);
+    }
+
+    pub(crate) fn indexes(&self) -> Vec<Arc<TableIndex>> {
+        self.indexes.read().clone()
+    }
+
+    /// Returns a counter that changes whenever an index is attached. Read it
+    /// before `indexes` to detect indexes attached after the list was taken.
+    pub(crate) fn index_generation(&self) -> u64 {
+        self.index_generation.load(Ordering::SeqCst)
+    }
+
+    /// Registers a commit whose index entries were worked out at index
+    /// `generation`, until the returned pass is dropped.
+    ///
+    /// Fails with a conflict if an index was attached since, as the commit
+    /// would leave it without entries for its writes.
+    pub(crate) fn enter_commit(self: &Arc<Self>, generation: u64) -> Result<CommitPass> {
+        self.commits_in_flight.fetch_add(1, Ordering::SeqCst);
+        // Dropped on failure, leaving the table again
+        let pass = CommitPass { table: self.clone() };
+        if self.index_generation.load(Ordering::SeqCst) != generation {
+            return Err(MaemioError::Conflict);
+        }
+        Ok(pass)
+    }
+
+    /// Waits until no commit that may have missed a newly attached index is
+    /// still in flight, apart from the `own` passes the caller holds.
+    ///
+    /// Commits in flight never wait on schema changes, so this cannot stall
+    /// for longer than the commits take to install their writes.
+    pub(crate) fn drain_commits(&self, own: usize) {
+        while self.commits_in_flight.load(Ordering::SeqCst) > own {
+            std::thread::yield_now();
+        }
+    }
+
+    pub(crate) fn add_foreign_key(&self, foreign_key: Arc<ForeignKey>) {
+        self.foreign_keys.write().push(foreign_key);
+    }
+
+    pub(crate) fn add_reference(&self, foreign_key: Arc<ForeignKey>) {
+        self.referenced_by.write().push(foreign_key);
+    }
+
+    pub(crate) fn remove_foreign_key(&self, name: &str) -> Option<Arc<ForeignKey>> {
+        let mut foreign_keys = self.foreign_keys.write();
+        let pos = foreign_keys.iter().position(|fk| fk.name() == name)?;
+        Some(foreign_keys.remove(pos))
+    }
+
+    pub(crate) fn remove_reference(&self, foreign_key: &Arc<ForeignKey>) {
+        self.referenced_by.write().retain(|fk| !Arc::ptr_eq(fk, foreign_key));
+    }
+
+    /// Foreign keys declared on this table
+    pub fn foreign_keys(&self) -> Vec<Arc<ForeignKey>> {
+        self.foreign_keys.read().clone()
+    }
+
+    /// Foreign keys of other tables that reference this table
+    pub fn referenced_by(&self) -> Vec<Arc<ForeignKey>> {
+        self.referenced_by.read().clone()
+    }
+
+    /// Stores superseded versions of at least `min_size` bytes as deltas
+    /// against the next newer version instead of full copies, or turns delta
+    /// encoding off with `None`.
+    ///
+    /// Deltas cut the memory held by old versions of large, slightly changed
+    /// records, at the cost of rebuilding them when they are read. Values
+    /// stored compressed are not delta encoded.
+    pub fn set_delta_threshold(&self, min_size: Option<usize>) {
+        let min_size = min_size.map_or(0, |size| size.max(1));
+        self.delta_threshold.store(min_size, Ordering::Relaxed);
+    }
+
+    pub fn delta_threshold(&self) -> Option<usize> {
+        match self.delta_threshold.load(Ordering::Relaxed) {
+            0 => None,
+            min_size => Some(min_size),
+        }
+    }
+
+    /// Sets the codec applied to values written from now on.
+    ///
+    /// Values already stored keep their encoding and stay readable.
+    pub fn set_compression(&self, compression: Compression) {
+        *self.compression.write() = compression;
+    }
+
+    pub fn compression(&self) -> Compression {
+        *self.compression.read()
+    }
+
+    /// Sizes of the values considered for compression so far
+    pub fn compression_stats(&self) -> CompressionStats {
+        self.compression_counters.snapshot()
+    }
+
+    /// Schedules an expiry check of a record written with a time to live
+    pub(crate) fn track_expiry(&self, record_id: u64, deadline: Instant) {
+        self.expiry.lock().push(Reverse((deadline, record_id)));
+    }
+
+    /// Removes and returns the records whose deadline is not after `now`
+    pub(crate) fn take_due_expiries(&self, now: Instant) -> Vec<u64> {
+        let mut expiry = self.expiry.lock();
+        let mut due = Vec::new();
+        while let Some(&Reverse((deadline, record_id))) = expiry.peek() {
+            if deadline > now {
+                break;
+            }
+            expiry.pop();
+            due.push(record_id);
+        }
+        due
+    }
+
+    /// Schedules the removal of a record deleted at `wts` that nothing
+    /// refers to anymore, such as a blob chunk dropped from its manifest
+    pub(crate) fn track_dead(&self, record_id: u64, wts: u64) {
+        self.dead.lock().push(Reverse((wts, record_id)));
+    }
+
+    /// Removes the records scheduled by `track_dead` whose delete is older
+    /// than every snapshot from `min_rts` on. Returns how many were removed.
+    pub(crate) fn reclaim_dead(&self, min_rts: u64) -> usize {
+        let mut dead = self.dead.lock();
+        let mut removed = 0;
+        while let Some(&Reverse((wts, record_id))) = dead.peek() {
+            if wts >= min_rts {
+                break;
+            }
+            dead.pop();
+            // Only the delete the record was scheduled for lets it go
+            let deleted = self
+                .get_record(record_id)
+                .ok()
+                .and_then(|record| record.find_visible_version(u64::MAX).ok().flatten())
+                .is_some_and(|version| version.is_tombstone() && version.wts == wts);
+            if deleted {
+                self.remove_record(record_id);
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    /// Counts a considered value once it is installed
+    pub(crate) fn record_compression(&self, raw_len: usize, stored_len: usize) {
+        self.compression_counters.record(raw_len, stored_len);
+    }
+
+    /// Whether a version about to be installed is considered for compression
+    pub(crate) fn compresses(&self, version: &Version) -> bool {
+        self.compression() != Compression::None
+            && !version.is_tombstone()
+            && version.data.len() > MAX_INLINE_SIZE
+    }
+
+    /// Returns a compressed copy of a version about to be installed, or
+    /// `None` when it is stored as is
+    pub(crate) fn compress_version(&self, version: &Version) -> Option<Version> {
+        if !self.compresses(version) {
+            return None;
+        }
+        self.compression().compress(&version.data).map(|data| {
+            let mut compressed = Version::compressed(version.wts, data);
+            compressed.expires_at = version.expires_at;
+            compressed.blob_manifest = version.blob_manifest;
+            compressed
+        })
+    }
+
+    /// Current memory use of the table's records, versions and maintained indexes
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let records = self.records.len() + self.chunks.len();
+        let versions = self.memory.versions();
+        MemoryUsage {
+            records,
+            record_bytes: records * RECORD_OVERHEAD,
+            versions,
+            data_bytes: self.memory.data_bytes(),
+            version_overhead_bytes: versions * VERSION_OVERHEAD,
+            index_bytes: self.indexes().iter().map(|index| index.index.memory_usage()).sum(),
+        }
+    }
+
+    /// Memory use of each maintained index, by name
+    pub fn index_memory_usage(&self) -> Vec<(String, usize)> {
+        self.indexes()
+            .iter()
+            .map(|index| (index.name.clone(), index.index.memory_usage()))
+            .collect()
+    }
+
+    /// Caps the table's total memory use, or lifts the cap with `None`.
+    ///
+    /// Commits whose new versions would take the table past the quota fail
+    /// with `QuotaExceeded`; deletes are always allowed, since collecting the
+    /// versions they supersede is how a table gets back under its quota.
+    pub fn set_memory_quota(&self, bytes: Option<usize>) {
+        let bytes = bytes.map_or(0, |bytes| bytes.max(1));
+        self.memory_quota.store(bytes, Ordering::Relaxed);
+    }
+
+    pub fn memory_quota(&self) -> Option<usize> {
+        match self.memory_quota.load(Ordering::Relaxed) {
+            0 => None,
+            bytes => Some(bytes),
+        }
+    }
+
+    /// Claims `bytes` of the quota for a commit, failing if they do not fit
+    /// next to what the table uses and what other commits have claimed.
+    ///
+    /// The claim lasts until the returned reservation is dropped, which the
+    /// commit does once its versions and index entries are counted.
+    pub(crate) fn reserve_quota(self: &Arc<Self>, bytes: usize) -> Result<QuotaReservation> {
+        let Some(quota) = self.memory_quota() else {
+            return Ok(QuotaReservation { table: self.clone(), bytes: 0 });
+        };
+        let reserved = self.quota_reserved.fetch_add(bytes, Ordering::AcqRel) + bytes;
+        // Dropped on failure, handing the claim back
+        let reservation = QuotaReservation { table: self.clone(), bytes };
+        let usage = self.memory_usage().total_bytes();
+        if usage + reserved > quota {
+            return Err(MaemioError::QuotaExceeded(format!(
+                "table {} uses {} of {} bytes and the commit needs {} more",
+                self.name, usage, quota, reserved
+            )));
+        }
+        Ok(reservation)
+    }
+
+    /// Returns the number of records in the table, not counting the chunks
+    /// of its blobs
+    pub fn record_count(&self) -> usize {
+        self.records.len()
+    }
+
+    pub(crate) fn records(&self) -> &RecordTable {
+        &self.records
+    }
+
+    pub(crate) fn chunks(&self) -> &RecordTable {
+        &self.chunks
+    }
+
+    /// Whether the id belongs to a published blob chunk
+    pub(crate) fn is_chunk(&self, record_id: u64) -> bool {
+        self.chunks.contains(record_id)
+    }
+
+    /// Looks up a record or blob chunk by id
+    pub(crate) fn get_record(&self, record_id: u64) -> Result<Arc<RecordHead>> {
+        self.records
+            .get(record_id)
+            .or_else(|| self.chunks.get(record_id))
+            .ok_or(MaemioError::RecordNotFound(record_id))
+    }
+
+    /// Whether the record exists and its latest committed version is not a delete
+    pub(crate) fn record_exists(&self, record_id: u64) -> Result<bool> {
+        let Some(record) = self.records.get(record_id) else {
+            return Ok(false);
+        };
+        Ok(record.find_visible_version(u64::MAX)?.is_some_and(|version| !version.is_tombstone()))
+    }
+
+    pub(crate) fn create_record(&self, record_id: u64, creation_ts: u64) -> Result<()> {
+        if self.chunks.contains(record_id) || !self.records.insert(record_id, self.new_record(creation_ts)) {
+            return Err(MaemioError::System(format!(
+                "Record {} already exists in table {}", record_id, self.name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Creates a record under an id drawn from the table's id allocator.
+    ///
+    /// Ids already taken by hand-picked records are skipped.
+    pub(crate) fn create_record_auto(&self, creation_ts: u64) -> Result<u64> {
+        loop {
+            let record_id = self.id_allocator.next_id()?;
+            if !self.chunks.contains(record_id) && self.records.insert(record_id, self.new_record(creation_ts)) {
+                return Ok(record_id);
+            }
+        }
+    }
+
+    /// Draws an id from the table's id allocator that no record holds yet,
+    /// without creating the record
+    pub(crate) fn allocate_record_id(&self) -> Result<u64> {
+        loop {
+            let record_id = self.id_allocator.next_id()?;
+            if !self.records.contains(record_id) && !self.chunks.contains(record_id) {
+                return Ok(record_id);
+            }
+        }
+    }
+
+    /// Adds a record inserted by a committing transaction, among the blob
+    /// chunks if `chunk` is set. Fails if a record or chunk has the id.
+    pub(crate) fn publish_record(&self, record_id: u64, record: Arc<RecordHead>, chunk: bool) -> bool {
+        let (records, others) = if chunk {
+            (&self.chunks, &self.records)
+        } else {
+            (&self.records, &self.chunks)
+        };
+        !others.contains(record_id) && records.insert(record_id, record)
+    }
+
+    /// Removes a record or blob chunk
+    pub(crate) fn remove_record(&self, record_id: u64) {
+        if self.records.remove(record_id).is_none() {
+            self.chunks.remove(record_id);
+        }
+    }
+
+    /// Builds a record head charged to this table, not yet part of it
+    pub(crate) fn new_record(&self, creation_ts: u64) -> Arc<RecordHead> {
+        Arc::new(RecordHead::with_memory(creation_ts, self.memory.clone()))
+    }
+
+    /// Sets how record ids are generated. Sequential ids pick up after the
+    /// last one handed out rather than starting over.
+    pub fn set_id_strategy(&self, strategy: IdStrategy) {
+        self.id_allocator.set_strategy(strategy);
+    }
+
+    pub fn id_strategy(&self) -> IdStrategy {
+        self.id_allocator.strategy()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_records() {
+        let table = Table::new(1, "users", 4, None);
+        table.create_record(5, 0).unwrap();
+        assert!(table.create_record(5, 0).is_err());
+        assert!(table.get_record(5).is_ok());
+        assert!(matches!(table.get_record(6), Err(MaemioError::RecordNotFound(6))));
+
+        // Generated ids skip the hand-picked one
+        table.create_record(1, 0).unwrap();
+        assert_eq!(table.create_record_auto(0).unwrap(), 2);
+        assert_eq!(table.record_count(), 3);
+    }
+
+    #[test]
+    fn test_quota_reservations() {
+        let table = Arc::new(Table::new(1, "users", 4, None));
+        table.set_memory_quota(Some(1000));
+
+        // Claims that fit alone do not fit together
+        let first = table.reserve_quota(600).unwrap();
+        assert!(matches!(table.reserve_quota(600), Err(MaemioError::QuotaExceeded(_))));
+        assert!(table.reserve_quota(400).is_ok());
+        drop(first);
+        assert!(table.reserve_quota(600).is_ok());
+        assert_eq!(table.quota_reserved.load(Ordering::Relaxed), 0);
+    }
+}
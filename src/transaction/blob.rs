@@ -0,0 +1,339 @@
+// src/transaction/blob.rs
+//
+// Large values split into chunks. A blob record holds a manifest with the
+// blob's length and the ids of the records holding its chunks, in order. The
+// manifest is marked as such on its version, so no ordinary value is taken
+// for one. Chunks share the table's id space but are kept out of its scans
+// and record count. Reads and writes of a blob go through the transaction's
+// read and write sets like any other record, so a blob is versioned and
+// validated chunk by chunk.
+use std::io;
+use std::sync::Arc;
+use super::Transaction;
+use crate::data::Version;
+use crate::error::{MaemioError, Result};
+use crate::schema::encoding::{read_varint, write_varint};
+
+/// Size of the chunks new blobs are split into
+pub const BLOB_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Layout of a blob: its length and the records holding its chunks
+#[derive(Debug, Clone, PartialEq)]
+struct BlobManifest {
+    len: u64,
+    chunk_size: u64,
+    chunks: Vec<u64>,
+}
+
+impl BlobManifest {
+    fn new(chunk_size: usize) -> Self {
+        Self {
+            len: 0,
+            chunk_size: chunk_size as u64,
+            chunks: Vec::new(),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(3 + 2 * self.chunks.len());
+        write_varint(&mut out, self.len);
+        write_varint(&mut out, self.chunk_size);
+        write_varint(&mut out, self.chunks.len() as u64);
+        for &chunk in &self.chunks {
+            write_varint(&mut out, chunk);
+        }
+        out
+    }
+
+    /// Decodes the manifest held by a version, or `None` if the version
+    /// holds an ordinary value
+    fn of_version(record_id: u64, version: &Version) -> Result<Option<Self>> {
+        if !version.is_blob_manifest() {
+            return Ok(None);
+        }
+        Self::decode(record_id, &version.data).map(Some)
+    }
+
+    fn decode(record_id: u64, data: &[u8]) -> Result<Self> {
+        let malformed = || MaemioError::System(format!("Record {} has a malformed blob manifest", record_id));
+        let mut rest = data;
+        let len = read_varint(&mut rest)?;
+        let chunk_size = read_varint(&mut rest)?;
+        let count = read_varint(&mut rest)? as usize;
+        if chunk_size == 0 || count != len.div_ceil(chunk_size) as usize {
+            return Err(malformed());
+        }
+        let chunks = (0..count)
+            .map(|_| read_varint(&mut rest))
+            .collect::<Result<Vec<_>>>()?;
+        if !rest.is_empty() {
+            return Err(malformed());
+        }
+        Ok(Self { len, chunk_size, chunks })
+    }
+
+    /// Byte range of chunk `i` within the blob
+    fn chunk_range(&self, i: usize) -> (u64, u64) {
+        let start = i as u64 * self.chunk_size;
+        (start, (start + self.chunk_size).min(self.len))
+    }
+}
+
+/// Streams a blob chunk by chunk; created by `Transaction::blob_reader`
+pub struct BlobReader<'a> {
+    tx: &'a mut Transaction,
+    table_id: u64,
+    manifest: BlobManifest,
+    pos: u64,
+    // The chunk the position is in, once read
+    chunk: Option<(usize, Arc<Version>)>,
+}
+
+impl BlobReader<'_> {
+    /// Total length of the blob
+    pub fn len(&self) -> u64 {
+        self.manifest.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.manifest.len == 0
+    }
+}
+
+impl io::Read for BlobReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.manifest.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let index = (self.pos / self.manifest.chunk_size) as usize;
+        if self.chunk.as_ref().is_none_or(|(current, _)| *current != index) {
+            let chunk = self.tx
+                .read_chunk(self.table_id, &self.manifest, index)
+                .map_err(io::Error::other)?;
+            self.chunk = Some((index, chunk));
+        }
+
+        let (_, chunk) = self.chunk.as_ref().expect("chunk was just read");
+        let offset = (self.pos - self.manifest.chunk_range(index).0) as usize;
+        let n = buf.len().min(chunk.data.len() - offset);
+        buf[..n].copy_from_slice(&chunk.data[offset..offset + n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+/// Blob storage built from ordinary records of a table of opaque bytes.
+///
+/// The blob record must exist before it is first written, like any record;
+/// chunk records are created as needed. Overwriting or deleting a blob leaves
+/// older snapshots reading the chunks they saw.
+impl Transaction {
+    /// Stores `data` as a blob, replacing the record's previous value
+    pub fn write_blob(&mut self, table_id: u64, record_id: u64, data: &[u8]) -> Result<()> {
+        let mut manifest = self.existing_manifest(table_id, record_id)?
+            .unwrap_or_else(|| BlobManifest::new(BLOB_CHUNK_SIZE));
+        manifest.len = data.len() as u64;
+        self.resize_chunks(table_id, &mut manifest)?;
+        for i in 0..manifest.chunks.len() {
+            let (start, end) = manifest.chunk_range(i);
+            self.write_slice(table_id, manifest.chunks[i], &data[start as usize..end as usize])?;
+        }
+        self.write_manifest(table_id, record_id, &manifest)
+    }
+
+    /// Reads a whole blob
+    pub fn read_blob(&mut self, table_id: u64, record_id: u64) -> Result<Vec<u8>> {
+        let len = self.blob_len(table_id, record_id)?;
+        self.read_blob_range(table_id, record_id, 0, len as usize)
+    }
+
+    /// Returns the length of a blob without reading its chunks
+    pub fn blob_len(&mut self, table_id: u64, record_id: u64) -> Result<u64> {
+        Ok(self.manifest(table_id, record_id)?.len)
+    }
+
+    /// Reads up to `len` bytes starting at `offset`, touching only the chunks
+    /// in that range. Reads past the end of the blob are cut short.
+    pub fn read_blob_range(&mut self, table_id: u64, record_id: u64, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let manifest = self.manifest(table_id, record_id)?;
+        let end = blob_range_end(offset, len)?.min(manifest.len);
+        if offset >= end {
+            return Ok(Vec::new());
+        }
+
+        let mut out = Vec::with_capacity((end - offset) as usize);
+        let first = (offset / manifest.chunk_size) as usize;
+        let last = ((end - 1) / manifest.chunk_size) as usize;
+        for i in first..=last {
+            let (chunk_start, chunk_end) = manifest.chunk_range(i);
+            let chunk = self.read_chunk(table_id, &manifest, i)?;
+            let from = offset.max(chunk_start) - chunk_start;
+            let to = end.min(chunk_end) - chunk_start;
+            out.extend_from_slice(&chunk.data[from as usize..to as usize]);
+        }
+        Ok(out)
+    }
+
+    /// Overwrites the blob's bytes from `offset` on with `data`, rewriting
+    /// only the chunks in that range. Writing past the end extends the blob,
+    /// filling any gap with zeros.
+    pub fn write_blob_range(&mut self, table_id: u64, record_id: u64, offset: u64, data: &[u8]) -> Result<()> {
+        let mut manifest = self.manifest(table_id, record_id)?;
+        let end = blob_range_end(offset, data.len())?;
+        let old_len = manifest.len;
+        if end > old_len {
+            manifest.len = end;
+            self.resize_chunks(table_id, &mut manifest)?;
+            self.write_manifest(table_id, record_id, &manifest)?;
+        }
+
+        // Chunks past the old end are rewritten too, so a gap between the
+        // old end and `offset` reads as zeros.
+        let rewrite_start = if end > old_len { offset.min(old_len) } else { offset };
+        if rewrite_start >= end {
+            return Ok(());
+        }
+        let first = (rewrite_start / manifest.chunk_size) as usize;
+        let last = ((end - 1) / manifest.chunk_size) as usize;
+        for i in first..=last {
+            let (chunk_start, chunk_end) = manifest.chunk_range(i);
+            let mut bytes = self.clock.arena().alloc((chunk_end - chunk_start) as usize);
+            if chunk_start < old_len {
+                // The chunk as it was before the blob grew
+                bytes.extend_from_slice(&self.read_chunk_sized(table_id, &manifest, i, old_len)?.data);
+            }
+            bytes.resize((chunk_end - chunk_start) as usize, 0);
+
+            let from = offset.max(chunk_start);
+            let to = end.min(chunk_end);
+            if from < to {
+                let src = &data[(from - offset) as usize..(to - offset) as usize];
+                bytes[(from - chunk_start) as usize..(to - chunk_start) as usize].copy_from_slice(src);
+            }
+            self.write_in(table_id, manifest.chunks[i], bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Deletes a blob together with its chunks
+    pub fn delete_blob(&mut self, table_id: u64, record_id: u64) -> Result<()> {
+        let manifest = self.manifest(table_id, record_id)?;
+        for chunk in manifest.chunks {
+            self.delete_in(table_id, chunk)?;
+        }
+        self.delete_in(table_id, record_id)
+    }
+
+    /// Opens a blob for streaming reads that fetch one chunk at a time
+    pub fn blob_reader(&mut self, table_id: u64, record_id: u64) -> Result<BlobReader<'_>> {
+        let manifest = self.manifest(table_id, record_id)?;
+        Ok(BlobReader {
+            tx: self,
+            table_id,
+            manifest,
+            pos: 0,
+            chunk: None,
+        })
+    }
+
+    fn manifest(&mut self, table_id: u64, record_id: u64) -> Result<BlobManifest> {
+        let version = self.read_in(table_id, record_id)?;
+        BlobManifest::of_version(record_id, &version)?
+            .ok_or_else(|| MaemioError::System(format!("Record {} is not a blob", record_id)))
+    }
+
+    fn write_manifest(&mut self, table_id: u64, record_id: u64, manifest: &BlobManifest) -> Result<()> {
+        let mut version = Version::new(self.timestamp, manifest.encode());
+        version.blob_manifest = true;
+        self.write_version(table_id, record_id, version)
+    }
+
+    /// The record's manifest if it currently holds a blob, `None` if it is
+    /// empty, deleted or holds an ordinary value
+    fn existing_manifest(&mut self, table_id: u64, record_id: u64) -> Result<Option<BlobManifest>> {
+        match self.read_in(table_id, record_id) {
+            Ok(version) => BlobManifest::of_version(record_id, &version),
+            Err(MaemioError::RecordNotFound(id)) if id == record_id => {
+                // Deleted records are fine to write; missing ones are not.
+                self.table(table_id)?.get_record(record_id)?;
+                Ok(None)
+            }
+            Err(MaemioError::NoVisibleVersion) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Creates or deletes chunk records so the manifest covers its length.
+    ///
+    /// New chunks are inserted by this transaction and start out empty; the
+    /// caller writes their bytes.
+    fn resize_chunks(&mut self, table_id: u64, manifest: &mut BlobManifest) -> Result<()> {
+        let needed = manifest.len.div_ceil(manifest.chunk_size) as usize;
+        while manifest.chunks.len() > needed {
+            let chunk = manifest.chunks.pop().expect("more chunks than needed");
+            self.delete_in(table_id, chunk)?;
+        }
+        while manifest.chunks.len() < needed {
+            let chunk = self.insert_in(table_id, Vec::new())?;
+            self.pending_chunks.insert((table_id, chunk));
+            manifest.chunks.push(chunk);
+        }
+        Ok(())
+    }
+
+    fn read_chunk(&mut self, table_id: u64, manifest: &BlobManifest, index: usize) -> Result<Arc<Version>> {
+        self.read_chunk_sized(table_id, manifest, index, manifest.len)
+    }
+
+    /// Reads chunk `index` of a blob that is `len` bytes long
+    fn read_chunk_sized(
+        &mut self,
+        table_id: u64,
+        manifest: &BlobManifest,
+        index: usize,
+        len: u64,
+    ) -> Result<Arc<Version>> {
+        let chunk = self.read_in(table_id, manifest.chunks[index])?;
+        let start = index as u64 * manifest.chunk_size;
+        let end = (start + manifest.chunk_size).min(len);
+        if chunk.data.len() as u64 != end - start {
+            return Err(MaemioError::System(format!(
+                "Blob chunk {} has {} bytes, expected {}", manifest.chunks[index], chunk.data.len(), end - start
+            )));
+        }
+        Ok(chunk)
+    }
+}
+
+/// End of the byte range of `len` bytes starting at `offset`
+fn blob_range_end(offset: u64, len: usize) -> Result<u64> {
+    offset.checked_add(len as u64).ok_or_else(|| {
+        MaemioError::System(format!("Blob range of {} bytes at {} is out of bounds", len, offset))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let manifest = BlobManifest {
+            len: 150,
+            chunk_size: 64,
+            chunks: vec![7, 300, 9],
+        };
+        assert_eq!(manifest.chunk_range(2), (128, 150));
+        assert_eq!(BlobManifest::decode(1, &manifest.encode()).unwrap(), manifest);
+
+        // Only versions marked as manifests hold one
+        let plain = Version::new(1, manifest.encode());
+        assert_eq!(BlobManifest::of_version(1, &plain).unwrap(), None);
+        let mut marked = plain.clone();
+        marked.blob_manifest = true;
+        assert_eq!(BlobManifest::of_version(1, &marked).unwrap(), Some(manifest.clone()));
+        let mut truncated = manifest.encode();
+        truncated.pop();
+        assert!(BlobManifest::decode(1, &truncated).is_err());
+    }
+}
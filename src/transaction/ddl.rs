@@ -0,0 +1,362 @@
+// src/transaction/ddl.rs
+use std::sync::Arc;
+use std::time::Duration;
+use super::Transaction;
+use crate::data::RecordHead;
+use crate::catalog::{CatalogEntry, DdlOp, ObjectKind, CATALOG_TABLE_ID};
+use crate::error::{MaemioError, Result};
+use crate::index::{IndexType, KeyExtractor};
+use crate::map::{entry_key, MAP_PRIMARY_INDEX};
+use crate::queue::Queue;
+use crate::schema::{ColumnType, Schema};
+use crate::sequence::IdStrategy;
+use crate::table::{ForeignKey, OnDelete, Table, DEFAULT_TABLE_ID};
+
+/// Schema changes made inside a transaction.
+///
+/// Each change is checked when staged, recorded in the system catalog with
+/// the transaction's other writes and applied when it commits; an aborted
+/// transaction leaves no trace of it. Tables created here can be written to
+/// by the same transaction right away.
+impl Transaction {
+    /// Creates a table of opaque bytes
+    pub fn create_table(&mut self, name: &str) -> Result<Arc<Table>> {
+        self.stage_create_table(name, None)
+    }
+
+    /// Creates a table whose rows are validated against `schema`
+    pub fn create_table_with_schema(&mut self, name: &str, schema: Schema) -> Result<Arc<Table>> {
+        self.stage_create_table(name, Some(schema))
+    }
+
+    fn stage_create_table(&mut self, name: &str, schema: Option<Schema>) -> Result<Arc<Table>> {
+        if self.resolve_table(name).is_ok() {
+            return Err(MaemioError::System(format!("Table {} already exists", name)));
+        }
+        let table = self.tables.allocate_table(name, schema);
+        self.table_cache.insert(table.id(), table.clone());
+        self.insert_catalog_entry(&CatalogEntry::table(&table))?;
+        self.ddl.push(DdlOp::CreateTable(table.clone()));
+        Ok(table)
+    }
+
+    /// Creates a queue stored in a new table `name`; see `Maemio::create_queue`
+    pub fn create_queue(&mut self, name: &str, visibility_timeout: Duration) -> Result<Queue> {
+        let table = self.stage_create_table(name, None)?;
+        // Message ids follow enqueue order. The table is not registered
+        // before this transaction commits, so nobody allocates ids earlier.
+        table.set_id_strategy(IdStrategy::Sequential);
+        let queue = Queue::new(name, table.id(), visibility_timeout);
+        self.insert_catalog_entry(&CatalogEntry::queue(&queue))?;
+        Ok(queue)
+    }
+
+    /// Drops a table together with its records, indexes and foreign keys.
+    ///
+    /// Tables still referenced by another table's foreign key cannot be dropped.
+    pub fn drop_table(&mut self, name: &str) -> Result<()> {
+        let table = self.resolve_table(name)?;
+        if table.id() == DEFAULT_TABLE_ID || table.id() == CATALOG_TABLE_ID {
+            return Err(MaemioError::System("System tables cannot be dropped".into()));
+        }
+        if let Some(fk) = table.referenced_by().iter().find(|fk| fk.child_table() != table.id()) {
+            return Err(MaemioError::ConstraintViolation(format!(
+                "Table {} is referenced by foreign key {}", name, fk.name()
+            )));
+        }
+        self.delete_catalog_entries(|entry| {
+            matches!(entry.kind, ObjectKind::Table | ObjectKind::Index | ObjectKind::ForeignKey | ObjectKind::Queue)
+                && entry.table_id == Some(table.id())
+        })?;
+        self.ddl.push(DdlOp::DropTable(table));
+        Ok(())
+    }
+
+    /// Creates an index that is not maintained automatically
+    pub fn create_index(&mut self, table_id: u64, name: &str, index_type: IndexType) -> Result<()> {
+        self.stage_create_index(table_id, name, index_type, None, false)
+    }
+
+    /// Creates an index that transactions keep in sync with the table's records
+    pub fn create_index_with_key(
+        &mut self,
+        table_id: u64,
+        name: &str,
+        index_type: IndexType,
+        key: KeyExtractor,
+    ) -> Result<()> {
+        self.stage_create_index(table_id, name, index_type, Some(key), false)
+    }
+
+    /// Creates a maintained index that allows at most one record per key
+    pub fn create_unique_index(
+        &mut self,
+        table_id: u64,
+        name: &str,
+        index_type: IndexType,
+        key: KeyExtractor,
+    ) -> Result<()> {
+        self.stage_create_index(table_id, name, index_type, Some(key), true)
+    }
+
+    /// Creates the primary index of a map: a unique index over the keys the
+    /// map stores in front of each value
+    pub(crate) fn create_map_index(&mut self, table_id: u64) -> Result<()> {
+        let key = KeyExtractor::function(entry_key);
+        self.stage_create_index(table_id, MAP_PRIMARY_INDEX, IndexType::BTree, Some(key), true)
+    }
+
+    fn stage_create_index(
+        &mut self,
+        table_id: u64,
+        name: &str,
+        index_type: IndexType,
+        key: Option<KeyExtractor>,
+        unique: bool,
+    ) -> Result<()> {
+        if let Some(key) = &key {
+            key.bind(self.table(table_id)?.schema())?;
+        }
+        if self.index_exists(table_id, name) {
+            return Err(MaemioError::System(format!(
+                "Index {} already exists for table {}", name, table_id
+            )));
+        }
+        self.insert_catalog_entry(&CatalogEntry::index(table_id, name, index_type, key.as_ref(), unique))?;
+        self.ddl.push(DdlOp::CreateIndex {
+            table_id,
+            name: name.to_string(),
+            index_type,
+            key,
+            unique,
+        });
+        Ok(())
+    }
+
+    /// Drops an index; indexes backing a foreign key go with the foreign key
+    pub fn drop_index(&mut self, table_id: u64, name: &str) -> Result<()> {
+        if !self.index_exists(table_id, name) {
+            return Err(MaemioError::System(format!(
+                "Index {} not found for table {}", name, table_id
+            )));
+        }
+        let foreign_keys = self.table(table_id).map(|table| table.foreign_keys()).unwrap_or_default();
+        if let Some(fk) = foreign_keys.iter().find(|fk| fk.index.name == name) {
+            return Err(MaemioError::ConstraintViolation(format!(
+                "Index {} backs foreign key {}", name, fk.name()
+            )));
+        }
+        self.delete_catalog_entries(|entry| {
+            entry.kind == ObjectKind::Index && entry.table_id == Some(table_id) && entry.name == name
+        })?;
+        self.ddl.push(DdlOp::DropIndex { table_id, name: name.to_string() });
+        Ok(())
+    }
+
+    /// Declares that `column` of the child table holds record ids of the
+    /// parent table; see `Maemio::create_foreign_key`
+    pub fn create_foreign_key(
+        &mut self,
+        name: &str,
+        child_table_id: u64,
+        column: &str,
+        parent_table_id: u64,
+        on_delete: OnDelete,
+    ) -> Result<()> {
+        let child = self.table(child_table_id)?;
+        self.table(parent_table_id)?;
+        let schema = child.require_schema()?;
+        let column_type = schema.columns()[schema.column_index(column)?].column_type;
+        if column_type != ColumnType::Int {
+            return Err(MaemioError::SchemaViolation(format!(
+                "Foreign key column {} must be an Int column", column
+            )));
+        }
+        if self.foreign_key_exists(child_table_id, name)
+            || self.index_exists(child_table_id, &ForeignKey::index_name(name))
+        {
+            return Err(MaemioError::System(format!(
+                "Foreign key {} already exists for table {}", name, child_table_id
+            )));
+        }
+        self.insert_catalog_entry(&CatalogEntry::foreign_key(
+            name, child_table_id, column, parent_table_id, on_delete,
+        ))?;
+        self.ddl.push(DdlOp::CreateForeignKey {
+            name: name.to_string(),
+            child_table_id,
+            column: column.to_string(),
+            parent_table_id,
+            on_delete,
+        });
+        Ok(())
+    }
+
+    /// Drops a foreign key declared on a child table, along with its index
+    pub fn drop_foreign_key(&mut self, child_table_id: u64, name: &str) -> Result<()> {
+        if !self.foreign_key_exists(child_table_id, name) {
+            return Err(MaemioError::System(format!(
+                "Foreign key {} not found for table {}", name, child_table_id
+            )));
+        }
+        self.delete_catalog_entries(|entry| {
+            entry.kind == ObjectKind::ForeignKey && entry.table_id == Some(child_table_id) && entry.name == name
+        })?;
+        self.ddl.push(DdlOp::DropForeignKey { child_table_id, name: name.to_string() });
+        Ok(())
+    }
+
+    /// Creates a named sequence starting at `start` and advancing by `increment`
+    pub fn create_sequence(&mut self, name: &str, start: u64, increment: u64) -> Result<()> {
+        if increment == 0 {
+            return Err(MaemioError::System(format!(
+                "Sequence {} must have a non-zero increment", name
+            )));
+        }
+        if self.sequence_exists(name) {
+            return Err(MaemioError::System(format!("Sequence {} already exists", name)));
+        }
+        self.insert_catalog_entry(&CatalogEntry::sequence(name, start, increment))?;
+        self.ddl.push(DdlOp::CreateSequence { name: name.to_string(), start, increment });
+        Ok(())
+    }
+
+    /// Drops an existing sequence
+    pub fn drop_sequence(&mut self, name: &str) -> Result<()> {
+        if !self.sequence_exists(name) {
+            return Err(MaemioError::SequenceNotFound(name.to_string()));
+        }
+        self.delete_catalog_entries(|entry| entry.kind == ObjectKind::Sequence && entry.name == name)?;
+        self.ddl.push(DdlOp::DropSequence(name.to_string()));
+        Ok(())
+    }
+
+    /// Lists the catalog as of this transaction, including its own staged changes
+    pub fn catalog_entries(&mut self) -> Result<Vec<CatalogEntry>> {
+        Ok(self.catalog_rows(|_| true)?.into_iter().map(|(_, entry)| entry).collect())
+    }
+
+    /// Looks up a table by name, taking staged creates and drops into account
+    fn resolve_table(&self, name: &str) -> Result<Arc<Table>> {
+        for op in self.ddl.iter().rev() {
+            match op {
+                DdlOp::CreateTable(table) if table.name() == name => return Ok(table.clone()),
+                DdlOp::DropTable(table) if table.name() == name => {
+                    return Err(MaemioError::TableNotFound(name.to_string()));
+                }
+                _ => {}
+            }
+        }
+        self.tables.get_table_by_name(name)
+    }
+
+    fn index_exists(&self, table_id: u64, name: &str) -> bool {
+        let mut exists = self.catalog.indexes().get_index(table_id, name).is_ok();
+        for op in &self.ddl {
+            match op {
+                DdlOp::CreateIndex { table_id: id, name: n, .. } if *id == table_id && n == name => exists = true,
+                DdlOp::DropIndex { table_id: id, name: n } if *id == table_id && n == name => exists = false,
+                _ => {}
+            }
+        }
+        exists
+    }
+
+    fn foreign_key_exists(&mut self, child_table_id: u64, name: &str) -> bool {
+        let mut exists = self.table(child_table_id)
+            .is_ok_and(|child| child.foreign_keys().iter().any(|fk| fk.name() == name));
+        for op in &self.ddl {
+            match op {
+                DdlOp::CreateForeignKey { child_table_id: id, name: n, .. } if *id == child_table_id && n == name => {
+                    exists = true
+                }
+                DdlOp::DropForeignKey { child_table_id: id, name: n } if *id == child_table_id && n == name => {
+                    exists = false
+                }
+                _ => {}
+            }
+        }
+        exists
+    }
+
+    fn sequence_exists(&self, name: &str) -> bool {
+        let mut exists = self.sequences.get_sequence(name).is_ok();
+        for op in &self.ddl {
+            match op {
+                DdlOp::CreateSequence { name: n, .. } if n == name => exists = true,
+                DdlOp::DropSequence(n) if n == name => exists = false,
+                _ => {}
+            }
+        }
+        exists
+    }
+
+    /// Adds a catalog row under a fresh record id
+    pub(crate) fn insert_catalog_entry(&mut self, entry: &CatalogEntry) -> Result<()> {
+        self.insert_row(CATALOG_TABLE_ID, &entry.to_row()).map(|_| ())
+    }
+
+    fn delete_catalog_entries<F>(&mut self, matches: F) -> Result<()>
+    where
+        F: Fn(&CatalogEntry) -> bool,
+    {
+        for (record_id, _) in self.catalog_rows(matches)? {
+            self.delete_in(CATALOG_TABLE_ID, record_id)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the catalog rows visible to this transaction that satisfy
+    /// `matches`, ordered by record id.
+    ///
+    /// Every row is looked at, but only matching rows join the read set, so
+    /// a schema change conflicts only with changes to the objects it touches.
+    /// Commits of schema changes are serialized and re-check their objects
+    /// when applied, which covers rows this transaction did not read.
+    fn catalog_rows<F>(&mut self, matches: F) -> Result<Vec<(u64, CatalogEntry)>>
+    where
+        F: Fn(&CatalogEntry) -> bool,
+    {
+        let catalog_table = self.table(CATALOG_TABLE_ID)?;
+        let schema = catalog_table.require_schema()?.clone();
+        let mut records: Vec<(u64, Option<Arc<RecordHead>>)> = catalog_table
+            .records()
+            .entries()
+            .into_iter()
+            .map(|(record_id, record)| (record_id, Some(record)))
+            .collect();
+        // Rows inserted by this transaction are not in the table yet
+        records.extend(
+            self.pending_records
+                .keys()
+                .filter(|(table_id, _)| *table_id == CATALOG_TABLE_ID)
+                .map(|&(_, record_id)| (record_id, None)),
+        );
+        records.sort_unstable_by_key(|&(record_id, _)| record_id);
+
+        let mut rows = Vec::new();
+        for (record_id, record) in records {
+            let key = (CATALOG_TABLE_ID, record_id);
+            let (version, read) = match (self.local_writes.get(&key), record) {
+                (Some(version), _) => (version.clone(), false),
+                (None, Some(record)) => match record.find_visible_version(self.timestamp)? {
+                    Some(version) => (version, true),
+                    // Rows of transactions that never committed
+                    None => continue,
+                },
+                (None, None) => continue,
+            };
+            if version.is_tombstone() {
+                continue;
+            }
+            let entry = CatalogEntry::from_row(schema.decode_row(&version.data)?)?;
+            if matches(&entry) {
+                if read {
+                    self.read_set.insert(key, version);
+                }
+                rows.push((record_id, entry));
+            }
+        }
+        Ok(rows)
+    }
+}
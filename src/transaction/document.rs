@@ -0,0 +1,118 @@
+// src/transaction/document.rs
+//
+// Reads and partial updates of JSON documents. A path update reads the whole
+// document, changes it and writes it back as a new version, so it conflicts
+// with concurrent writes to the same record like any read-modify-write.
+use std::collections::BTreeSet;
+use serde_json::Value as JsonValue;
+use super::Transaction;
+use crate::document::{json_to_key, Document, JsonPath};
+use crate::error::{MaemioError, Result};
+use crate::table::DEFAULT_TABLE_ID;
+
+impl Transaction {
+    /// Reads a document from the default table
+    pub fn read_document(&mut self, record_id: u64) -> Result<Document> {
+        self.read_document_in(DEFAULT_TABLE_ID, record_id)
+    }
+
+    /// Writes a document to the default table
+    pub fn write_document(&mut self, record_id: u64, document: &Document) -> Result<()> {
+        self.write_document_in(DEFAULT_TABLE_ID, record_id, document)
+    }
+
+    /// Reads the value at `path` of a document in the default table
+    pub fn get_path(&mut self, record_id: u64, path: &str) -> Result<Option<JsonValue>> {
+        self.get_path_in(DEFAULT_TABLE_ID, record_id, path)
+    }
+
+    /// Sets the value at `path` of a document in the default table
+    pub fn set_path(&mut self, record_id: u64, path: &str, value: JsonValue) -> Result<()> {
+        self.set_path_in(DEFAULT_TABLE_ID, record_id, path, value)
+    }
+
+    /// Removes the value at `path` of a document in the default table
+    pub fn remove_path(&mut self, record_id: u64, path: &str) -> Result<Option<JsonValue>> {
+        self.remove_path_in(DEFAULT_TABLE_ID, record_id, path)
+    }
+
+    /// Reads a document; fails with `MaemioError::Decode` if the record does
+    /// not hold JSON
+    pub fn read_document_in(&mut self, table_id: u64, record_id: u64) -> Result<Document> {
+        let version = self.read_in(table_id, record_id)?;
+        Document::parse(&version.data)
+    }
+
+    pub fn write_document_in(&mut self, table_id: u64, record_id: u64, document: &Document) -> Result<()> {
+        self.write_in(table_id, record_id, document.to_bytes())
+    }
+
+    pub fn get_path_in(&mut self, table_id: u64, record_id: u64, path: &str) -> Result<Option<JsonValue>> {
+        let path = JsonPath::parse(path)?;
+        let document = self.read_document_in(table_id, record_id)?;
+        Ok(path.get(document.value()).cloned())
+    }
+
+    /// Sets the value at `path`, creating missing object members on the way.
+    /// A record without a value yet starts out as an empty document.
+    pub fn set_path_in(&mut self, table_id: u64, record_id: u64, path: &str, value: JsonValue) -> Result<()> {
+        let path = JsonPath::parse(path)?;
+        let mut document = match self.read_document_in(table_id, record_id) {
+            Ok(document) => document.into_value(),
+            Err(MaemioError::RecordNotFound(id)) if id == record_id => JsonValue::Null,
+            Err(MaemioError::NoVisibleVersion) => JsonValue::Null,
+            Err(err) => return Err(err),
+        };
+        path.set(&mut document, value)?;
+        self.write_document_in(table_id, record_id, &Document::new(document))
+    }
+
+    /// Removes the value at `path` and returns it; the document is rewritten
+    /// only if something was removed
+    pub fn remove_path_in(&mut self, table_id: u64, record_id: u64, path: &str) -> Result<Option<JsonValue>> {
+        let path = JsonPath::parse(path)?;
+        let mut document = self.read_document_in(table_id, record_id)?.into_value();
+        let removed = path.remove(&mut document)?;
+        if removed.is_some() {
+            self.write_document_in(table_id, record_id, &Document::new(document))?;
+        }
+        Ok(removed)
+    }
+
+    /// Finds the documents whose value at the path of a JSON path index
+    /// equals `value`.
+    ///
+    /// Indexes follow the latest committed state. Records the key was
+    /// removed from since, and records this transaction wrote, are looked at
+    /// as well, and every candidate is checked against this transaction's
+    /// view before it is returned. Results come in record id order.
+    pub fn find_documents(&mut self, table_id: u64, index_name: &str, value: &JsonValue) -> Result<Vec<(u64, Document)>> {
+        let table = self.table(table_id)?;
+        let index = table.indexes()
+            .into_iter()
+            .find(|index| index.name == index_name)
+            .ok_or_else(|| MaemioError::System(format!(
+                "Index {} not found for table {}", index_name, table_id
+            )))?;
+        let Some(key) = json_to_key(value) else {
+            return Ok(Vec::new());
+        };
+
+        let mut candidates: BTreeSet<u64> = index.index.get_all(&key, self.timestamp)?.into_iter().collect();
+        candidates.extend(index.removed_records(&key));
+        candidates.extend(self.local_writes.keys().filter(|(table, _)| *table == table_id).map(|&(_, record_id)| record_id));
+
+        let mut found = Vec::new();
+        for record_id in candidates {
+            let version = match self.read_in(table_id, record_id) {
+                Ok(version) => version,
+                Err(MaemioError::RecordNotFound(_)) | Err(MaemioError::NoVisibleVersion) => continue,
+                Err(err) => return Err(err),
+            };
+            if index.key.extract(&version.data)?.as_ref() == Some(&key) {
+                found.push((record_id, Document::parse(&version.data)?));
+            }
+        }
+        Ok(found)
+    }
+}
@@ -1,23 +1,25 @@
 // src/transaction/manager.rs
-use std::collections::HashMap;
 use std::sync::Arc;
-use parking_lot::RwLock;
 use super::Transaction;
 use crate::clock::ClockManager;
 use crate::error::{MaemioError, Result};
 use crate::data::RecordHead;
 use crate::gc::GarbageCollector;
 use crate::contention::ContentionManager;
+use crate::catalog::Catalog;
+use crate::table::{TableManager, DEFAULT_TABLE_ID};
 
 pub struct TransactionManager {
     clock_manager: Arc<ClockManager>,
-    records: Arc<RwLock<HashMap<u64, Arc<RecordHead>>>>,
+    tables: Arc<TableManager>,
     contention_manager: Arc<ContentionManager>,
+    catalog: Arc<Catalog>,
 }
 
 impl TransactionManager {
     pub fn new(
         clock_manager: Arc<ClockManager>,
+        catalog: Arc<Catalog>,
         thread_count: usize,
     ) -> Result<Self> {
         let contention_manager = Arc::new(ContentionManager::new(
@@ -28,11 +30,20 @@ impl TransactionManager {
 
         Ok(Self {
             clock_manager,
-            records: Arc::new(RwLock::new(HashMap::new())),
+            tables: catalog.tables().clone(),
             contention_manager,
+            catalog,
         })
     }
 
+    pub fn clock_manager(&self) -> &Arc<ClockManager> {
+        &self.clock_manager
+    }
+
+    pub fn tables(&self) -> &Arc<TableManager> {
+        &self.tables
+    }
+
     pub fn execute_with_gc<F, T>(&self, thread_id: usize, gc: &GarbageCollector, mut operation: F) -> Result<T>
     where
         F: FnMut(&mut Transaction) -> Result<T>  // Note: parameter is now marked as mut
@@ -80,35 +91,36 @@ impl TransactionManager {
         let clock = self.clock_manager.get_clock(thread_id);
         Transaction::new(
             clock,
-            self.records.clone(),
+            self.catalog.clone(),
             self.contention_manager.clone(),
             thread_id,
         )
     }
 
+    /// Creates a record in the default table
     pub fn create_record(&self, record_id: u64) -> Result<()> {
-        let mut records = self.records.write();
-        
-        if records.contains_key(&record_id) {
-            return Err(MaemioError::System(
-                format!("Record {} already exists", record_id)
-            ));
-        }
+        self.create_record_in(DEFAULT_TABLE_ID, record_id)
+    }
 
+    /// Creates a record in the given table
+    pub fn create_record_in(&self, table_id: u64, record_id: u64) -> Result<()> {
         // Get a new timestamp for this record creation
         let creation_ts = self.clock_manager.get_min_write_ts();
-        
-        // Create the record with this timestamp
-        records.insert(record_id, Arc::new(RecordHead::new(creation_ts)));
-        Ok(())
+
+        // Create the record with this timestamp; only its shard is locked
+        self.tables.get_table(table_id)?.create_record(record_id, creation_ts)
     }
 
-    pub fn get_record(&self, record_id: u64) -> Result<Arc<RecordHead>> {
-        self.records.read()
-            .get(&record_id)
-            .cloned()
-            .ok_or(MaemioError::RecordNotFound(record_id))
+    /// Creates a record under an id drawn from the table's id allocator
+    pub fn create_record_auto(&self, table_id: u64) -> Result<u64> {
+        let creation_ts = self.clock_manager.get_min_write_ts();
+        self.tables.get_table(table_id)?.create_record_auto(creation_ts)
     }
+
+    pub fn get_record(&self, table_id: u64, record_id: u64) -> Result<Arc<RecordHead>> {
+        self.tables.get_table(table_id)?.get_record(record_id)
+    }
+
     pub fn start_contention_management(&self) -> std::thread::JoinHandle<()> {
         // Delegate to the contention manager
         self.contention_manager.start_hill_climbing()
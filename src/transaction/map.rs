@@ -0,0 +1,67 @@
+// src/transaction/map.rs
+//
+// Bookkeeping for typed maps. A key put for the first time gets a record
+// inserted by the transaction, and the map's primary index learns the key
+// when the transaction commits, like any maintained index. Lookups that find
+// no entry are remembered and checked again at commit: an entry that another
+// transaction made visible meanwhile, or a key another transaction claimed
+// while this one is inserting it, is a conflict.
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+use super::Transaction;
+use crate::error::{MaemioError, Result};
+use crate::index::IndexKey;
+use crate::table::TableIndex;
+
+impl Transaction {
+    /// Record this transaction inserted for a map key, if any
+    pub(crate) fn pending_key(&self, table_id: u64, key: &IndexKey) -> Option<u64> {
+        self.pending_keys.get(&(table_id, key.clone())).copied()
+    }
+
+    /// Inserts the record holding a new map key
+    pub(crate) fn insert_key(&mut self, table_id: u64, key: IndexKey, entry: Vec<u8>) -> Result<u64> {
+        let record_id = self.insert_in(table_id, entry)?;
+        self.pending_keys.insert((table_id, key), record_id);
+        Ok(record_id)
+    }
+
+    /// Map keys this transaction inserted with keys in `range`, in key order
+    pub(crate) fn pending_keys_in(&self, table_id: u64, range: (Bound<IndexKey>, Bound<IndexKey>)) -> Vec<(IndexKey, u64)> {
+        self.pending_keys
+            .iter()
+            .filter(|((table, key), _)| *table == table_id && range.contains(key))
+            .map(|((_, key), &record_id)| (key.clone(), record_id))
+            .collect()
+    }
+
+    /// Remembers that a map lookup found no entry for `key`
+    pub(crate) fn register_absent_key(&mut self, table_id: u64, index: Arc<TableIndex>, key: IndexKey) {
+        self.absent_keys.push((table_id, index, key));
+    }
+
+    /// Fails with a conflict if an entry looked up as absent has become
+    /// visible, or a key being inserted was claimed by another transaction
+    pub(crate) fn validate_absent_keys(&self) -> Result<()> {
+        for (table_id, index, key) in &self.absent_keys {
+            let holder = index.index.get(key, u64::MAX)?;
+            if holder.is_some() && self.pending_key(*table_id, key).is_some() {
+                return Err(MaemioError::Conflict);
+            }
+            for holder in holder.into_iter().chain(index.removed_records(key)) {
+                let record_key = (*table_id, holder);
+                if self.write_set.contains_key(&record_key) || self.read_set.contains_key(&record_key) {
+                    continue;
+                }
+                // Records that no longer exist hold nothing
+                let Ok(record) = self.get_record(record_key) else {
+                    continue;
+                };
+                if self.appeared(&record)? {
+                    return Err(MaemioError::Conflict);
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,48 +1,136 @@
 // src/transaction/mod.rs
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 use std::sync::atomic::Ordering;
-use parking_lot::RwLock;
+use std::time::{Duration, Instant};
+use crate::catalog::{Catalog, DdlOp};
 use crate::clock::Clock;
-use crate::data::{Version, RecordHead};
+use crate::data::{Version, RecordHead, RECORD_OVERHEAD, VERSION_OVERHEAD};
 use crate::error::{MaemioError, Result};
 use crate::contention::ContentionManager;
+use crate::index::IndexKey;
+use crate::schema::{Row, Value};
+use crate::sequence::SequenceManager;
+use crate::table::{CommitPass, ForeignKey, OnDelete, QuotaReservation, Table, TableIndex, TableManager, DEFAULT_TABLE_ID};
 mod manager;
+mod ddl;
+mod blob;
+mod ttl;
+mod scan;
+mod map;
+mod queue;
+#[cfg(feature = "serde")]
+mod typed;
+#[cfg(feature = "json")]
+mod document;
 pub use manager::TransactionManager;
+pub use blob::{BlobReader, BLOB_CHUNK_SIZE};
+pub use scan::Scan;
+use scan::PhantomChecks;
+use queue::QueueOps;
+
+/// Identifies a record by `(table_id, record_id)`
+pub(crate) type RecordKey = (u64, u64);
+
+/// Change to one maintained index caused by a committed write
+struct IndexUpdate {
+    table_id: u64,
+    index: Arc<TableIndex>,
+    record_id: u64,
+    old_key: Option<IndexKey>,
+    new_key: Option<IndexKey>,
+}
+
+/// A committed write ready to be linked into its record's version chain
+struct Install {
+    table: Arc<Table>,
+    record_id: u64,
+    record: Arc<RecordHead>,
+    version: Arc<Version>,
+    // Write timestamp of the version a partial update changed, and that
+    // version as a delta against the new one
+    patched: Option<(u64, Vec<u8>)>,
+    // Size of the value before encoding, if the table's codec considered it
+    raw_len: Option<usize>,
+    // Time to live of the write, counted from the commit
+    ttl: Option<Duration>,
+}
 
 #[derive(Clone)]
 struct ValidationData {
     timestamp: u64,
-    write_checks: HashMap<u64, Version>,
-    read_checks: HashMap<u64, Arc<Version>>,
+    write_checks: HashMap<RecordKey, Arc<Version>>,
+    read_checks: HashMap<RecordKey, Arc<Version>>,
 }
 
 pub struct Transaction {
     timestamp: u64,
-    read_set: HashMap<u64, Arc<Version>>,
-    write_set: HashMap<u64, Version>,
-    local_writes: HashMap<u64, Arc<Version>>,
+    read_set: HashMap<RecordKey, Arc<Version>>,
+    write_set: HashMap<RecordKey, Arc<Version>>,
+    local_writes: HashMap<RecordKey, Arc<Version>>,
+    // Records inserted by this transaction, added to their tables when it commits
+    pending_records: HashMap<RecordKey, Arc<RecordHead>>,
+    // Inserted records that hold blob chunks
+    pending_chunks: HashSet<RecordKey>,
+    // Committed versions changed by `update_columns`, with their columns
+    // stored as a delta against the staged write
+    column_deltas: HashMap<RecordKey, (u64, Vec<u8>)>,
     clock: Arc<Clock>,
-    records: Arc<RwLock<HashMap<u64, Arc<RecordHead>>>>,
+    tables: Arc<TableManager>,
+    // Tables this transaction touched, so lookups skip the table catalog
+    table_cache: HashMap<u64, Arc<Table>>,
     contention_manager: Arc<ContentionManager>,
+    sequences: Arc<SequenceManager>,
+    catalog: Arc<Catalog>,
+    // Schema changes applied when the transaction commits
+    ddl: Vec<DdlOp>,
+    // First read a scan could not complete; the transaction cannot commit
+    read_error: Option<MaemioError>,
+    // Gaps seen by scans, validated against records appearing in them
+    phantoms: PhantomChecks,
+    // Map keys this transaction put under new records, by table; their
+    // index entries are added when it commits
+    pending_keys: BTreeMap<(u64, IndexKey), u64>,
+    // Map keys found absent, with the index looked in; validated at commit
+    absent_keys: Vec<(u64, Arc<TableIndex>, IndexKey)>,
+    // Queue messages enqueued or acknowledged, settled when it finishes
+    queue_ops: QueueOps,
+    // Deadlines of expiring values are checked against this, so a record
+    // does not expire halfway through the transaction
+    started_at: Instant,
     thread_id: usize,
 }
 
 impl Transaction {
     pub fn new(
         clock: Arc<Clock>, 
-        records: Arc<RwLock<HashMap<u64, Arc<RecordHead>>>>,
+        catalog: Arc<Catalog>,
         contention_manager: Arc<ContentionManager>,
         thread_id: usize,
     ) -> Self {
+        // Keeps the versions this transaction may read from being collected
+        let timestamp = clock.begin_transaction();
         Self {
-            timestamp: clock.generate_write_timestamp(),
+            timestamp,
             read_set: HashMap::new(),
             write_set: HashMap::new(),
             local_writes: HashMap::new(),
+            pending_records: HashMap::new(),
+            pending_chunks: HashSet::new(),
+            column_deltas: HashMap::new(),
             clock,
-            records,
+            tables: catalog.tables().clone(),
+            table_cache: HashMap::new(),
             contention_manager,
+            sequences: catalog.sequences().clone(),
+            catalog,
+            ddl: Vec::new(),
+            read_error: None,
+            phantoms: PhantomChecks::default(),
+            pending_keys: BTreeMap::new(),
+            absent_keys: Vec::new(),
+            queue_ops: QueueOps::default(),
+            started_at: Instant::now(),
             thread_id,
         }
     }
@@ -51,38 +139,565 @@ impl Transaction {
         self.timestamp
     }
 
+    /// Reads a record from the default table
     pub fn read(&mut self, record_id: u64) -> Result<Arc<Version>> {
-        if let Some(local_version) = self.local_writes.get(&record_id) {
+        self.read_in(DEFAULT_TABLE_ID, record_id)
+    }
+
+    /// Writes a record in the default table
+    pub fn write(&mut self, record_id: u64, data: Vec<u8>) -> Result<()> {
+        self.write_in(DEFAULT_TABLE_ID, record_id, data)
+    }
+
+    /// Deletes a record from the default table
+    pub fn delete(&mut self, record_id: u64) -> Result<()> {
+        self.delete_in(DEFAULT_TABLE_ID, record_id)
+    }
+
+    /// Reads a record from the given table
+    pub fn read_in(&mut self, table_id: u64, record_id: u64) -> Result<Arc<Version>> {
+        let version = self.read_version(table_id, record_id)?;
+        if version.is_expired_at(self.started_at) {
+            return Err(MaemioError::RecordNotFound(record_id));
+        }
+        Ok(version)
+    }
+
+    /// Reads the visible version of a record, whether or not it has expired
+    fn read_version(&mut self, table_id: u64, record_id: u64) -> Result<Arc<Version>> {
+        let key = (table_id, record_id);
+        if let Some(local_version) = self.local_writes.get(&key) {
+            if local_version.is_tombstone() {
+                return Err(MaemioError::RecordNotFound(record_id));
+            }
             return Ok(local_version.clone());
         }
-        let record = self.get_record(record_id)?;
-        let visible_version = record.find_visible_version(self.timestamp)
+        let table = self.table(table_id)?;
+        // Repeated reads share the version rebuilt by the first one
+        if let Some(version) = self.read_set.get(&key) {
+            if version.is_tombstone() {
+                return Err(MaemioError::RecordNotFound(record_id));
+            }
+            return Ok(version.clone());
+        }
+        let record = table.get_record(record_id)?;
+        let visible_version = record.find_visible_version(self.timestamp)?
             .ok_or(MaemioError::NoVisibleVersion)?;
-        self.read_set.insert(record_id, visible_version.clone());
+        self.read_set.insert(key, visible_version.clone());
+        if visible_version.is_tombstone() {
+            return Err(MaemioError::RecordNotFound(record_id));
+        }
         Ok(visible_version)
     }
 
-    pub fn write(&mut self, record_id: u64, data: Vec<u8>) -> Result<()> {
-        let record = self.get_record(record_id)?;
-        let new_version = Version::new(self.timestamp, data);
-        self.write_set.insert(record_id, new_version.clone());
-        self.local_writes.insert(record_id, Arc::new(new_version));
+    /// Writes a record in the given table
+    pub fn write_in(&mut self, table_id: u64, record_id: u64, data: Vec<u8>) -> Result<()> {
+        self.write_version(table_id, record_id, Version::new(self.timestamp, data))
+    }
+
+    /// Writes a record in the given table, copying `data` into a buffer from
+    /// this thread's arena
+    pub fn write_slice(&mut self, table_id: u64, record_id: u64, data: &[u8]) -> Result<()> {
+        let data = self.clock.arena().alloc_copy(data);
+        self.write_in(table_id, record_id, data)
+    }
+
+    /// Deletes a record from the given table by writing a tombstone
+    pub fn delete_in(&mut self, table_id: u64, record_id: u64) -> Result<()> {
+        self.stage_version(table_id, record_id, Version::tombstone(self.timestamp))
+    }
+
+    /// Inserts a record under an id drawn from the table's id allocator.
+    ///
+    /// The record joins the table when the transaction commits; until then
+    /// only this transaction sees it, and an abort leaves nothing behind.
+    pub fn insert_in(&mut self, table_id: u64, data: Vec<u8>) -> Result<u64> {
+        let record_id = self.reserve_record(table_id)?;
+        let result = self.write_version(table_id, record_id, Version::new(self.timestamp, data));
+        self.unreserve_on_error(table_id, record_id, result)
+    }
+
+    /// Inserts a row of a schema table under a fresh record id, like `insert_in`
+    pub fn insert_row(&mut self, table_id: u64, row: &Row) -> Result<u64> {
+        let data = self.table(table_id)?.require_schema()?.encode_row(row)?;
+        let record_id = self.reserve_record(table_id)?;
+        let result = self.stage_version(table_id, record_id, Version::new(self.timestamp, data));
+        self.unreserve_on_error(table_id, record_id, result)
+    }
+
+    /// Allocates a record id and a record head that only this transaction sees
+    fn reserve_record(&mut self, table_id: u64) -> Result<u64> {
+        let table = self.table(table_id)?;
+        let record_id = table.allocate_record_id()?;
+        self.pending_records.insert((table_id, record_id), table.new_record(self.timestamp));
+        Ok(record_id)
+    }
+
+    fn unreserve_on_error(&mut self, table_id: u64, record_id: u64, result: Result<()>) -> Result<u64> {
+        match result {
+            Ok(()) => Ok(record_id),
+            Err(err) => {
+                self.pending_records.remove(&(table_id, record_id));
+                Err(err)
+            }
+        }
+    }
+
+    /// Checks a new value against the table's schema and stages it
+    fn write_version(&mut self, table_id: u64, record_id: u64, version: Version) -> Result<()> {
+        let table = self.table(table_id)?;
+        if let Some(schema) = table.schema() {
+            schema.decode_row(&version.data)?;
+        }
+        self.stage_version(table_id, record_id, version)
+    }
+
+    fn stage_version(&mut self, table_id: u64, record_id: u64, mut version: Version) -> Result<()> {
+        let key = (table_id, record_id);
+        self.table(table_id)?;
+        self.get_record(key)?;
+        // The payload is recycled into this thread's arena once collected.
+        version.arena = Some(self.clock.arena().clone());
+        // The same version is read back locally and later linked into the chain.
+        let new_version = Arc::new(version);
+        self.write_set.insert(key, new_version.clone());
+        self.local_writes.insert(key, new_version);
+        self.column_deltas.remove(&key);
+        Ok(())
+    }
+
+    /// Reads a record of a schema table as a structured row
+    pub fn read_row(&mut self, table_id: u64, record_id: u64) -> Result<Row> {
+        let table = self.table(table_id)?;
+        let version = self.read_in(table_id, record_id)?;
+        table.require_schema()?.decode_row(&version.data)
+    }
+
+    /// Encodes a row with the table's schema and writes it.
+    ///
+    /// The row is validated once, before encoding, so the encoded bytes are
+    /// staged without being decoded again.
+    pub fn write_row(&mut self, table_id: u64, record_id: u64, row: &Row) -> Result<()> {
+        let data = self.table(table_id)?.require_schema()?.encode_row(row)?;
+        self.stage_version(table_id, record_id, Version::new(self.timestamp, data))
+    }
+
+    /// Reads only the named columns of a schema table's record
+    pub fn read_columns(&mut self, table_id: u64, record_id: u64, columns: &[&str]) -> Result<Vec<Value>> {
+        let table = self.table(table_id)?;
+        let version = self.read_in(table_id, record_id)?;
+        table.require_schema()?.decode_columns(&version.data, columns)
+    }
+
+    /// Changes some columns of a schema table's record, keeping the others.
+    ///
+    /// Untouched columns are copied as encoded bytes rather than decoded and
+    /// re-encoded. When the update commits, the version it changed keeps only
+    /// the old values of the updated columns.
+    pub fn update_columns(&mut self, table_id: u64, record_id: u64, updates: &[(&str, Value)]) -> Result<()> {
+        let key = (table_id, record_id);
+        let table = self.table(table_id)?;
+        let staged = self.local_writes.contains_key(&key);
+        let version = self.read_in(table_id, record_id)?;
+        let (data, delta) = table.require_schema()?.patch_row_with_delta(&version.data, updates)?;
+        self.stage_version(table_id, record_id, Version::new(self.timestamp, data))?;
+        // A delta against an earlier write of this transaction has no
+        // committed version to replace
+        if !staged {
+            self.column_deltas.insert(key, (version.wts, delta));
+        }
         Ok(())
     }
 
+    /// Draws the next value from a named sequence.
+    ///
+    /// Values are handed out outside the transaction: the sequence advances
+    /// immediately and stays advanced if the transaction aborts, so an
+    /// aborted transaction leaves a gap. Concurrent transactions never draw
+    /// the same value and never conflict over the sequence.
+    pub fn next_value(&mut self, sequence: &str) -> Result<u64> {
+        self.sequences.next_value(sequence)
+    }
+
     pub fn commit(&mut self) -> Result<()> {
-        self.validate()?;
-        // To avoid overlapping borrows on self, take out the write_set.
-        let write_set = std::mem::take(&mut self.write_set);
-        for (record_id, version) in write_set {
-            let record = self.get_record(record_id)?;
+        if let Some(err) = self.read_error.take() {
+            return Err(err);
+        }
+        if self.ddl.is_empty() {
+            return self.commit_writes(&[]);
+        }
+
+        // Schema changes are serialized with each other for the whole commit
+        let catalog = self.catalog.clone();
+        let _ddl_guard = catalog.lock_ddl();
+        let ddl = std::mem::take(&mut self.ddl);
+        self.commit_writes(&ddl)
+    }
+
+    fn commit_writes(&mut self, ddl: &[DdlOp]) -> Result<()> {
+        self.cascade_deletes()?;
+        self.validate(ddl)?;
+        self.check_constraints()?;
+        // Compute index keys before installing anything, so a failing key
+        // extractor aborts the transaction without partial effects.
+        let generations = self.index_generations()?;
+        let index_updates = self.prepare_index_updates()?;
+        // Values are compressed before any lock is taken
+        let installs = self.prepare_installs()?;
+        // Unique and foreign key indexes stay locked from their checks until
+        // the new entries are in, so two transactions cannot claim the same
+        // key and a parent cannot vanish under a new child.
+        let locked_indexes = self.commit_locks(&index_updates)?;
+        let _guards: Vec<_> = locked_indexes
+            .iter()
+            .map(|index| index.commit_lock.lock())
+            .collect();
+        // Indexes attached from here on wait for this commit before they are
+        // filled; ones attached since the keys were computed abort it.
+        let _passes = Self::enter_commits(generations)?;
+        self.check_unique(&index_updates)?;
+        self.check_foreign_keys()?;
+        let _reservations = self.reserve_quotas(&installs, &index_updates)?;
+
+        // Every check passed: staged schema changes and inserted records
+        // become visible now, together with the writes. New indexes and
+        // foreign keys cover this transaction's writes as they are created.
+        let undo = self.catalog.apply(ddl, &self.write_set)?;
+        if let Err(err) = self.publish_records() {
+            self.catalog.undo(undo);
+            return Err(err);
+        }
+        self.write_set.clear();
+        self.local_writes.clear();
+        self.column_deltas.clear();
+        let committed_at = Instant::now();
+        for Install { table, record_id, record, version, patched, raw_len, ttl } in installs {
+            let deadline = ttl.map(|ttl| committed_at + ttl);
+            let version = match deadline {
+                Some(deadline) => expiring(version, deadline),
+                None => version,
+            };
             version.commit();
-            record.install_version(version.clone())?;
+            let stored_len = version.data.len();
+            // Deleted chunks are out of every manifest written since, and
+            // acknowledged messages are never delivered again
+            let dead = version.is_tombstone()
+                && (table.is_chunk(record_id) || self.queue_ops.acks(table.id(), record_id));
+            let wts = version.wts;
+            match (patched, table.delta_threshold()) {
+                (Some((base_wts, delta)), _) => record.install_version_patched(version, base_wts, delta)?,
+                (None, Some(min_size)) => record.install_version_delta(version, min_size)?,
+                (None, None) => record.install_version(version)?,
+            }
+            if let Some(raw_len) = raw_len {
+                table.record_compression(raw_len, stored_len);
+            }
+            if let Some(deadline) = deadline {
+                table.track_expiry(record_id, deadline);
+            }
+            if dead {
+                table.track_dead(record_id, wts);
+            }
         }
+        self.apply_index_updates(index_updates)?;
+        self.queue_ops.committed();
         self.clock.reset_boost();
         Ok(())
     }
 
+    /// Adds the records this transaction inserted to their tables. Fails
+    /// with a conflict, adding none, if a record was created under one of
+    /// their ids in the meantime.
+    fn publish_records(&self) -> Result<()> {
+        let mut published: Vec<(Arc<Table>, u64)> = Vec::with_capacity(self.pending_records.len());
+        for (key, record) in &self.pending_records {
+            let table = self.get_table(key.0)?;
+            if !table.publish_record(key.1, record.clone(), self.pending_chunks.contains(key)) {
+                for (table, record_id) in published {
+                    table.remove_record(record_id);
+                }
+                return Err(MaemioError::Conflict);
+            }
+            published.push((table, key.1));
+        }
+        Ok(())
+    }
+
+    /// Resolves the record of each write and encodes it the way its table
+    /// stores values
+    fn prepare_installs(&self) -> Result<Vec<Install>> {
+        let mut installs = Vec::with_capacity(self.write_set.len());
+        for (&(table_id, record_id), version) in &self.write_set {
+            let table = self.get_table(table_id)?;
+            let record = self.get_record((table_id, record_id))?;
+            let raw_len = table.compresses(version).then_some(version.data.len());
+            let version = match table.compress_version(version) {
+                Some(compressed) => Arc::new(compressed),
+                None => version.clone(),
+            };
+            let patched = self.column_deltas.get(&(table_id, record_id)).cloned();
+            let ttl = self.write_set[&(table_id, record_id)].ttl;
+            installs.push(Install { table, record_id, record, version, patched, raw_len, ttl });
+        }
+        Ok(installs)
+    }
+
+    /// Claims the memory the new records, versions and index entries take
+    /// from their tables' quotas, failing if one would be exceeded
+    fn reserve_quotas(&self, installs: &[Install], index_updates: &[IndexUpdate]) -> Result<Vec<QuotaReservation>> {
+        let mut needed: HashMap<u64, (&Arc<Table>, usize)> = HashMap::new();
+        for install in installs {
+            let entry = needed.entry(install.table.id()).or_insert((&install.table, 0));
+            if !install.version.is_tombstone() {
+                entry.1 += VERSION_OVERHEAD + install.version.data.len();
+            }
+            if self.pending_records.contains_key(&(install.table.id(), install.record_id)) {
+                entry.1 += RECORD_OVERHEAD;
+            }
+        }
+        for update in index_updates {
+            if let (Some(key), Some(entry)) = (&update.new_key, needed.get_mut(&update.table_id)) {
+                entry.1 += key.entry_size();
+            }
+        }
+        needed
+            .into_values()
+            .map(|(table, bytes)| table.reserve_quota(bytes))
+            .collect()
+    }
+
+    /// Index generations of the tables this transaction writes, taken
+    /// before their index lists
+    fn index_generations(&self) -> Result<Vec<(Arc<Table>, u64)>> {
+        let mut generations: Vec<(Arc<Table>, u64)> = Vec::new();
+        for &(table_id, _) in self.write_set.keys() {
+            if generations.iter().all(|(table, _)| table.id() != table_id) {
+                let table = self.get_table(table_id)?;
+                let generation = table.index_generation();
+                generations.push((table, generation));
+            }
+        }
+        Ok(generations)
+    }
+
+    /// Registers the commit as in flight on the tables it writes, failing
+    /// with a conflict if one of them gained an index since `generations`
+    fn enter_commits(generations: Vec<(Arc<Table>, u64)>) -> Result<Vec<CommitPass>> {
+        generations
+            .into_iter()
+            .map(|(table, generation)| table.enter_commit(generation))
+            .collect()
+    }
+
+    /// Works out how each write changes the maintained indexes of its table
+    fn prepare_index_updates(&self) -> Result<Vec<IndexUpdate>> {
+        let mut updates = Vec::new();
+        for (&(table_id, record_id), version) in &self.write_set {
+            let table = self.get_table(table_id)?;
+            let indexes = table.indexes();
+            // Blob chunks hold raw slices of a value, not values to index
+            if indexes.is_empty()
+                || self.pending_chunks.contains(&(table_id, record_id))
+                || table.is_chunk(record_id)
+            {
+                continue;
+            }
+
+            let previous = match self.read_set.get(&(table_id, record_id)) {
+                Some(previous) => Some(previous.clone()),
+                None => self.get_record((table_id, record_id))?.find_visible_version(self.timestamp)?,
+            };
+            let previous = previous.filter(|previous| !previous.is_tombstone());
+            for index in indexes {
+                let old_key = match &previous {
+                    Some(previous) => index.key.extract(&previous.data)?,
+                    None => None,
+                };
+                let new_key = if version.is_tombstone() {
+                    None
+                } else {
+                    index.key.extract(&version.data)?
+                };
+                if old_key != new_key {
+                    updates.push(IndexUpdate { table_id, index, record_id, old_key, new_key });
+                }
+            }
+        }
+        Ok(updates)
+    }
+
+    /// Checks declared schema constraints against every row this transaction writes
+    fn check_constraints(&self) -> Result<()> {
+        for (&(table_id, _), version) in &self.write_set {
+            if version.is_tombstone() {
+                continue;
+            }
+            let table = self.get_table(table_id)?;
+            if let Some(schema) = table.schema().filter(|schema| !schema.constraints().is_empty()) {
+                schema.check_constraints(&schema.decode_row(&version.data)?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Distinct indexes whose commit locks this transaction needs, in a fixed lock order.
+    ///
+    /// These are unique indexes receiving new keys and the indexes of foreign
+    /// keys this transaction writes children of or deletes parents of.
+    fn commit_locks(&self, updates: &[IndexUpdate]) -> Result<Vec<Arc<TableIndex>>> {
+        let mut indexes: Vec<Arc<TableIndex>> = updates
+            .iter()
+            .filter(|update| update.new_key.is_some() && update.index.index.is_unique())
+            .map(|update| update.index.clone())
+            .collect();
+        for (&(table_id, _), version) in &self.write_set {
+            let table = self.get_table(table_id)?;
+            let foreign_keys = if version.is_tombstone() {
+                table.referenced_by()
+            } else {
+                table.foreign_keys()
+            };
+            indexes.extend(foreign_keys.into_iter().map(|fk| fk.index.clone()));
+        }
+        indexes.sort_by_key(|index| Arc::as_ptr(index) as usize);
+        indexes.dedup_by(|a, b| Arc::ptr_eq(a, b));
+        Ok(indexes)
+    }
+
+    /// Deletes the children of deleted parents under cascading foreign keys,
+    /// following chains of cascades through further tables.
+    fn cascade_deletes(&mut self) -> Result<()> {
+        let mut pending: Vec<RecordKey> = self.write_set
+            .iter()
+            .filter(|(_, version)| version.is_tombstone())
+            .map(|(&key, _)| key)
+            .collect();
+
+        while let Some((table_id, parent_id)) = pending.pop() {
+            for fk in self.get_table(table_id)?.referenced_by() {
+                if fk.on_delete() != OnDelete::Cascade {
+                    continue;
+                }
+                for child_id in fk.children(parent_id)? {
+                    let child_key = (fk.child_table(), child_id);
+                    // Children already deleted or moved to another parent stay as they are
+                    if let Some(version) = self.write_set.get(&child_key) {
+                        if version.is_tombstone() || fk.parent_of(&version.data)? != Some(parent_id) {
+                            continue;
+                        }
+                    }
+                    self.delete_in(child_key.0, child_key.1)?;
+                    pending.push(child_key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether this transaction leaves `child_id` pointing at `parent_id`
+    fn still_references(&self, fk: &ForeignKey, child_id: u64, parent_id: u64) -> Result<bool> {
+        match self.write_set.get(&(fk.child_table(), child_id)) {
+            Some(version) if version.is_tombstone() => Ok(false),
+            Some(version) => Ok(fk.parent_of(&version.data)? == Some(parent_id)),
+            None => Ok(true),
+        }
+    }
+
+    /// Checks that written children reference live parents and that deleted
+    /// parents keep no children. Runs with the foreign keys' locks held.
+    fn check_foreign_keys(&self) -> Result<()> {
+        for (&(table_id, record_id), version) in &self.write_set {
+            let table = self.get_table(table_id)?;
+            if version.is_tombstone() {
+                for fk in table.referenced_by() {
+                    for child_id in fk.children(record_id)? {
+                        if !self.still_references(&fk, child_id, record_id)? {
+                            continue;
+                        }
+                        return Err(match fk.on_delete() {
+                            OnDelete::Restrict => MaemioError::ConstraintViolation(format!(
+                                "Foreign key {}: record {} is still referenced by record {}",
+                                fk.name(), record_id, child_id
+                            )),
+                            // A child committed after the cascade was worked out; retry
+                            OnDelete::Cascade => MaemioError::Conflict,
+                        });
+                    }
+                }
+                continue;
+            }
+
+            for fk in table.foreign_keys() {
+                let Some(parent_id) = fk.parent_of(&version.data)? else { continue };
+                let parent_key = (fk.parent_table(), parent_id);
+                let parent_exists = match self.write_set.get(&parent_key) {
+                    Some(parent) => !parent.is_tombstone(),
+                    None => self.get_table(fk.parent_table())?.record_exists(parent_id)?,
+                };
+                if !parent_exists {
+                    return Err(MaemioError::ConstraintViolation(format!(
+                        "Foreign key {}: record {} references missing record {}",
+                        fk.name(), record_id, parent_id
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Rejects new keys of unique indexes that another record still holds
+    /// after this transaction's own changes are applied.
+    fn check_unique(&self, updates: &[IndexUpdate]) -> Result<()> {
+        let mut claimed: HashMap<(*const TableIndex, &IndexKey), u64> = HashMap::new();
+        for update in updates {
+            let Some(new_key) = &update.new_key else { continue };
+            if !update.index.index.is_unique() {
+                continue;
+            }
+
+            let index_ptr = Arc::as_ptr(&update.index);
+            if claimed.insert((index_ptr, new_key), update.record_id).is_some() {
+                return Err(MaemioError::UniqueViolation(format!(
+                    "index {} receives key {:?} twice", update.index.name, new_key
+                )));
+            }
+
+            // Look at the latest state, not this transaction's snapshot
+            for holder in update.index.index.get_all(new_key, u64::MAX)? {
+                let released = holder == update.record_id || updates.iter().any(|other| {
+                    Arc::ptr_eq(&other.index, &update.index)
+                        && other.record_id == holder
+                        && other.old_key.as_ref() == Some(new_key)
+                });
+                if !released {
+                    return Err(MaemioError::UniqueViolation(format!(
+                        "index {} already maps key {:?} to record {}",
+                        update.index.name, new_key, holder
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_index_updates(&self, updates: Vec<IndexUpdate>) -> Result<()> {
+        // Remove every old entry first so keys swapped between records never collide
+        for update in &updates {
+            if let Some(old_key) = &update.old_key {
+                // The entry may already be gone if the index was rebuilt meanwhile
+                if update.index.index.remove_entry(old_key, update.record_id, self.timestamp).is_ok() {
+                    update.index.record_removal(old_key.clone(), update.record_id, self.timestamp);
+                }
+            }
+        }
+        for update in updates {
+            if let Some(new_key) = update.new_key {
+                update.index.index.insert(new_key, update.record_id, self.timestamp)?;
+            }
+        }
+        Ok(())
+    }
+
     fn prepare_validation_info(&self) -> Result<ValidationData> {
         Ok(ValidationData {
             timestamp: self.timestamp,
@@ -98,53 +713,78 @@ impl Transaction {
         })
     }
 
-    // validate now takes &self because it only reads data.
-    fn validate(&self) -> Result<()> {
+    // validate now takes &self because it only reads data. Versions are
+    // compared by write timestamp, so stored values are not rebuilt.
+    fn validate(&self, ddl: &[DdlOp]) -> Result<()> {
         let validation_data = self.prepare_validation_data()?;
-        {
-            let records = self.records.read();
-            for (record_id, _write_version) in &validation_data.write_checks {
-                let record = records.get(record_id)
-                    .ok_or(MaemioError::RecordNotFound(*record_id))?;
-                if let Some(current_visible) = record.find_visible_version(validation_data.timestamp) {
-                    if current_visible.wts > validation_data.timestamp {
-                        return Err(MaemioError::Conflict);
-                    }
-                }
+        for (key, _write_version) in &validation_data.write_checks {
+            // Writes into a table dropped since the transaction began are
+            // lost; tables this transaction creates are not registered yet
+            let created = ddl.iter().any(|op| matches!(op, DdlOp::CreateTable(table) if table.id() == key.0));
+            if !self.tables.contains(key.0) && !created {
+                return Err(MaemioError::TableNotFound(key.0.to_string()));
             }
-            for (record_id, read_version) in &validation_data.read_checks {
-                let record = records.get(record_id)
-                    .ok_or(MaemioError::RecordNotFound(*record_id))?;
-                let current_visible = record.find_visible_version(validation_data.timestamp)
-                    .ok_or(MaemioError::ValidationFailed)?;
-                if current_visible.wts != read_version.wts {
+            let record = self.get_record(*key)?;
+            if let Some(current_wts) = record.visible_wts(validation_data.timestamp) {
+                if current_wts > validation_data.timestamp {
                     return Err(MaemioError::Conflict);
                 }
             }
         }
-        Ok(())
+        for (key, read_version) in &validation_data.read_checks {
+            let record = self.get_record(*key)?;
+            let current_wts = record.visible_wts(validation_data.timestamp)
+                .ok_or(MaemioError::ValidationFailed)?;
+            if current_wts != read_version.wts {
+                return Err(MaemioError::Conflict);
+            }
+        }
+        self.validate_phantoms()?;
+        self.validate_absent_keys()
     }    
     
-    fn get_record(&self, record_id: u64) -> Result<Arc<RecordHead>> {
-        self.records.read()
-            .get(&record_id)
-            .cloned()
-            .ok_or(MaemioError::RecordNotFound(record_id))
+    fn get_table(&self, table_id: u64) -> Result<Arc<Table>> {
+        match self.table_cache.get(&table_id) {
+            Some(table) => Ok(table.clone()),
+            None => self.tables.get_table(table_id),
+        }
     }
 
+    fn get_record(&self, key: RecordKey) -> Result<Arc<RecordHead>> {
+        if let Some(record) = self.pending_records.get(&key) {
+            return Ok(record.clone());
+        }
+        self.get_table(key.0)?.get_record(key.1)
+    }
+
+    /// Caches the table handle and returns it
+    fn table(&mut self, table_id: u64) -> Result<Arc<Table>> {
+        let table = self.get_table(table_id)?;
+        self.table_cache.insert(table_id, table.clone());
+        Ok(table)
+    }
+
+    /// Creates a record in the default table
     pub fn create_record(&mut self, record_id: u64) -> Result<()> {
-        let record = Arc::new(RecordHead::new(self.timestamp));
-        self.records.write().insert(record_id, record);
-        Ok(())
+        self.create_record_in(DEFAULT_TABLE_ID, record_id)
+    }
+
+    /// Creates a record in the given table
+    pub fn create_record_in(&mut self, table_id: u64, record_id: u64) -> Result<()> {
+        self.table(table_id)?.create_record(record_id, self.timestamp)
+    }
+
+    /// Creates a record in the given table under an id from its id strategy
+    pub fn create_record_auto_in(&mut self, table_id: u64) -> Result<u64> {
+        self.table(table_id)?.create_record_auto(self.timestamp)
     }
 
     pub fn prepare_gc_tracking(&self) -> Vec<(Arc<RecordHead>, u64)> {
-        let records = self.records.read();
         self.write_set
             .iter()
-            .filter_map(|(&id, version)| {
-                records.get(&id)
-                    .map(|record| (record.clone(), version.wts))
+            .filter_map(|(&key, version)| {
+                self.get_record(key).ok()
+                    .map(|record| (record, version.wts))
             })
             .collect()
     }
@@ -153,46 +793,124 @@ impl Transaction {
     }
 }
 
+/// Sets the deadline of a version about to be installed, copying it only if
+/// it is still shared
+fn expiring(version: Arc<Version>, deadline: Instant) -> Arc<Version> {
+    let mut version = Arc::try_unwrap(version).unwrap_or_else(|shared| (*shared).clone());
+    version.expires_at = Some(deadline);
+    Arc::new(version)
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        self.queue_ops.finish();
+        self.clock.end_transaction(self.timestamp);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::clock::ClockManager;
+    use crate::index::IndexManager;
 
-    fn setup_test_env() -> (Arc<Clock>, Arc<RwLock<HashMap<u64, Arc<RecordHead>>>>, Arc<ContentionManager>) {
+    fn setup_test_env() -> (Arc<Clock>, Arc<Catalog>, Arc<ContentionManager>) {
         let clock_manager = Arc::new(ClockManager::new(1, 100).unwrap());
         let clock = clock_manager.get_clock(0);
-        let records = Arc::new(RwLock::new(HashMap::new()));
+        let catalog = Arc::new(Catalog::new(
+            Arc::new(TableManager::new(4)),
+            Arc::new(IndexManager::new()),
+            Arc::new(SequenceManager::new()),
+        ));
         let contention_manager = Arc::new(ContentionManager::new(1, 1000, 5));
-        (clock, records, contention_manager)
+        (clock, catalog, contention_manager)
     }
 
     #[test]
     fn test_basic_transaction() {
-        let (clock, records, contention_manager) = setup_test_env();
+        let (clock, catalog, contention_manager) = setup_test_env();
+        let tables = catalog.tables().clone();
         let record = Arc::new(RecordHead::new(0));
-        records.write().insert(1, record.clone());
-        let mut tx1 = Transaction::new(clock.clone(), records.clone(), contention_manager.clone(), 0);
+        tables.default_table().records().insert(1, record.clone());
+        let mut tx1 = Transaction::new(clock.clone(), catalog.clone(), contention_manager.clone(), 0);
         tx1.write(1, vec![1, 2, 3]).unwrap();
         tx1.commit().unwrap();
-        let mut tx2 = Transaction::new(clock.clone(), records.clone(), contention_manager.clone(), 0);
+        let mut tx2 = Transaction::new(clock.clone(), catalog.clone(), contention_manager.clone(), 0);
         let version = tx2.read(1).unwrap();
         assert_eq!(version.data, vec![1, 2, 3]);
     }
 
     #[test]
     fn test_concurrent_transactions() {
-        let (clock, records, contention_manager) = setup_test_env();
+        let (clock, catalog, contention_manager) = setup_test_env();
+        let tables = catalog.tables().clone();
         let record = Arc::new(RecordHead::new(0));
-        records.write().insert(1, record.clone());
-        let mut tx1 = Transaction::new(clock.clone(), records.clone(), contention_manager.clone(), 0);
+        tables.default_table().records().insert(1, record.clone());
+        let mut tx1 = Transaction::new(clock.clone(), catalog.clone(), contention_manager.clone(), 0);
         tx1.write(1, vec![1]).unwrap();
         tx1.commit().unwrap();
-        let mut tx2 = Transaction::new(clock.clone(), records.clone(), contention_manager.clone(), 1);
+        let mut tx2 = Transaction::new(clock.clone(), catalog.clone(), contention_manager.clone(), 1);
         tx2.write(1, vec![2]).unwrap();
         let result = tx2.commit();
         assert!(result.is_ok());
-        let mut verify_tx = Transaction::new(clock, records, contention_manager, 2);
+        let mut verify_tx = Transaction::new(clock, catalog, contention_manager, 2);
         let version = verify_tx.read(1).unwrap();
         assert_eq!(version.data, vec![2]);
     }
+
+    #[test]
+    fn test_next_value() {
+        let (clock, catalog, contention_manager) = setup_test_env();
+        catalog.sequences().create_sequence("ids", 1, 1).unwrap();
+        let mut tx = Transaction::new(clock, catalog, contention_manager, 0);
+        assert_eq!(tx.next_value("ids").unwrap(), 1);
+        assert_eq!(tx.next_value("ids").unwrap(), 2);
+        assert!(tx.next_value("missing").is_err());
+    }
+
+    #[test]
+    fn test_inserts_join_table_on_commit() {
+        let (clock, catalog, contention_manager) = setup_test_env();
+        let users = catalog.tables().create_table("users").unwrap();
+
+        // An aborted insert leaves no record behind
+        let mut tx = Transaction::new(clock.clone(), catalog.clone(), contention_manager.clone(), 0);
+        let record_id = tx.insert_in(users.id(), vec![1]).unwrap();
+        assert_eq!(tx.read_in(users.id(), record_id).unwrap().data, vec![1]);
+        assert_eq!(users.record_count(), 0);
+        drop(tx);
+        assert_eq!(users.record_count(), 0);
+
+        let mut tx = Transaction::new(clock.clone(), catalog.clone(), contention_manager.clone(), 0);
+        let record_id = tx.insert_in(users.id(), vec![2]).unwrap();
+        tx.commit().unwrap();
+        assert_eq!(users.record_count(), 1);
+        let mut tx = Transaction::new(clock, catalog, contention_manager, 0);
+        assert_eq!(tx.read_in(users.id(), record_id).unwrap().data, vec![2]);
+    }
+
+    #[test]
+    fn test_tables_are_isolated() {
+        let (clock, catalog, contention_manager) = setup_test_env();
+        let tables = catalog.tables().clone();
+        let users = tables.create_table("users").unwrap();
+        let orders = tables.create_table("orders").unwrap();
+
+        let mut tx = Transaction::new(clock.clone(), catalog.clone(), contention_manager.clone(), 0);
+        tx.create_record_in(users.id(), 5).unwrap();
+        tx.create_record_in(orders.id(), 5).unwrap();
+        tx.write_in(users.id(), 5, vec![1]).unwrap();
+        tx.write_in(orders.id(), 5, vec![2]).unwrap();
+        assert!(tx.write(5, vec![3]).is_err());
+        tx.commit().unwrap();
+
+        let mut tx = Transaction::new(clock.clone(), catalog.clone(), contention_manager.clone(), 0);
+        assert_eq!(tx.read_in(users.id(), 5).unwrap().data, vec![1]);
+        assert_eq!(tx.read_in(orders.id(), 5).unwrap().data, vec![2]);
+
+        // Writes into a table dropped mid-transaction fail validation
+        tx.write_in(users.id(), 5, vec![4]).unwrap();
+        tables.drop_table(users.id()).unwrap();
+        assert!(matches!(tx.commit(), Err(MaemioError::TableNotFound(_))));
+    }
 }
@@ -0,0 +1,144 @@
+// src/transaction/queue.rs
+//
+// Queue operations inside transactions. Enqueued messages, deliveries and
+// acknowledgements take effect when the transaction commits, together with
+// its other writes.
+use super::Transaction;
+use crate::error::{MaemioError, Result};
+use crate::queue::{now_millis, MessageHeader, Queue, QueueMessage, Receipt};
+
+/// Queue messages a transaction enqueues or acknowledges
+#[derive(Default)]
+pub(crate) struct QueueOps {
+    // Messages whose ids stay marked as being enqueued until the
+    // transaction finishes, whether it commits or not
+    enqueued: Vec<(Queue, u64)>,
+    // Messages whose claims are released once the acknowledgement commits
+    acked: Vec<(Queue, u64)>,
+}
+
+impl QueueOps {
+    /// Releases the claims of acknowledged messages, which stay hidden now
+    /// that their deletion is committed
+    pub(crate) fn committed(&mut self) {
+        for (queue, message_id) in self.acked.drain(..) {
+            queue.release(message_id);
+        }
+    }
+
+    /// Whether the transaction acknowledges a message of the queue stored
+    /// in `table_id`
+    pub(crate) fn acks(&self, table_id: u64, message_id: u64) -> bool {
+        self.acked.iter().any(|(queue, acked)| queue.table_id() == table_id && *acked == message_id)
+    }
+
+    /// Clears the marks of enqueued messages; by now they are either in
+    /// their tables or gone for good
+    pub(crate) fn finish(&mut self) {
+        for (queue, message_id) in self.enqueued.drain(..) {
+            queue.finish_enqueue(message_id);
+        }
+    }
+}
+
+impl Transaction {
+    /// Appends a message to the queue and returns its id
+    pub fn enqueue(&mut self, queue: &Queue, payload: &[u8]) -> Result<u64> {
+        let header = MessageHeader { invisible_until: 0, deliveries: 0 };
+        let data = header.encode(payload);
+        let message_id = queue.start_enqueue(|| self.insert_in(queue.table_id(), data))?;
+        self.queue_ops.enqueued.push((queue.clone(), message_id));
+        Ok(message_id)
+    }
+
+    /// Takes the oldest visible message, hiding it from other consumers for
+    /// the queue's visibility timeout. Returns `None` if no message is
+    /// available.
+    ///
+    /// Messages enqueued by this transaction are not handed out before it
+    /// commits.
+    pub fn dequeue(&mut self, queue: &Queue) -> Result<Option<QueueMessage>> {
+        let table_id = queue.table_id();
+        let table = self.table(table_id)?;
+        let now = now_millis();
+        let until = now + queue.visibility_timeout().as_millis() as u64;
+
+        let mut acknowledged_prefix = true;
+        let mut expected = queue.head();
+        for (message_id, record) in table.records().range_iter(queue.head()..) {
+            // Ids skipped since the last message belong to messages not in
+            // the table yet, or to enqueues that aborted
+            if acknowledged_prefix && message_id > expected {
+                acknowledged_prefix = !queue.enqueuing_in(expected..message_id)
+                    && table.records().range_iter(expected..message_id).next().is_none();
+            }
+            expected = message_id + 1;
+
+            // Availability is judged on the latest committed state without
+            // touching the read set, so skipped messages never conflict.
+            let Some(latest) = record.find_visible_version(u64::MAX)? else {
+                acknowledged_prefix = false;
+                continue;
+            };
+            if latest.is_tombstone() {
+                if acknowledged_prefix {
+                    queue.advance_head(message_id + 1);
+                }
+                continue;
+            }
+            // Acknowledged messages may be gone from the table already
+            if acknowledged_prefix {
+                queue.advance_head(message_id);
+            }
+            acknowledged_prefix = false;
+
+            if self.local_writes.contains_key(&(table_id, message_id)) {
+                continue;
+            }
+            let (header, _) = MessageHeader::decode(message_id, &latest.data)?;
+            if header.invisible_until > now || !queue.claim(message_id, now, until) {
+                continue;
+            }
+
+            let version = match self.read_in(table_id, message_id) {
+                Ok(version) if version.wts == latest.wts => version,
+                // The snapshot predates the latest delivery or acknowledgement
+                Ok(_) | Err(MaemioError::RecordNotFound(_)) | Err(MaemioError::NoVisibleVersion) => {
+                    queue.release(message_id);
+                    return Err(MaemioError::Conflict);
+                }
+                Err(err) => {
+                    queue.release(message_id);
+                    return Err(err);
+                }
+            };
+            let (header, payload) = MessageHeader::decode(message_id, &version.data)?;
+            let delivery = header.deliveries + 1;
+            let data = MessageHeader { invisible_until: until, deliveries: delivery }.encode(payload);
+            let payload = payload.to_vec();
+            self.write_in(table_id, message_id, data)?;
+            return Ok(Some(QueueMessage {
+                receipt: Receipt { message_id, delivery },
+                payload,
+            }));
+        }
+        Ok(None)
+    }
+
+    /// Acknowledges a delivered message, removing it from the queue. Fails
+    /// if the message was delivered again since the receipt was issued.
+    pub fn ack(&mut self, queue: &Queue, receipt: &Receipt) -> Result<()> {
+        let version = self.read_in(queue.table_id(), receipt.message_id)?;
+        let (header, _) = MessageHeader::decode(receipt.message_id, &version.data)?;
+        if header.deliveries != receipt.delivery {
+            return Err(MaemioError::ConstraintViolation(format!(
+                "Receipt for delivery {} of message {} is stale", receipt.delivery, receipt.message_id
+            )));
+        }
+        self.delete_in(queue.table_id(), receipt.message_id)?;
+        // The claim keeps other consumers away until the deletion commits;
+        // if it never does, the message is delivered again once it lapses.
+        self.queue_ops.acked.push((queue.clone(), receipt.message_id));
+        Ok(())
+    }
+}
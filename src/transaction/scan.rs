@@ -0,0 +1,422 @@
+// src/transaction/scan.rs
+//
+// Snapshot scans over every record of a table. A scan walks the table's
+// records in id order, one at a time, together with the records the
+// transaction inserted itself, and yields the version of each record visible
+// at the transaction's timestamp, with the transaction's own writes taking
+// precedence. Every version a scan looks at joins the read set and is
+// validated at commit like a point read. Records created by transactions
+// newer than the scanning one are not seen. So that they cannot slip in
+// unnoticed either, the
+// records a scan found without a visible version, and the id range of every
+// scan that ran to its end, are checked again at commit: a record that has
+// become visible there since is a conflict. A version that cannot be read
+// ends the scan early, and the transaction then fails to commit with the
+// read error.
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+use std::time::Instant;
+use super::{RecordKey, Transaction};
+use crate::data::{RecordHead, Version};
+use crate::error::{MaemioError, Result};
+use crate::table::Table;
+
+/// Filter applied to each visible record before it is yielded
+type Predicate<'a> = Box<dyn Fn(u64, &Version) -> bool + 'a>;
+
+/// Id range of a table, as bounds
+type IdRange = (Bound<u64>, Bound<u64>);
+
+/// What a transaction's scans found missing; records that become visible
+/// there before the transaction commits are phantoms
+#[derive(Default)]
+pub(crate) struct PhantomChecks {
+    // Records scanned without a visible version
+    absent: HashSet<RecordKey>,
+    // Id ranges scans walked completely, by table
+    ranges: Vec<(u64, IdRange)>,
+}
+
+/// Iterator over the records of a table visible to a transaction; created by
+/// `Transaction::scan` and its variants. Range scans yield records in id
+/// order and can be walked from either end.
+pub struct Scan<'a> {
+    table_id: u64,
+    timestamp: u64,
+    // Deadlines are checked against the transaction's start
+    started_at: Instant,
+    // Records are looked up in the table one at a time, between the ids
+    // already taken from either end
+    table: Arc<Table>,
+    front: Bound<u64>,
+    back: Bound<u64>,
+    // Records the transaction inserted within the bounds, in id order; they
+    // only join the table when it commits
+    pending: VecDeque<(u64, Arc<RecordHead>)>,
+    // Set once a read failed
+    stopped: bool,
+    local_writes: &'a HashMap<RecordKey, Arc<Version>>,
+    // Where versions read from the table are recorded: straight into the
+    // transaction's read set, or buffered while partitions run in parallel
+    read_set: Option<&'a mut HashMap<RecordKey, Arc<Version>>>,
+    reads: Vec<(RecordKey, Arc<Version>)>,
+    // Where a failed read is reported, following the read set
+    read_error: Option<&'a mut Option<MaemioError>>,
+    error: Option<MaemioError>,
+    // Where records without a visible version and the covered range are
+    // registered, following the read set
+    phantoms: Option<&'a mut PhantomChecks>,
+    absent: Vec<RecordKey>,
+    // Ids the record list was taken from, covered once the scan runs out
+    bounds: IdRange,
+    exhausted: bool,
+    predicate: Option<Predicate<'a>>,
+}
+
+impl Scan<'_> {
+    /// Number of records left to look at, including ones that turn out to be
+    /// invisible or filtered out. Counting walks the rest of the range.
+    pub fn remaining(&self) -> usize {
+        if self.stopped {
+            return 0;
+        }
+        self.table.records().range_iter((self.front, self.back)).count() + self.pending.len()
+    }
+
+    /// Takes the next record from the front, or from the back, of what is
+    /// left, merging the table's records with the transaction's inserts
+    fn take(&mut self, from_back: bool) -> Option<(u64, Arc<RecordHead>)> {
+        if self.stopped {
+            return None;
+        }
+        let mut records = self.table.records().range_iter((self.front, self.back));
+        let stored = if from_back { records.next_back() } else { records.next() };
+        let pending = if from_back { self.pending.back() } else { self.pending.front() };
+        let take_pending = match (&stored, pending) {
+            (Some((stored_id, _)), Some((pending_id, _))) => (pending_id < stored_id) != from_back,
+            (None, Some(_)) => true,
+            (_, None) => false,
+        };
+        let (record_id, record) = if !take_pending {
+            stored?
+        } else if from_back {
+            self.pending.pop_back()?
+        } else {
+            self.pending.pop_front()?
+        };
+        if from_back {
+            self.back = Bound::Excluded(record_id);
+        } else {
+            self.front = Bound::Excluded(record_id);
+        }
+        Some((record_id, record))
+    }
+
+    /// The version this scan yields for a record, if any
+    fn visible(&mut self, record_id: u64, record: &RecordHead) -> Option<Arc<Version>> {
+        let key = (self.table_id, record_id);
+        let version = match self.local_writes.get(&key) {
+            Some(local_version) => local_version.clone(),
+            None => {
+                let version = match record.find_visible_version(self.timestamp) {
+                    Ok(Some(version)) => version,
+                    Ok(None) => {
+                        match self.phantoms.as_mut() {
+                            Some(phantoms) => {
+                                phantoms.absent.insert(key);
+                            }
+                            None => self.absent.push(key),
+                        }
+                        return None;
+                    }
+                    Err(err) => {
+                        self.fail(err);
+                        return None;
+                    }
+                };
+                match self.read_set.as_mut() {
+                    Some(read_set) => {
+                        read_set.insert(key, version.clone());
+                    }
+                    None => self.reads.push((key, version.clone())),
+                }
+                version
+            }
+        };
+        if version.is_tombstone() || version.is_expired_at(self.started_at) {
+            return None;
+        }
+        Some(version)
+    }
+
+    /// Ends the scan, keeping the first error for the transaction's commit
+    fn fail(&mut self, err: MaemioError) {
+        self.stopped = true;
+        match self.read_error.as_mut() {
+            Some(read_error) => {
+                read_error.get_or_insert(err);
+            }
+            None => {
+                self.error.get_or_insert(err);
+            }
+        }
+    }
+
+    /// Registers the scanned range once every record in it was looked at
+    fn finish(&mut self) {
+        if self.exhausted {
+            return;
+        }
+        self.exhausted = true;
+        if let Some(phantoms) = self.phantoms.as_mut() {
+            phantoms.ranges.push((self.table_id, self.bounds));
+        }
+    }
+
+    /// The visible version of a record if it also passes the predicate
+    fn yielded(&mut self, record_id: u64, record: &RecordHead) -> Option<Arc<Version>> {
+        let version = self.visible(record_id, record)?;
+        match &self.predicate {
+            Some(predicate) if !predicate(record_id, &version) => None,
+            _ => Some(version),
+        }
+    }
+}
+
+impl Iterator for Scan<'_> {
+    type Item = (u64, Arc<Version>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((record_id, record)) = self.take(false) {
+            if let Some(version) = self.yielded(record_id, &record) {
+                return Some((record_id, version));
+            }
+        }
+        self.finish();
+        None
+    }
+}
+
+impl DoubleEndedIterator for Scan<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while let Some((record_id, record)) = self.take(true) {
+            if let Some(version) = self.yielded(record_id, &record) {
+                return Some((record_id, version));
+            }
+        }
+        self.finish();
+        None
+    }
+}
+
+impl Transaction {
+    /// Iterates over every record of a table visible to this transaction, in
+    /// no particular order
+    pub fn scan(&mut self, table_id: u64) -> Result<Scan<'_>> {
+        self.scan_records(table_id, (Bound::Unbounded, Bound::Unbounded), None)
+    }
+
+    /// Iterates over the visible records of a table with ids in `range`, in
+    /// id order; `..` walks the whole table in order. A range without ids,
+    /// such as `2000..1000`, yields nothing.
+    pub fn scan_range<R>(&mut self, table_id: u64, range: R) -> Result<Scan<'_>>
+    where
+        R: RangeBounds<u64>,
+    {
+        let bounds = (range.start_bound().cloned(), range.end_bound().cloned());
+        self.scan_records(table_id, bounds, None)
+    }
+
+    /// Iterates over the visible records of a table that satisfy `predicate`
+    pub fn scan_where<'a, P>(&'a mut self, table_id: u64, predicate: P) -> Result<Scan<'a>>
+    where
+        P: Fn(u64, &Version) -> bool + 'a,
+    {
+        let bounds = (Bound::Unbounded, Bound::Unbounded);
+        self.scan_records(table_id, bounds, Some(Box::new(predicate)))
+    }
+
+    /// Scans a table on up to `partitions` threads at once. Each thread runs
+    /// `f` over a scan of its share of the records; the results come back
+    /// in partition order.
+    ///
+    /// All partitions read the same snapshot, and their reads join the read
+    /// set once every partition has finished.
+    pub fn par_scan<F, R>(&mut self, table_id: u64, partitions: usize, f: F) -> Result<Vec<R>>
+    where
+        F: Fn(&mut Scan<'_>) -> R + Sync,
+        R: Send,
+    {
+        self.par_scan_where(table_id, partitions, |_, _| true, f)
+    }
+
+    /// Scans a table on up to `partitions` threads at once like `par_scan`,
+    /// yielding only the visible records that satisfy `predicate`
+    pub fn par_scan_where<P, F, R>(&mut self, table_id: u64, partitions: usize, predicate: P, f: F) -> Result<Vec<R>>
+    where
+        P: Fn(u64, &Version) -> bool + Sync,
+        F: Fn(&mut Scan<'_>) -> R + Sync,
+        R: Send,
+    {
+        let table = self.table(table_id)?;
+        let unbounded = (Bound::Unbounded, Bound::Unbounded);
+        let pending = self.pending_in(table_id, unbounded);
+        // Partitions split the ids between the lowest and the highest record
+        // evenly, so finding them takes no walk over the table
+        let mut records = table.records().range_iter(..);
+        let lowest = records.next().map(|(id, _)| id).into_iter().chain(pending.first().map(|(id, _)| *id)).min();
+        let highest = records.next_back().map(|(id, _)| id).into_iter().chain(pending.last().map(|(id, _)| *id)).max();
+        drop(records);
+        let bounds = match lowest.zip(highest) {
+            Some((lowest, highest)) => partition_bounds(lowest, highest.max(lowest), partitions),
+            None => Vec::new(),
+        };
+
+        let timestamp = self.timestamp;
+        let started_at = self.started_at;
+        let local_writes = &self.local_writes;
+        let predicate = &predicate;
+        let f = &f;
+
+        let finished: Vec<_> = std::thread::scope(|scope| {
+            let handles: Vec<_> = bounds
+                .into_iter()
+                .map(|bounds| {
+                    let table = table.clone();
+                    let pending: VecDeque<_> = pending.iter().filter(|(id, _)| bounds.contains(id)).cloned().collect();
+                    scope.spawn(move || {
+                        let mut scan = Scan {
+                            table_id,
+                            timestamp,
+                            started_at,
+                            table,
+                            front: bounds.0,
+                            back: bounds.1,
+                            pending,
+                            stopped: false,
+                            local_writes,
+                            read_set: None,
+                            reads: Vec::new(),
+                            read_error: None,
+                            error: None,
+                            phantoms: None,
+                            absent: Vec::new(),
+                            bounds,
+                            exhausted: false,
+                            predicate: Some(Box::new(move |record_id, version: &Version| predicate(record_id, version))),
+                        };
+                        let result = f(&mut scan);
+                        (result, scan.reads, scan.error, scan.absent, scan.exhausted)
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+                .collect()
+        });
+
+        // The table is covered only if every partition ran to its end
+        let mut covered = true;
+        let mut results = Vec::with_capacity(finished.len());
+        for (result, reads, error, absent, exhausted) in finished {
+            self.read_set.extend(reads);
+            if let Some(err) = error {
+                self.read_error.get_or_insert(err);
+            }
+            self.phantoms.absent.extend(absent);
+            covered &= exhausted;
+            results.push(result);
+        }
+        if covered {
+            self.phantoms.ranges.push((table_id, unbounded));
+        }
+        Ok(results)
+    }
+
+    fn scan_records<'a>(
+        &'a mut self,
+        table_id: u64,
+        bounds: IdRange,
+        predicate: Option<Predicate<'a>>,
+    ) -> Result<Scan<'a>> {
+        let table = self.table(table_id)?;
+        let pending = self.pending_in(table_id, bounds).into();
+        Ok(Scan {
+            table_id,
+            timestamp: self.timestamp,
+            started_at: self.started_at,
+            table,
+            front: bounds.0,
+            back: bounds.1,
+            pending,
+            stopped: false,
+            local_writes: &self.local_writes,
+            read_set: Some(&mut self.read_set),
+            reads: Vec::new(),
+            read_error: Some(&mut self.read_error),
+            error: None,
+            phantoms: Some(&mut self.phantoms),
+            absent: Vec::new(),
+            bounds,
+            exhausted: false,
+            predicate,
+        })
+    }
+
+    /// Records this transaction inserted into a table with ids in `bounds`,
+    /// in id order, leaving out blob chunks
+    fn pending_in(&self, table_id: u64, bounds: IdRange) -> Vec<(u64, Arc<RecordHead>)> {
+        let mut pending: Vec<_> = self.pending_records
+            .iter()
+            .filter(|(key, _)| key.0 == table_id && bounds.contains(&key.1) && !self.pending_chunks.contains(key))
+            .map(|(&(_, record_id), record)| (record_id, record.clone()))
+            .collect();
+        pending.sort_unstable_by_key(|(record_id, _)| *record_id);
+        pending
+    }
+
+    /// Fails with a conflict if a record this transaction's scans found
+    /// missing, or one in a range they covered, has become visible since
+    pub(crate) fn validate_phantoms(&self) -> Result<()> {
+        let unread = |key: &RecordKey| !self.read_set.contains_key(key) && !self.write_set.contains_key(key);
+        for key in self.phantoms.absent.iter().filter(|key| unread(key)) {
+            if self.appeared(&*self.get_record(*key)?)? {
+                return Err(MaemioError::Conflict);
+            }
+        }
+        for &(table_id, bounds) in &self.phantoms.ranges {
+            let table = self.get_table(table_id)?;
+            for (record_id, record) in table.records().range_iter(bounds) {
+                if unread(&(table_id, record_id)) && self.appeared(&record)? {
+                    return Err(MaemioError::Conflict);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a record has a live version visible to this transaction
+    pub(super) fn appeared(&self, record: &RecordHead) -> Result<bool> {
+        Ok(record
+            .find_visible_version(self.timestamp)?
+            .is_some_and(|version| !version.is_tombstone()))
+    }
+}
+
+/// Splits the ids from `lowest` to `highest` into up to `partitions` ranges
+/// of about equal width; the outer ones are open-ended
+fn partition_bounds(lowest: u64, highest: u64, partitions: usize) -> Vec<IdRange> {
+    let span = u128::from(highest - lowest) + 1;
+    let count = (partitions.max(1) as u128).min(span);
+    let width = span / count;
+    let at = |i: u128| (u128::from(lowest) + i * width) as u64;
+    (0..count)
+        .map(|i| {
+            let start = if i == 0 { Bound::Unbounded } else { Bound::Included(at(i)) };
+            let end = if i + 1 == count { Bound::Unbounded } else { Bound::Excluded(at(i + 1)) };
+            (start, end)
+        })
+        .collect()
+}
@@ -0,0 +1,46 @@
+// src/transaction/ttl.rs
+//
+// Values that expire. A write with a time to live gets its deadline when it
+// commits; once the deadline passes, transactions started afterwards treat
+// the record as deleted. Tables keep the deadlines of committed expiring
+// writes, and the garbage collector replaces records that are still expired
+// with tombstones, so the expired values are reclaimed like any superseded
+// version.
+use std::time::Duration;
+use super::Transaction;
+use crate::data::Version;
+use crate::error::{MaemioError, Result};
+use crate::table::DEFAULT_TABLE_ID;
+
+impl Transaction {
+    /// Writes a record in the default table that expires `ttl` after the transaction commits
+    pub fn write_with_ttl(&mut self, record_id: u64, data: Vec<u8>, ttl: Duration) -> Result<()> {
+        self.write_in_with_ttl(DEFAULT_TABLE_ID, record_id, data, ttl)
+    }
+
+    /// Writes a record in the given table that expires `ttl` after the
+    /// transaction commits.
+    ///
+    /// A later write without a time to live makes the record permanent again.
+    pub fn write_in_with_ttl(&mut self, table_id: u64, record_id: u64, data: Vec<u8>, ttl: Duration) -> Result<()> {
+        let mut version = Version::new(self.timestamp, data);
+        version.ttl = Some(ttl);
+        self.write_version(table_id, record_id, version)
+    }
+
+    /// Deletes a record if the value this transaction sees has expired.
+    /// Returns whether the record was deleted.
+    ///
+    /// The read is validated at commit, so a record renewed concurrently is
+    /// not deleted.
+    pub(crate) fn delete_if_expired(&mut self, table_id: u64, record_id: u64) -> Result<bool> {
+        match self.read_version(table_id, record_id) {
+            Ok(version) if version.is_expired_at(self.started_at) => {
+                self.delete_in(table_id, record_id)?;
+                Ok(true)
+            }
+            Ok(_) | Err(MaemioError::RecordNotFound(_)) | Err(MaemioError::NoVisibleVersion) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+}
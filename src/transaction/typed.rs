@@ -0,0 +1,36 @@
+// src/transaction/typed.rs
+//
+// Typed reads and writes of record values through a serde codec. Values are
+// encoded when written and decoded when read, on top of the byte-level
+// `read_in` and `write_in`.
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use super::Transaction;
+use crate::codec::{Binary, Codec};
+use crate::error::Result;
+use crate::table::DEFAULT_TABLE_ID;
+
+impl Transaction {
+    /// Writes a value to a record of the default table with the binary codec
+    pub fn put_typed<T: Serialize + ?Sized>(&mut self, record_id: u64, value: &T) -> Result<()> {
+        self.put_encoded::<Binary, T>(DEFAULT_TABLE_ID, record_id, value)
+    }
+
+    /// Reads a value written by `put_typed` from the default table
+    pub fn get_typed<T: DeserializeOwned>(&mut self, record_id: u64) -> Result<T> {
+        self.get_encoded::<Binary, T>(DEFAULT_TABLE_ID, record_id)
+    }
+
+    /// Writes a value to a record of the given table with codec `C`
+    pub fn put_encoded<C: Codec, T: Serialize + ?Sized>(&mut self, table_id: u64, record_id: u64, value: &T) -> Result<()> {
+        let data = C::encode(value)?;
+        self.write_in(table_id, record_id, data)
+    }
+
+    /// Reads a value of the given table with codec `C`. Fails with
+    /// `MaemioError::Decode` if the record does not hold a `T`.
+    pub fn get_encoded<C: Codec, T: DeserializeOwned>(&mut self, table_id: u64, record_id: u64) -> Result<T> {
+        let version = self.read_in(table_id, record_id)?;
+        C::decode(&version.data)
+    }
+}